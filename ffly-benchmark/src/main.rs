@@ -0,0 +1,348 @@
+use std::{
+    process,
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use fastrand::Rng;
+use ffly_rs::{FireflyStream, GenericError};
+use futures::future::join_all;
+use tokio::task::JoinSet;
+
+use crate::{
+    report::{Outcome, Recorder, Report, Settings},
+    workload::{key, value, Mix, Operation, Request, TtlDistribution, Workload},
+};
+
+mod report;
+mod workload;
+
+#[cfg(test)]
+mod test_report;
+
+#[cfg(test)]
+mod test_workload;
+
+/// Benchmark a Firefly server with a configurable workload.
+#[derive(Parser, Debug, Clone)]
+#[clap(author, version, about, long_about = None, disable_help_flag = true)]
+struct Args {
+    /// Print help.
+    #[clap(long, action = clap::ArgAction::Help)]
+    help: Option<bool>,
+
+    /// The host of the server.
+    #[clap(short, long, default_value = "127.0.0.1", env = "FFLY_HOST")]
+    host: String,
+
+    /// The port of the server.
+    #[clap(short, long, default_value_t = 46600, env = "FFLY_PORT")]
+    port: u16,
+
+    /// The user to authenticate as.
+    #[clap(short, long, env = "FFLY_USER")]
+    user: Option<String>,
+
+    /// The password of the user.
+    #[clap(long, env = "FFLY_PASSWORD")]
+    password: Option<String>,
+
+    /// The total amount of requests to send.
+    #[clap(short = 'n', long, default_value_t = 1_000_000)]
+    requests: u64,
+
+    /// The amount of connections to the server.
+    #[clap(short, long, default_value_t = 10)]
+    connections: usize,
+
+    /// The amount of workers sending requests, spread over the connections.
+    #[clap(long, default_value_t = 10)]
+    concurrency: usize,
+
+    /// The amount of requests every worker has in flight at once. The client
+    /// sends one request at a time over a connection, so the measured latency
+    /// includes the time a request waits in the client. (this isn't
+    /// pipelining)
+    #[clap(long, default_value_t = 1)]
+    in_flight: usize,
+
+    /// The amount of distinct keys the requests are spread over.
+    #[clap(long, default_value_t = 100_000)]
+    keys: u64,
+
+    /// The size of the keys in bytes.
+    #[clap(long, default_value_t = 64)]
+    key_size: usize,
+
+    /// The size of the values in bytes.
+    #[clap(long, default_value_t = 36)]
+    value_size: usize,
+
+    /// How often reads, writes and drops get sent, relative to each other.
+    /// (READ:WRITE:DROP)
+    #[clap(long, default_value = "0:1:0")]
+    mix: Mix,
+
+    /// The TTLs of the written records, in seconds.
+    /// (never, SECONDS, MIN-MAX or PERCENTAGE%:MIN-MAX)
+    #[clap(long, default_value = "never")]
+    ttl: TtlDistribution,
+
+    /// Write every key once before the benchmark starts, so reads hit.
+    #[clap(long)]
+    prefill: bool,
+
+    /// The seed of the workload, to repeat the same requests.
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// Print the report as JSON.
+    #[clap(long)]
+    json: bool,
+}
+
+/// The current time, in seconds since the UNIX epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Open the connections to the server.
+///
+/// # Arguments
+///
+/// * `args` - Where to connect to, and how many connections.
+async fn connect(args: &Args) -> Result<Vec<Arc<FireflyStream>>> {
+    let address = format!("{}:{}", args.host, args.port);
+    let mut connections = Vec::new();
+
+    for _ in 0..args.connections.max(1) {
+        let firefly = FireflyStream::connect(&address)
+            .await
+            .map_err(|e| anyhow!("Could not connect to {}: {}", address, e))?;
+
+        if let (Some(user), Some(password)) = (&args.user, &args.password) {
+            firefly
+                .auth(user, password)
+                .await
+                .map_err(|e| anyhow!("Could not authenticate: {}", e))?;
+        }
+
+        connections.push(Arc::new(firefly));
+    }
+
+    Ok(connections)
+}
+
+/// Write every key once, spread over all connections.
+///
+/// # Arguments
+///
+/// * `connections` - The connections to the server.
+/// * `args` - The key space and value size.
+async fn prefill(connections: &[Arc<FireflyStream>], args: &Args) -> Result<()> {
+    let mut filling = JoinSet::new();
+
+    for (index, firefly) in connections.iter().enumerate() {
+        let (firefly, args) = (firefly.clone(), args.clone());
+        filling.spawn(async move {
+            let mut rng = Rng::with_seed(index as u64);
+            let value = value(&mut rng, args.value_size);
+
+            for key_index in (index as u64..args.keys).step_by(args.connections.max(1)) {
+                firefly
+                    .new(&key(key_index, args.key_size), &value)
+                    .await
+                    .map_err(|e| anyhow!("Could not prefill the keys: {}", e))?;
+            }
+            Ok::<_, anyhow::Error>(())
+        });
+    }
+
+    while let Some(result) = filling.join_next().await {
+        result??;
+    }
+
+    Ok(())
+}
+
+/// Classify the result of a request.
+///
+/// # Arguments
+///
+/// * `result` - The result of the request, if the key was found.
+fn outcome(result: &Result<bool, GenericError>) -> Outcome {
+    match result {
+        Ok(true) => Outcome::Ok,
+        Ok(false) => Outcome::Miss,
+        Err(_) => Outcome::Error,
+    }
+}
+
+/// Send a request and time it.
+///
+/// # Arguments
+///
+/// * `firefly` - The connection to send the request over.
+/// * `request` - The request to send.
+/// * `value` - The value for writes.
+async fn send(
+    firefly: &FireflyStream,
+    request: &Request,
+    value: &str,
+) -> (Outcome, std::time::Duration, Option<String>) {
+    let start = Instant::now();
+    let result = match request.operation {
+        Operation::Read => firefly
+            .try_get_value(&request.key)
+            .await
+            .map(|value| value.is_some()),
+        Operation::Write => firefly
+            .new_with_ttl(&request.key, value, request.ttl as usize)
+            .await
+            .map(|_| true),
+        Operation::Drop => firefly.drop(&request.key).await.map(|_| true),
+    };
+    let latency = start.elapsed();
+
+    let outcome = outcome(&result);
+    let error = match outcome {
+        Outcome::Error => result.err().map(|e| e.to_string()),
+        _ => None,
+    };
+    (outcome, latency, error)
+}
+
+/// Send requests in batches of the in-flight amount, and record them. The
+/// requests of a batch wait for each other in the client.
+///
+/// # Arguments
+///
+/// * `firefly` - The connection of the worker.
+/// * `workload` - What to send.
+/// * `requests` - The amount of requests the worker sends.
+/// * `in_flight` - The amount of requests in a batch.
+/// * `rng` - The random number generator of the worker.
+/// * `value_size` - The size of the values in bytes.
+async fn work(
+    firefly: Arc<FireflyStream>,
+    workload: Workload,
+    requests: u64,
+    in_flight: usize,
+    mut rng: Rng,
+    value_size: usize,
+) -> Recorder {
+    let mut recorder = Recorder::default();
+    let value = value(&mut rng, value_size);
+    let mut remaining = requests;
+
+    while remaining > 0 {
+        let now = now();
+        let batch: Vec<Request> = (0..remaining.min(in_flight.max(1) as u64))
+            .map(|_| workload.next(&mut rng, now))
+            .collect();
+        remaining -= batch.len() as u64;
+
+        let sent = join_all(batch.iter().map(|request| send(&firefly, request, &value))).await;
+        for (request, (outcome, latency, error)) in batch.iter().zip(sent) {
+            recorder.record(request.operation, latency, outcome);
+            if recorder.first_error.is_none() {
+                recorder.first_error = error;
+            }
+        }
+    }
+
+    recorder
+}
+
+/// Run the benchmark.
+///
+/// # Arguments
+///
+/// * `args` - The server and the workload.
+async fn run(args: &Args) -> Result<Report> {
+    let connections = connect(args).await?;
+
+    if args.prefill {
+        eprintln!("Writing {} keys...", args.keys);
+        prefill(&connections, args).await?;
+    }
+
+    let workload = Workload {
+        mix: args.mix,
+        ttl: args.ttl,
+        keys: args.keys,
+        key_size: args.key_size,
+    };
+    let workers = args.concurrency.max(1) as u64;
+    let seed = args.seed.unwrap_or_else(|| fastrand::u64(..));
+
+    eprintln!(
+        "Sending {} requests with {} workers over {} connections...",
+        args.requests,
+        workers,
+        connections.len()
+    );
+
+    let start = Instant::now();
+    let mut working = JoinSet::new();
+    for worker in 0..workers {
+        // The first workers take the remainder.
+        let requests = args.requests / workers + u64::from(worker < args.requests % workers);
+        working.spawn(work(
+            connections[worker as usize % connections.len()].clone(),
+            workload.clone(),
+            requests,
+            args.in_flight,
+            Rng::with_seed(seed.wrapping_add(worker)),
+            args.value_size,
+        ));
+    }
+
+    let mut recorder = Recorder::default();
+    while let Some(worker) = working.join_next().await {
+        recorder.merge(&worker?);
+    }
+    let elapsed = start.elapsed();
+
+    if let Some(error) = &recorder.first_error {
+        eprintln!("First error: {}", error);
+    }
+
+    let settings = Settings {
+        connections: connections.len(),
+        concurrency: workers as usize,
+        in_flight: args.in_flight.max(1),
+        keys: args.keys,
+        key_size: args.key_size,
+        value_size: args.value_size,
+        mix: args.mix.to_string(),
+        ttl: args.ttl.to_string(),
+    };
+    Ok(Report::new(settings, &recorder, elapsed))
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+
+    let report = match run(&args).await {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{:#}", e);
+            process::exit(1);
+        }
+    };
+
+    match args.json {
+        true => println!(
+            "{}",
+            serde_json::to_string_pretty(&report).expect("the report can be serialized")
+        ),
+        false => print!("{}", report),
+    }
+}
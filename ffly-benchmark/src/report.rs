@@ -0,0 +1,276 @@
+use std::{fmt, time::Duration};
+
+use hdrhistogram::Histogram;
+use serde::Serialize;
+
+use crate::workload::Operation;
+
+/// The percentiles of the latency distribution in the report.
+const PERCENTILES: [f64; 7] = [50.0, 75.0, 90.0, 99.0, 99.9, 99.99, 100.0];
+
+/// How a request ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Ok,
+    /// The record did not exist.
+    Miss,
+    Error,
+}
+
+/// Records the latencies (in microseconds) and outcomes of requests. Every
+/// worker has its own, which get merged once the benchmark is done.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    histograms: [Histogram<u64>; 3],
+    misses: [u64; 3],
+    errors: u64,
+    /// The first error, to show what went wrong.
+    pub first_error: Option<String>,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        let histogram = || Histogram::new(3).expect("3 significant figures are supported");
+
+        Self {
+            histograms: [histogram(), histogram(), histogram()],
+            misses: [0; 3],
+            errors: 0,
+            first_error: None,
+        }
+    }
+}
+
+impl Recorder {
+    /// Record a request. Failed requests don't count towards the latencies.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The operation of the request.
+    /// * `latency` - How long it took to get the response.
+    /// * `outcome` - How the request ended.
+    pub fn record(&mut self, operation: Operation, latency: Duration, outcome: Outcome) {
+        let index = operation.index();
+
+        match outcome {
+            Outcome::Error => {
+                self.errors += 1;
+                return;
+            }
+            Outcome::Miss => self.misses[index] += 1,
+            Outcome::Ok => {}
+        }
+
+        self.histograms[index].saturating_record(latency.as_micros().max(1) as u64);
+    }
+
+    /// Add the requests of another recorder.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The recorder to add.
+    pub fn merge(&mut self, other: &Recorder) {
+        for index in 0..self.histograms.len() {
+            self.histograms[index]
+                .add(&other.histograms[index])
+                .expect("the histograms have the same bounds");
+            self.misses[index] += other.misses[index];
+        }
+
+        self.errors += other.errors;
+        if self.first_error.is_none() {
+            self.first_error.clone_from(&other.first_error);
+        }
+    }
+}
+
+/// The latency distribution of requests, in microseconds.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Latency {
+    pub mean_us: f64,
+    pub p50_us: u64,
+    pub p99_us: u64,
+    pub p999_us: u64,
+    pub max_us: u64,
+}
+
+impl Latency {
+    /// Summarize a histogram.
+    ///
+    /// # Arguments
+    ///
+    /// * `histogram` - The latencies in microseconds.
+    fn from_histogram(histogram: &Histogram<u64>) -> Self {
+        Self {
+            mean_us: histogram.mean(),
+            p50_us: histogram.value_at_quantile(0.5),
+            p99_us: histogram.value_at_quantile(0.99),
+            p999_us: histogram.value_at_quantile(0.999),
+            max_us: histogram.max(),
+        }
+    }
+}
+
+/// The results of one operation.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OperationReport {
+    pub operation: Operation,
+    /// The amount of successful requests, including misses.
+    pub requests: u64,
+    /// The amount of requests for records which did not exist.
+    pub misses: u64,
+    pub latency: Latency,
+}
+
+/// A percentile of the latency distribution.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Percentile {
+    pub percentile: f64,
+    pub latency_us: u64,
+}
+
+/// How the benchmark was configured.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Settings {
+    pub connections: usize,
+    pub concurrency: usize,
+    pub in_flight: usize,
+    pub keys: u64,
+    pub key_size: usize,
+    pub value_size: usize,
+    pub mix: String,
+    pub ttl: String,
+}
+
+/// The results of a benchmark.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+    pub settings: Settings,
+    /// The amount of successful requests.
+    pub requests: u64,
+    pub errors: u64,
+    pub duration_secs: f64,
+    /// Successful requests per second.
+    pub throughput: f64,
+    /// The latency of all operations.
+    pub latency: Latency,
+    pub distribution: Vec<Percentile>,
+    pub operations: Vec<OperationReport>,
+}
+
+impl Report {
+    /// Create the report of a benchmark.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - How the benchmark was configured.
+    /// * `recorder` - The requests of all workers.
+    /// * `elapsed` - How long the benchmark took.
+    pub fn new(settings: Settings, recorder: &Recorder, elapsed: Duration) -> Self {
+        let mut all = Histogram::<u64>::new(3).expect("3 significant figures are supported");
+        let mut operations = Vec::new();
+
+        for operation in Operation::ALL {
+            let histogram = &recorder.histograms[operation.index()];
+            if histogram.is_empty() {
+                continue;
+            }
+
+            all.add(histogram)
+                .expect("the histograms have the same bounds");
+            operations.push(OperationReport {
+                operation,
+                requests: histogram.len(),
+                misses: recorder.misses[operation.index()],
+                latency: Latency::from_histogram(histogram),
+            });
+        }
+
+        let duration_secs = elapsed.as_secs_f64();
+        Self {
+            settings,
+            requests: all.len(),
+            errors: recorder.errors,
+            duration_secs,
+            throughput: match duration_secs {
+                secs if secs > 0.0 => all.len() as f64 / secs,
+                _ => 0.0,
+            },
+            latency: Latency::from_histogram(&all),
+            distribution: PERCENTILES
+                .iter()
+                .map(|percentile| Percentile {
+                    percentile: *percentile,
+                    latency_us: all.value_at_percentile(*percentile),
+                })
+                .collect(),
+            operations,
+        }
+    }
+}
+
+/// Format microseconds as a duration.
+///
+/// # Arguments
+///
+/// * `micros` - The amount of microseconds.
+fn micros(micros: u64) -> String {
+    format!("{:.2?}", Duration::from_micros(micros))
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let settings = &self.settings;
+        writeln!(
+            f,
+            "{} requests in {:.2}s, over {} connections (concurrency {}, in flight {})",
+            self.requests,
+            self.duration_secs,
+            settings.connections,
+            settings.concurrency,
+            settings.in_flight
+        )?;
+        writeln!(
+            f,
+            "Mix {} (read:write:drop), TTL {}, {} keys of {} bytes, values of {} bytes",
+            settings.mix, settings.ttl, settings.keys, settings.key_size, settings.value_size
+        )?;
+        writeln!(f, "Throughput: {:.0} requests/s", self.throughput)?;
+        writeln!(f, "Errors: {}", self.errors)?;
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<10} {:>10} {:>8} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            "operation", "requests", "misses", "mean", "p50", "p99", "p99.9", "max"
+        )?;
+        for operation in &self.operations {
+            let latency = &operation.latency;
+            writeln!(
+                f,
+                "{:<10} {:>10} {:>8} {:>10} {:>10} {:>10} {:>10} {:>10}",
+                operation.operation.to_string(),
+                operation.requests,
+                operation.misses,
+                micros(latency.mean_us as u64),
+                micros(latency.p50_us),
+                micros(latency.p99_us),
+                micros(latency.p999_us),
+                micros(latency.max_us)
+            )?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Latency distribution:")?;
+        for percentile in &self.distribution {
+            writeln!(
+                f,
+                "  {:>7.3}%  {}",
+                percentile.percentile,
+                micros(percentile.latency_us)
+            )?;
+        }
+
+        Ok(())
+    }
+}
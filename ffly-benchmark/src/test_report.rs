@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+use crate::{
+    report::{Outcome, Recorder, Report, Settings},
+    workload::Operation,
+};
+
+fn settings() -> Settings {
+    Settings {
+        connections: 2,
+        concurrency: 4,
+        in_flight: 1,
+        keys: 100,
+        key_size: 64,
+        value_size: 36,
+        mix: "1:1:0".to_string(),
+        ttl: "never".to_string(),
+    }
+}
+
+#[test]
+fn test_report() {
+    let (mut first, mut second) = (Recorder::default(), Recorder::default());
+    for micros in 1..=100 {
+        first.record(Operation::Read, Duration::from_micros(micros), Outcome::Ok);
+    }
+    first.record(Operation::Read, Duration::from_micros(5), Outcome::Miss);
+    second.record(Operation::Write, Duration::from_millis(2), Outcome::Ok);
+    second.record(Operation::Write, Duration::from_secs(1), Outcome::Error);
+    second.first_error = Some("Error: Rate limited!".to_string());
+
+    first.merge(&second);
+    assert_eq!(first.first_error.as_deref(), Some("Error: Rate limited!"));
+
+    let report = Report::new(settings(), &first, Duration::from_secs(2));
+    assert_eq!(report.requests, 102);
+    assert_eq!(report.errors, 1);
+    assert_eq!(report.throughput, 51.0);
+    assert_eq!(report.latency.max_us, 2000);
+
+    // Operations without requests are left out.
+    assert_eq!(report.operations.len(), 2);
+    let read = &report.operations[0];
+    assert_eq!(read.operation, Operation::Read);
+    assert_eq!((read.requests, read.misses), (101, 1));
+    assert_eq!(read.latency.p50_us, 50);
+    assert_eq!(read.latency.p99_us, 99);
+    assert_eq!(read.latency.max_us, 100);
+
+    assert_eq!(report.distribution.last().unwrap().percentile, 100.0);
+    assert_eq!(report.distribution.last().unwrap().latency_us, 2000);
+}
+
+#[test]
+fn test_report_formats() {
+    let mut recorder = Recorder::default();
+    recorder.record(Operation::Drop, Duration::from_micros(10), Outcome::Ok);
+    let report = Report::new(settings(), &recorder, Duration::from_secs(1));
+
+    let text = report.to_string();
+    assert!(text.contains("Throughput: 1 requests/s"));
+    assert!(text.contains("drop"));
+    assert!(text.contains("99.900%"));
+
+    let json: serde_json::Value = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["operations"][0]["operation"], "drop");
+    assert_eq!(json["operations"][0]["latency"]["p999_us"], 10);
+    assert_eq!(json["settings"]["mix"], "1:1:0");
+}
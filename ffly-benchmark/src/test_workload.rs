@@ -0,0 +1,102 @@
+use fastrand::Rng;
+
+use crate::workload::{key, value, Mix, Operation, TtlDistribution, Workload};
+
+#[test]
+fn test_parse_mix() {
+    let mix: Mix = "80:15:5".parse().unwrap();
+    assert_eq!(
+        mix,
+        Mix {
+            read: 80,
+            write: 15,
+            drop: 5
+        }
+    );
+    assert_eq!(mix.to_string(), "80:15:5");
+
+    assert!("80:20".parse::<Mix>().is_err());
+    assert!("a:b:c".parse::<Mix>().is_err());
+    assert!("0:0:0".parse::<Mix>().is_err());
+    assert!("4294967295:1:0".parse::<Mix>().is_err());
+}
+
+#[test]
+fn test_mix_pick() {
+    let mut rng = Rng::with_seed(1);
+    let mut picked = [0; 3];
+    let mix: Mix = "2:1:0".parse().unwrap();
+
+    for _ in 0..3000 {
+        picked[mix.pick(&mut rng).index()] += 1;
+    }
+
+    assert_eq!(picked[Operation::Drop.index()], 0);
+    assert!((1800..2200).contains(&picked[Operation::Read.index()]));
+}
+
+#[test]
+fn test_parse_ttl() {
+    let never: TtlDistribution = "never".parse().unwrap();
+    assert_eq!(never.share, 0);
+    assert_eq!(never.to_string(), "never");
+
+    let fixed: TtlDistribution = "60".parse().unwrap();
+    assert_eq!((fixed.share, fixed.min, fixed.max), (100, 60, 60));
+
+    let ranged: TtlDistribution = "25%:60-3600".parse().unwrap();
+    assert_eq!((ranged.share, ranged.min, ranged.max), (25, 60, 3600));
+    assert_eq!(ranged.to_string(), "25%:60-3600");
+
+    for invalid in ["0", "10-5", "101%:60", "soon", "50%"] {
+        assert!(invalid.parse::<TtlDistribution>().is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn test_ttl_pick() {
+    let mut rng = Rng::with_seed(2);
+    let ttl: TtlDistribution = "50%:60-120".parse().unwrap();
+    let picked: Vec<u64> = (0..1000).map(|_| ttl.pick(&mut rng, 1000)).collect();
+
+    let expiring = picked.iter().filter(|ttl| **ttl != 0).count();
+    assert!((400..600).contains(&expiring));
+    assert!(picked
+        .iter()
+        .all(|ttl| *ttl == 0 || (1060..=1120).contains(ttl)));
+}
+
+#[test]
+fn test_workload() {
+    let workload = Workload {
+        mix: "1:1:1".parse().unwrap(),
+        ttl: "60".parse().unwrap(),
+        keys: 10,
+        key_size: 8,
+    };
+
+    let mut rng = Rng::with_seed(3);
+    for _ in 0..100 {
+        let request = workload.next(&mut rng, 1000);
+        assert_eq!(request.key.len(), 8);
+        assert!(request.key.parse::<u64>().unwrap() < 10);
+        match request.operation {
+            Operation::Write => assert_eq!(request.ttl, 1060),
+            _ => assert_eq!(request.ttl, 0),
+        }
+    }
+
+    // The same seed gives the same requests.
+    let first = workload.next(&mut Rng::with_seed(4), 1000);
+    assert_eq!(workload.next(&mut Rng::with_seed(4), 1000), first);
+}
+
+#[test]
+fn test_key_and_value() {
+    assert_eq!(key(42, 6), "000042");
+    assert_eq!(key(1234, 2), "1234");
+
+    let value = value(&mut Rng::with_seed(5), 36);
+    assert_eq!(value.len(), 36);
+    assert!(value.chars().all(|char| char.is_ascii_alphanumeric()));
+}
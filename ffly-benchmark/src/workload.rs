@@ -0,0 +1,231 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use fastrand::Rng;
+use serde::Serialize;
+
+/// The operations a benchmark sends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    /// Get the value of a record.
+    Read,
+    /// Create (or overwrite) a record.
+    Write,
+    /// Remove a record.
+    Drop,
+}
+
+impl Operation {
+    pub const ALL: [Operation; 3] = [Operation::Read, Operation::Write, Operation::Drop];
+
+    /// The position of the operation in `Operation::ALL`.
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operation::Read => write!(f, "read"),
+            Operation::Write => write!(f, "write"),
+            Operation::Drop => write!(f, "drop"),
+        }
+    }
+}
+
+/// How often each operation gets picked, relative to each other.
+/// (`READ:WRITE:DROP`, e.g. `80:15:5`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mix {
+    pub read: u32,
+    pub write: u32,
+    pub drop: u32,
+}
+
+impl Mix {
+    /// Pick an operation, weighted by the mix.
+    ///
+    /// # Arguments
+    ///
+    /// * `rng` - The random number generator to pick with.
+    pub fn pick(&self, rng: &mut Rng) -> Operation {
+        let picked = rng.u32(0..self.read + self.write + self.drop);
+
+        if picked < self.read {
+            Operation::Read
+        } else if picked < self.read + self.write {
+            Operation::Write
+        } else {
+            Operation::Drop
+        }
+    }
+}
+
+impl FromStr for Mix {
+    type Err = anyhow::Error;
+
+    fn from_str(mix: &str) -> Result<Self> {
+        let invalid = || anyhow!("The mix must be READ:WRITE:DROP, e.g. 80:15:5");
+
+        let weights = mix
+            .split(':')
+            .map(|weight| weight.trim().parse::<u32>().map_err(|_| invalid()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let [read, write, drop] = weights[..] else {
+            return Err(invalid());
+        };
+
+        // The weights get summed to pick an operation.
+        let total = read
+            .checked_add(write)
+            .and_then(|total| total.checked_add(drop))
+            .ok_or_else(invalid)?;
+        if total == 0 {
+            return Err(anyhow!("The mix must contain at least one operation"));
+        }
+
+        Ok(Self { read, write, drop })
+    }
+}
+
+impl fmt::Display for Mix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.read, self.write, self.drop)
+    }
+}
+
+/// The TTLs new records get. A share of the writes gets a TTL, which is picked
+/// uniformly between a minimum and maximum amount of seconds.
+/// (`never`, `SECONDS`, `MIN-MAX` or `PERCENTAGE%:MIN-MAX`, e.g. `25%:60-3600`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TtlDistribution {
+    /// The percentage of the writes which get a TTL.
+    pub share: u32,
+    pub min: u64,
+    pub max: u64,
+}
+
+impl TtlDistribution {
+    /// Pick the TTL of a new record.
+    ///
+    /// # Arguments
+    ///
+    /// * `rng` - The random number generator to pick with.
+    /// * `now` - The current time. (seconds since the UNIX epoch)
+    pub fn pick(&self, rng: &mut Rng, now: u64) -> u64 {
+        match rng.u32(0..100) < self.share {
+            true => now + rng.u64(self.min..=self.max),
+            false => 0,
+        }
+    }
+}
+
+impl FromStr for TtlDistribution {
+    type Err = anyhow::Error;
+
+    fn from_str(ttl: &str) -> Result<Self> {
+        let invalid = || anyhow!("The TTL must be never, SECONDS, MIN-MAX or PERCENTAGE%:MIN-MAX");
+
+        if ttl == "never" {
+            return Ok(Self {
+                share: 0,
+                min: 0,
+                max: 0,
+            });
+        }
+
+        let (share, range) = match ttl.split_once("%:") {
+            Some((share, range)) => (share.parse().map_err(|_| invalid())?, range),
+            None => (100, ttl),
+        };
+
+        let (min, max) = match range.split_once('-') {
+            Some((min, max)) => (min.parse(), max.parse()),
+            None => (range.parse(), range.parse()),
+        };
+
+        match (min, max) {
+            (Ok(min), Ok(max)) if min > 0 && min <= max && share <= 100 => {
+                Ok(Self { share, min, max })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for TtlDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.share {
+            0 => write!(f, "never"),
+            share => write!(f, "{}%:{}-{}", share, self.min, self.max),
+        }
+    }
+}
+
+/// A request of the benchmark.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub operation: Operation,
+    pub key: String,
+    /// The TTL for writes. (seconds since the UNIX epoch, 0 = never)
+    pub ttl: u64,
+}
+
+/// What a benchmark sends.
+#[derive(Debug, Clone)]
+pub struct Workload {
+    pub mix: Mix,
+    pub ttl: TtlDistribution,
+    /// The amount of distinct keys the requests are spread over.
+    pub keys: u64,
+    /// The size of the keys in bytes.
+    pub key_size: usize,
+}
+
+impl Workload {
+    /// Generate the next request.
+    ///
+    /// # Arguments
+    ///
+    /// * `rng` - The random number generator to pick the request with.
+    /// * `now` - The current time. (seconds since the UNIX epoch)
+    pub fn next(&self, rng: &mut Rng, now: u64) -> Request {
+        let operation = self.mix.pick(rng);
+        let ttl = match operation {
+            Operation::Write => self.ttl.pick(rng, now),
+            _ => 0,
+        };
+
+        Request {
+            operation,
+            key: key(rng.u64(0..self.keys.max(1)), self.key_size),
+            ttl,
+        }
+    }
+}
+
+/// The key of a record, its index padded with zeroes to the key size. Keys
+/// are never shorter than their index.
+///
+/// # Arguments
+///
+/// * `index` - The index of the key in the key space.
+/// * `size` - The size of the key in bytes.
+pub fn key(index: u64, size: usize) -> String {
+    format!("{:0>size$}", index)
+}
+
+/// A random alphanumeric value.
+///
+/// # Arguments
+///
+/// * `rng` - The random number generator.
+/// * `size` - The size of the value in bytes.
+pub fn value(rng: &mut Rng, size: usize) -> String {
+    std::iter::repeat_with(|| rng.alphanumeric())
+        .take(size)
+        .collect()
+}
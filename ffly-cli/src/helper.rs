@@ -0,0 +1,138 @@
+use std::borrow::Cow;
+
+use rustyline::{
+    completion::{Completer, Pair},
+    highlight::Highlighter,
+    hint::Hinter,
+    validate::Validator,
+    Context, Helper,
+};
+
+use crate::query::{match_keyword, KEYWORDS, STREAMING};
+
+/// The commands of ffly-cli itself, which don't get sent to the server.
+pub const COMMANDS: [&str; 6] = [
+    ".mode string",
+    ".mode bitwise",
+    ".dump",
+    ".restore",
+    ".help",
+    ".quit",
+];
+
+const KEYWORD_COLOR: &str = "\x1b[1;34m";
+const STRING_COLOR: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// Highlights and completes queries while they get typed.
+#[derive(Debug, Default)]
+pub struct CliHelper;
+
+/// Color the keyword of a query, and the quoted strings in its arguments.
+/// Lines which don't start with a keyword are left as they are.
+///
+/// # Arguments
+///
+/// * `line` - The line to color.
+pub fn highlight_query(line: &str) -> Cow<'_, str> {
+    let Some((_, _, arguments)) = match_keyword(line) else {
+        return Cow::Borrowed(line);
+    };
+
+    let keyword = &line[..line.len() - arguments.len()];
+    let mut highlighted = format!("{}{}{}", KEYWORD_COLOR, keyword, RESET);
+    let mut quote: Option<char> = None;
+
+    for char in arguments.chars() {
+        match (quote, char) {
+            (Some(open), char) if char == open => {
+                highlighted.push(char);
+                highlighted.push_str(RESET);
+                quote = None;
+                continue;
+            }
+            (None, '\'' | '"') => {
+                highlighted.push_str(STRING_COLOR);
+                quote = Some(char);
+            }
+            _ => {}
+        }
+        highlighted.push(char);
+    }
+
+    if quote.is_some() {
+        highlighted.push_str(RESET);
+    }
+
+    Cow::Owned(highlighted)
+}
+
+/// Get the completions for what is typed before the cursor. Keywords (and
+/// commands) only get completed at the start of the line, streaming queries
+/// are left out as they can't be executed. Returns where the
+/// completed part starts, and the completions.
+///
+/// # Arguments
+///
+/// * `line` - The line which is being typed.
+/// * `pos` - The position of the cursor.
+pub fn complete_line(line: &str, pos: usize) -> (usize, Vec<String>) {
+    let typed = &line[..pos];
+    let start = typed.len() - typed.trim_start().len();
+    let typed = typed.trim_start().to_uppercase();
+
+    let completions = match typed.starts_with('.') {
+        true => COMMANDS
+            .iter()
+            .filter(|command| command.to_uppercase().starts_with(&typed))
+            .map(|command| command.to_string())
+            .collect(),
+        false => KEYWORDS
+            .iter()
+            .filter(|(keyword, byte)| keyword.starts_with(&typed) && !STREAMING.contains(byte))
+            .map(|(keyword, _)| keyword.to_string())
+            .collect(),
+    };
+
+    (start, completions)
+}
+
+impl Completer for CliHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, completions) = complete_line(line, pos);
+        let candidates = completions
+            .into_iter()
+            .map(|completion| Pair {
+                display: completion.clone(),
+                replacement: completion,
+            })
+            .collect();
+
+        Ok((start, candidates))
+    }
+}
+
+impl Highlighter for CliHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        highlight_query(line)
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _forced: bool) -> bool {
+        true
+    }
+}
+
+impl Hinter for CliHelper {
+    type Hint = String;
+}
+
+impl Validator for CliHelper {}
+
+impl Helper for CliHelper {}
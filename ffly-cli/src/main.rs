@@ -0,0 +1,337 @@
+use std::{
+    io::{self, IsTerminal, Read},
+    path::PathBuf,
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+use ffly_rs::FireflyStream;
+use rustyline::{error::ReadlineError, history::DefaultHistory, Editor};
+
+use crate::{
+    helper::CliHelper,
+    query::{format_response, match_keyword, to_query, Mode},
+};
+
+mod helper;
+mod query;
+
+#[cfg(test)]
+mod test_helper;
+
+#[cfg(test)]
+mod test_query;
+
+const HELP: &str = "\
+Queries are typed like string queries, e.g. NEW 'key' VALUE 'value';
+In bitwise mode the quotes and filler words are optional, e.g. NEW key value
+
+Commands:
+  .mode string|bitwise  Switch the query syntax of the connection
+  .dump <file>          Write a dump of all databases to a file
+  .restore <file>       Replace all databases with a dump
+  .help                 Print this help
+  .quit                 Close the connection";
+
+/// An interactive client for Firefly servers.
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None, disable_help_flag = true)]
+struct Args {
+    /// Print help.
+    #[clap(long, action = clap::ArgAction::Help)]
+    help: Option<bool>,
+
+    /// The host of the server.
+    #[clap(short, long, default_value = "127.0.0.1", env = "FFLY_HOST")]
+    host: String,
+
+    /// The port of the server.
+    #[clap(short, long, default_value_t = 46600, env = "FFLY_PORT")]
+    port: u16,
+
+    /// Connect over a Unix socket instead of TCP.
+    #[clap(long)]
+    socket: Option<String>,
+
+    /// The user to authenticate as.
+    #[clap(short, long, env = "FFLY_USER")]
+    user: Option<String>,
+
+    /// The password of the user.
+    #[clap(long, env = "FFLY_PASSWORD")]
+    password: Option<String>,
+
+    /// Start in bitwise mode instead of string mode.
+    #[clap(long)]
+    bitwise: bool,
+
+    /// The maximum size of a response, in bytes.
+    #[clap(long, default_value_t = 65536)]
+    max_response_size: usize,
+
+    /// Execute a query (or command) and exit, can be repeated.
+    #[clap(short, long)]
+    execute: Vec<String>,
+}
+
+/// A connection to a server, and the query syntax it uses.
+struct Cli {
+    firefly: FireflyStream,
+    mode: Mode,
+}
+
+impl Cli {
+    /// Connect to the server and authenticate, if a user is given.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - Where to connect to, and how.
+    async fn connect(args: &Args) -> Result<Self> {
+        let firefly = match &args.socket {
+            #[cfg(unix)]
+            Some(path) => FireflyStream::connect_unix_with_max_buffer(path, args.max_response_size)
+                .await
+                .map_err(|e| anyhow!("Could not connect to {}: {}", path, e))?,
+            #[cfg(not(unix))]
+            Some(_) => return Err(anyhow!("Unix sockets are not supported on this platform")),
+            None => {
+                let address = format!("{}:{}", args.host, args.port);
+                FireflyStream::connect_with_max_buffer(&address, args.max_response_size)
+                    .await
+                    .map_err(|e| anyhow!("Could not connect to {}: {}", address, e))?
+            }
+        };
+
+        if let (Some(user), Some(password)) = (&args.user, &args.password) {
+            firefly
+                .auth(user, password)
+                .await
+                .map_err(|e| anyhow!("Could not authenticate: {}", e))?;
+        }
+
+        // The connection starts in bitwise mode.
+        let mut cli = Self {
+            firefly,
+            mode: Mode::Bitwise,
+        };
+        if !args.bitwise {
+            cli.set_mode(Mode::String).await?;
+        }
+
+        Ok(cli)
+    }
+
+    /// Switch the query syntax of the connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - The query syntax to switch to.
+    async fn set_mode(&mut self, mode: Mode) -> Result<()> {
+        if mode == self.mode {
+            return Ok(());
+        }
+
+        let query: &[u8] = match mode {
+            Mode::String => b"6",
+            Mode::Bitwise => b"QUERY TYPE BITWISE;",
+        };
+
+        let response = self.firefly.query(query).await.map_err(|e| anyhow!(e))?;
+        if response != "Ok" {
+            return Err(anyhow!(response));
+        }
+
+        self.mode = mode;
+        Ok(())
+    }
+
+    /// Run a command of ffly-cli itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command, including its `.`.
+    async fn command(&mut self, command: &str) -> Result<String> {
+        let (name, argument) = command
+            .split_once(char::is_whitespace)
+            .map_or((command, ""), |(name, argument)| (name, argument.trim()));
+
+        match (name, argument) {
+            (".help", _) => Ok(HELP.to_string()),
+            (".mode", "string") => self.set_mode(Mode::String).await.map(|_| "Ok".into()),
+            (".mode", "bitwise") => self.set_mode(Mode::Bitwise).await.map(|_| "Ok".into()),
+            (".mode", _) => Err(anyhow!("Usage: .mode string|bitwise")),
+            (".dump" | ".restore", "") => Err(anyhow!("Usage: {} <file>", name)),
+            (".dump", path) => {
+                let mut file = tokio::fs::File::create(path)
+                    .await
+                    .with_context(|| format!("Could not create {}", path))?;
+
+                // Dumps are only streamed in bitwise mode.
+                let mode = self.mode;
+                self.set_mode(Mode::Bitwise).await?;
+                let dumped = self.firefly.dump(&mut file).await;
+                self.set_mode(mode).await?;
+
+                let bytes = dumped.map_err(|e| anyhow!(e))?;
+                Ok(format!("Wrote {} bytes to {}", bytes, path))
+            }
+            (".restore", path) => {
+                let mut file = tokio::fs::File::open(path)
+                    .await
+                    .with_context(|| format!("Could not open {}", path))?;
+
+                let mode = self.mode;
+                self.set_mode(Mode::Bitwise).await?;
+                let restored = self.firefly.restore(&mut file).await;
+                self.set_mode(mode).await?;
+
+                restored.map_err(|e| anyhow!(e))?;
+                Ok("Ok".to_string())
+            }
+            _ => Err(anyhow!("Unknown command {}, see .help", name)),
+        }
+    }
+
+    /// Execute a line, a query or a command. Returns the formatted response,
+    /// or the error the server responded with.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The line to execute.
+    async fn execute(&mut self, line: &str) -> Result<String> {
+        if line.starts_with('.') {
+            return self.command(line).await;
+        }
+
+        let query = to_query(line, self.mode).map_err(|e| anyhow!(e))?;
+        let query_type = match_keyword(line).map(|(_, byte, _)| byte);
+        let response = self.firefly.query(&query).await.map_err(|e| anyhow!(e))?;
+
+        if response.starts_with("Error") {
+            return Err(anyhow!(response));
+        }
+
+        // Typed QUERY TYPE queries switch the mode as well.
+        match query_type {
+            Some(b'6') => self.mode = Mode::String,
+            Some(b'7') => self.mode = Mode::Bitwise,
+            _ => {}
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        Ok(format_response(query_type, &response, now))
+    }
+}
+
+/// The file the history of the interactive mode gets kept in.
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".ffly_history"))
+}
+
+/// Execute lines one by one, for scripts. Stops at the first error.
+///
+/// # Arguments
+///
+/// * `cli` - The connection to execute the lines on.
+/// * `lines` - The lines to execute, empty lines get skipped.
+async fn run_script(cli: &mut Cli, lines: &[String]) -> Result<()> {
+    for line in lines.iter().map(|line| line.trim()) {
+        if line.is_empty() {
+            continue;
+        }
+
+        println!("{}", cli.execute(line).await?);
+    }
+
+    Ok(())
+}
+
+/// Read lines from the terminal, with line editing and history, until the
+/// user quits.
+///
+/// # Arguments
+///
+/// * `cli` - The connection to execute the lines on.
+/// * `prompt` - What the connection is to, shown before every line.
+async fn run_interactive(cli: &mut Cli, prompt: &str) -> Result<()> {
+    let mut editor: Editor<CliHelper, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(CliHelper));
+
+    let history = history_path();
+    if let Some(path) = &history {
+        // There is no history the first time.
+        let _ = editor.load_history(path);
+    }
+
+    loop {
+        let mode = match cli.mode {
+            Mode::String => "string",
+            Mode::Bitwise => "bitwise",
+        };
+
+        let line = match editor.readline(&format!("{} ({})> ", prompt, mode)) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        editor.add_history_entry(line)?;
+        if line == ".quit" {
+            break;
+        }
+
+        match cli.execute(line).await {
+            Ok(response) => println!("{}", response),
+            Err(e) => println!("{:#}", e),
+        }
+    }
+
+    if let Some(path) = &history {
+        editor.save_history(path)?;
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+
+    let result = async {
+        let mut cli = Cli::connect(&args).await?;
+
+        if !args.execute.is_empty() {
+            return run_script(&mut cli, &args.execute).await;
+        }
+
+        if !io::stdin().is_terminal() {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            let lines: Vec<String> = input.lines().map(String::from).collect();
+            return run_script(&mut cli, &lines).await;
+        }
+
+        let prompt = match &args.socket {
+            Some(path) => path.clone(),
+            None => format!("{}:{}", args.host, args.port),
+        };
+        run_interactive(&mut cli, &prompt).await
+    }
+    .await;
+
+    if let Err(e) = result {
+        eprintln!("{:#}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,246 @@
+/// Every query, as its keyword is typed in a string query, with its bitwise
+/// type.
+pub const KEYWORDS: [(&str, u8); 28] = [
+    ("NEW", b'0'),
+    ("GET", b'1'),
+    ("GET VALUE", b'2'),
+    ("GET TTL", b'3'),
+    ("DROP", b'4'),
+    ("DROP ALL", b'5'),
+    ("QUERY TYPE STRING", b'6'),
+    ("QUERY TYPE BITWISE", b'7'),
+    ("AUTH", b'8'),
+    ("INFO", b'9'),
+    ("SLOWLOG GET", b'A'),
+    ("SLOWLOG RESET", b'B'),
+    ("MONITOR", b'C'),
+    ("CLIENT LIST", b'D'),
+    ("CLIENT SETNAME", b'E'),
+    ("CLIENT KILL", b'F'),
+    ("SELECT", b'G'),
+    ("FLUSHDB", b'H'),
+    ("DBSIZE", b'J'),
+    ("SAVE", b'K'),
+    ("BGSAVE", b'L'),
+    ("LASTSAVE", b'M'),
+    ("FLUSHALL", b'N'),
+    ("SYNC", b'P'),
+    ("REPLICAOF", b'Q'),
+    ("RAFT", b'R'),
+    ("DUMP", b'S'),
+    ("RESTORE", b'T'),
+];
+
+/// Queries which switch the connection to a streaming protocol, these can't
+/// be executed as a single query.
+pub const STREAMING: [u8; 5] = [b'C', b'P', b'R', b'S', b'T'];
+
+/// The query syntax the connection uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    String,
+    Bitwise,
+}
+
+/// Match the letters of a keyword against the start of a query, like the
+/// server whitespace and case are ignored. Returns the amount of letters that
+/// matched, and the rest of the query after the last matched letter.
+///
+/// # Arguments
+///
+/// * `query` - The query to match.
+/// * `keyword` - The keyword to match against.
+fn match_letters<'q>(query: &'q str, keyword: &str) -> (usize, &'q str) {
+    let mut letters = keyword.bytes().filter(|byte| *byte != b' ').peekable();
+    let (mut matched, mut end) = (0, 0);
+
+    for (index, byte) in query.bytes().enumerate() {
+        if byte.is_ascii_whitespace() {
+            continue;
+        }
+
+        match letters.next_if(|letter| byte.eq_ignore_ascii_case(letter)) {
+            Some(_) => (matched, end) = (matched + 1, index + 1),
+            None => break,
+        }
+    }
+
+    (matched, &query[end..])
+}
+
+/// Find the keyword a query starts with. Like the server, a keyword may be
+/// abbreviated as long as only one keyword starts that way, so `GETV` is
+/// `GET VALUE`. Unlike the server, a keyword must be followed by whitespace, a
+/// quote or the end of the query, so it never runs into an unquoted argument.
+/// Returns the keyword, its bitwise type and the rest of the query.
+///
+/// # Arguments
+///
+/// * `query` - The query to find the keyword of.
+pub fn match_keyword(query: &str) -> Option<(&'static str, u8, &str)> {
+    let matches: Vec<_> = KEYWORDS
+        .iter()
+        .map(|(keyword, byte)| {
+            let (matched, rest) = match_letters(query, keyword);
+            (*keyword, *byte, matched, rest)
+        })
+        .filter(|(_, _, matched, rest)| {
+            *matched > 0
+                && rest
+                    .chars()
+                    .next()
+                    .is_none_or(|char| char.is_whitespace() || "'\";".contains(char))
+        })
+        .collect();
+
+    let most = matches.iter().map(|(_, _, matched, _)| *matched).max()?;
+    let longest: Vec<_> = matches
+        .into_iter()
+        .filter(|(_, _, matched, _)| *matched == most)
+        .collect();
+
+    let complete = longest
+        .iter()
+        .find(|(keyword, _, matched, _)| keyword.replace(' ', "").len() == *matched);
+
+    match (complete, longest.as_slice()) {
+        (Some((keyword, byte, _, rest)), _) | (None, [(keyword, byte, _, rest)]) => {
+            Some((keyword, *byte, rest))
+        }
+        _ => None,
+    }
+}
+
+/// Words which only make a string query readable, they are not arguments.
+const FILLER_WORDS: [&str; 3] = ["VALUE", "WITH", "TTL"];
+
+/// Split the arguments of a query on whitespace. Arguments with whitespace can
+/// be wrapped in single or double quotes, a trailing `;` is ignored. Unquoted
+/// filler words are skipped, so string query syntax can be used as well.
+/// (`NEW 'key' VALUE 'value'` is the same as `NEW key value`)
+///
+/// # Arguments
+///
+/// * `arguments` - The arguments to split.
+pub fn split_arguments(arguments: &str) -> Result<Vec<String>, String> {
+    let arguments = arguments.trim().trim_end_matches(';');
+    let mut split = Vec::new();
+    let mut current: Option<(String, bool)> = None;
+    let mut quote: Option<char> = None;
+
+    let mut finish = |current: Option<(String, bool)>| {
+        if let Some((word, quoted)) = current {
+            let filler = FILLER_WORDS.contains(&word.to_uppercase().as_str());
+            if quoted || !filler {
+                split.push(word);
+            }
+        }
+    };
+
+    for char in arguments.chars() {
+        match (quote, char) {
+            (Some(open), char) if char == open => quote = None,
+            (None, '\'' | '"') => {
+                quote = Some(char);
+                current.get_or_insert_with(Default::default).1 = true;
+            }
+            (None, char) if char.is_whitespace() => finish(current.take()),
+            (_, char) => current.get_or_insert_with(Default::default).0.push(char),
+        }
+    }
+
+    if let Some(open) = quote {
+        return Err(format!("Missing closing {}", open));
+    }
+
+    finish(current);
+    Ok(split)
+}
+
+/// Convert a query to a bitwise query, the arguments are split by
+/// `split_arguments`. `NEW` without a TTL gets a TTL of 0. (never expire)
+///
+/// # Arguments
+///
+/// * `line` - The query, e.g. `GET VALUE key`.
+pub fn to_bitwise(line: &str) -> Result<Vec<u8>, String> {
+    let (_, byte, rest) = match_keyword(line).ok_or("Unknown query")?;
+    let mut arguments = split_arguments(rest)?;
+
+    if byte == b'0' && arguments.len() == 2 {
+        arguments.push("0".to_string());
+    }
+
+    let mut query = vec![byte];
+    query.extend_from_slice(arguments.join("\0").as_bytes());
+    Ok(query)
+}
+
+/// Convert a line to the query that gets sent in a mode. Returns an error
+/// message if the query can't be sent.
+///
+/// # Arguments
+///
+/// * `line` - The line the user typed.
+/// * `mode` - The query syntax of the connection.
+pub fn to_query(line: &str, mode: Mode) -> Result<Vec<u8>, String> {
+    if let Some((keyword, byte, _)) = match_keyword(line) {
+        if STREAMING.contains(&byte) {
+            return Err(format!("{} can't be executed by ffly-cli", keyword));
+        }
+    }
+
+    match mode {
+        Mode::Bitwise => to_bitwise(line),
+        Mode::String => {
+            let line = line.trim();
+            match line.ends_with(';') {
+                true => Ok(line.as_bytes().to_vec()),
+                false => Ok(format!("{};", line).into_bytes()),
+            }
+        }
+    }
+}
+
+/// Describe when a TTL expires.
+///
+/// # Arguments
+///
+/// * `ttl` - The TTL. (seconds since the UNIX epoch, 0 = never)
+/// * `now` - The current time. (seconds since the UNIX epoch)
+pub fn describe_ttl(ttl: &str, now: u64) -> String {
+    match ttl.parse::<u64>() {
+        Ok(0) => format!("{} (never expires)", ttl),
+        Ok(expiry) if expiry <= now => format!("{} (expired)", ttl),
+        Ok(expiry) => format!("{} (expires in {}s)", ttl, expiry - now),
+        Err(_) => ttl.to_string(),
+    }
+}
+
+/// Format a response to be read by humans. The value and TTL of `GET` get
+/// labeled, other responses with multiple NUL delimited values get numbered.
+///
+/// # Arguments
+///
+/// * `query_type` - The bitwise type of the query which got executed.
+/// * `response` - The response of the server.
+/// * `now` - The current time. (seconds since the UNIX epoch)
+pub fn format_response(query_type: Option<u8>, response: &str, now: u64) -> String {
+    if response.starts_with("Error") {
+        return response.to_string();
+    }
+
+    match (query_type, response.split_once('\0')) {
+        (Some(b'1'), Some((value, ttl))) => {
+            format!("value: {}\nttl: {}", value, describe_ttl(ttl, now))
+        }
+        (Some(b'3'), None) => describe_ttl(response, now),
+        (_, Some(_)) => response
+            .split('\0')
+            .enumerate()
+            .map(|(index, value)| format!("{}) {}", index + 1, value))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        (_, None) => response.to_string(),
+    }
+}
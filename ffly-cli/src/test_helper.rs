@@ -0,0 +1,41 @@
+use crate::helper::{complete_line, highlight_query};
+
+#[test]
+fn test_complete_keywords() {
+    let (start, completions) = complete_line("  get ", 6);
+    assert_eq!(start, 2);
+    assert_eq!(completions, vec!["GET VALUE", "GET TTL"]);
+
+    let (start, completions) = complete_line("fl", 2);
+    assert_eq!(start, 0);
+    assert_eq!(completions, vec!["FLUSHDB", "FLUSHALL"]);
+
+    // Only the part before the cursor counts.
+    let (_, completions) = complete_line("dbx", 1);
+    assert_eq!(completions, vec!["DROP", "DROP ALL", "DBSIZE"]);
+
+    let (_, completions) = complete_line("GET 'key'", 9);
+    assert!(completions.is_empty());
+}
+
+#[test]
+fn test_complete_commands() {
+    let (_, completions) = complete_line(".m", 2);
+    assert_eq!(completions, vec![".mode string", ".mode bitwise"]);
+
+    let (_, completions) = complete_line(".RES", 4);
+    assert_eq!(completions, vec![".restore"]);
+}
+
+#[test]
+fn test_highlight_query() {
+    assert_eq!(
+        highlight_query("GET 'key';"),
+        "\x1b[1;34mGET\x1b[0m \x1b[32m'key'\x1b[0m;"
+    );
+    assert_eq!(
+        highlight_query("getv \"open"),
+        "\x1b[1;34mgetv\x1b[0m \x1b[32m\"open\x1b[0m"
+    );
+    assert_eq!(highlight_query("unknown 'key'"), "unknown 'key'");
+}
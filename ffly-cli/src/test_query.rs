@@ -0,0 +1,91 @@
+use crate::query::{
+    describe_ttl, format_response, match_keyword, split_arguments, to_bitwise, to_query, Mode,
+};
+
+#[test]
+fn test_match_keyword() {
+    assert_eq!(match_keyword("GET key"), Some(("GET", b'1', " key")));
+    assert_eq!(
+        match_keyword("get value 'key';"),
+        Some(("GET VALUE", b'2', " 'key';"))
+    );
+    assert_eq!(match_keyword("GETV key"), Some(("GET VALUE", b'2', " key")));
+    assert_eq!(
+        match_keyword("DROP ALL value"),
+        Some(("DROP ALL", b'5', " value"))
+    );
+    assert_eq!(match_keyword("dbsize;"), Some(("DBSIZE", b'J', ";")));
+
+    // An unquoted argument never becomes part of the keyword.
+    assert_eq!(match_keyword("GET vkey"), Some(("GET", b'1', " vkey")));
+    assert_eq!(
+        match_keyword("DROP allowed"),
+        Some(("DROP", b'4', " allowed"))
+    );
+
+    // Ambiguous or unknown.
+    assert_eq!(match_keyword("CLIENT"), None);
+    assert_eq!(match_keyword("FLUSH"), None);
+    assert_eq!(match_keyword("PING"), None);
+}
+
+#[test]
+fn test_split_arguments() {
+    assert_eq!(split_arguments(" a b  c ").unwrap(), vec!["a", "b", "c"]);
+    assert_eq!(
+        split_arguments("'a b' \"c'd\";").unwrap(),
+        vec!["a b", "c'd"]
+    );
+    assert_eq!(
+        split_arguments("'key' VALUE 'value' WITH TTL '60';").unwrap(),
+        vec!["key", "value", "60"]
+    );
+    assert_eq!(
+        split_arguments("key 'value'").unwrap(),
+        vec!["key", "value"]
+    );
+    assert_eq!(split_arguments("''").unwrap(), vec![""]);
+    assert!(split_arguments("'open").is_err());
+}
+
+#[test]
+fn test_to_bitwise() {
+    assert_eq!(to_bitwise("NEW key hello").unwrap(), b"0key\x00hello\x000");
+    assert_eq!(
+        to_bitwise("NEW 'key' VALUE 'a value' WITH TTL '60'").unwrap(),
+        b"0key\x00a value\x0060"
+    );
+    assert_eq!(to_bitwise("GET TTL key").unwrap(), b"3key");
+    assert_eq!(to_bitwise("FLUSHALL").unwrap(), b"N");
+    assert_eq!(to_bitwise("nothing").unwrap_err(), "Unknown query");
+}
+
+#[test]
+fn test_to_query() {
+    assert_eq!(to_query("GET 'key'", Mode::String).unwrap(), b"GET 'key';");
+    assert_eq!(to_query(" DBSIZE; ", Mode::String).unwrap(), b"DBSIZE;");
+    assert_eq!(to_query("GET key", Mode::Bitwise).unwrap(), b"1key");
+
+    for streaming in ["MONITOR", "SYNC", "RAFT", "DUMP", "RESTORE"] {
+        assert!(to_query(streaming, Mode::String).is_err());
+    }
+}
+
+#[test]
+fn test_format_response() {
+    assert_eq!(describe_ttl("0", 100), "0 (never expires)");
+    assert_eq!(describe_ttl("50", 100), "50 (expired)");
+    assert_eq!(describe_ttl("160", 100), "160 (expires in 60s)");
+
+    assert_eq!(
+        format_response(Some(b'1'), "value\x00160", 100),
+        "value: value\nttl: 160 (expires in 60s)"
+    );
+    assert_eq!(format_response(Some(b'3'), "0", 100), "0 (never expires)");
+    assert_eq!(format_response(Some(b'A'), "a\0b", 100), "1) a\n2) b");
+    assert_eq!(format_response(Some(b'2'), "value", 100), "value");
+    assert_eq!(
+        format_response(Some(b'1'), "Error: Key not found!", 100),
+        "Error: Key not found!"
+    );
+}
@@ -1,7 +1,7 @@
 /// Create, Read and Delete records example
 use ffly_rs::FireflyStream;
 
-static FIREFLY_ADDR: &'static str = "127.0.0.1:46600";
+static FIREFLY_ADDR: &str = "127.0.0.1:46600";
 
 #[tokio::main]
 async fn main() {
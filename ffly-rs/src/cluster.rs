@@ -0,0 +1,463 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    sync::Arc,
+};
+
+use tokio::task::JoinSet;
+
+use crate::{
+    current_epoch, FireflyError, FireflyResult, FireflyStream, GenericError, OptResult,
+    StringResult,
+};
+
+/// The default amount of points each server gets on the hash ring.
+pub const DEFAULT_VIRTUAL_NODES: usize = 160;
+
+/// Hash a key to a point on the ring. (64-bit FNV-1a, followed by the
+/// MurmurHash3 finalizer to spread similar keys)
+///
+/// # Arguments
+///
+/// * `key` - The bytes to hash.
+fn hash(key: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in key {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^ (hash >> 33)
+}
+
+/// A consistent hash ring. Every node gets a number of virtual nodes (points)
+/// on the ring, and a key belongs to the first point after its hash. Adding or
+/// removing a node only moves the keys of that node, about `1 / nodes` of all
+/// keys.
+#[derive(Debug, Clone)]
+pub struct HashRing {
+    virtual_nodes: usize,
+    ring: BTreeMap<u64, String>,
+}
+
+impl HashRing {
+    /// Create an empty ring.
+    ///
+    /// # Arguments
+    ///
+    /// * `virtual_nodes` - The amount of points each node gets on the ring.
+    pub fn new(virtual_nodes: usize) -> Self {
+        Self {
+            virtual_nodes: virtual_nodes.max(1),
+            ring: BTreeMap::new(),
+        }
+    }
+
+    /// Add a node to the ring, adding it twice has no effect.
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The name of the node. (e.g. its address)
+    pub fn add(&mut self, node: &str) {
+        for index in 0..self.virtual_nodes {
+            let point = hash(format!("{node}#{index}").as_bytes());
+            self.ring.entry(point).or_insert_with(|| node.to_string());
+        }
+    }
+
+    /// Remove a node from the ring.
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The name of the node.
+    pub fn remove(&mut self, node: &str) {
+        self.ring.retain(|_, owner| owner != node);
+    }
+
+    /// Get the node a key belongs to, `None` if the ring is empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the record.
+    pub fn node_for(&self, key: &str) -> Option<&str> {
+        let point = hash(key.as_bytes());
+
+        self.ring
+            .range(point..)
+            .next()
+            .or_else(|| self.ring.iter().next())
+            .map(|(_, node)| node.as_str())
+    }
+
+    /// If the ring has no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+}
+
+/// A client for multiple Firefly servers, which splits the records between
+/// them with consistent hashing. Queries on a single key go to the server that
+/// owns the key, queries on multiple keys get split per server, and queries on
+/// all records go to every server.
+///
+/// Records don't get moved when a server is added or removed, the keys that
+/// now belong to another server are no longer found.
+pub struct FireflyCluster {
+    ring: HashRing,
+    nodes: HashMap<String, Arc<FireflyStream>>,
+
+    /// The default TTL for new records.
+    /// If this value is not zero, it will be added to the current timestamp.
+    pub default_ttl: usize,
+}
+
+impl FireflyCluster {
+    /// Connect to every server of the cluster.
+    /// Fails if a connection cannot be established.
+    ///
+    /// # Arguments
+    ///
+    /// * `addresses` - The addresses of the servers. (e.g. ["127.0.0.1:46600"])
+    pub async fn connect(addresses: &[&str]) -> FireflyResult<Self> {
+        Self::connect_with_virtual_nodes(addresses, DEFAULT_VIRTUAL_NODES).await
+    }
+
+    /// Same as `FireflyCluster::connect`, but with a custom amount of virtual
+    /// nodes. More virtual nodes spread the keys more evenly. Every client of
+    /// the cluster must use the same amount.
+    ///
+    /// # Arguments
+    ///
+    /// * `addresses` - The addresses of the servers. (e.g. ["127.0.0.1:46600"])
+    /// * `virtual_nodes` - The amount of points each server gets on the ring.
+    pub async fn connect_with_virtual_nodes(
+        addresses: &[&str],
+        virtual_nodes: usize,
+    ) -> FireflyResult<Self> {
+        let mut cluster = Self {
+            ring: HashRing::new(virtual_nodes),
+            nodes: HashMap::new(),
+            default_ttl: 0,
+        };
+
+        for address in addresses {
+            cluster.add_node(address).await?;
+        }
+
+        Ok(cluster)
+    }
+
+    /// Connect to a server and add it to the cluster.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of the server.
+    pub async fn add_node(&mut self, address: &str) -> OptResult {
+        if !self.nodes.contains_key(address) {
+            let stream = FireflyStream::connect(address).await?;
+            self.nodes.insert(address.to_string(), Arc::new(stream));
+            self.ring.add(address);
+        }
+
+        Ok(())
+    }
+
+    /// Remove a server from the cluster. Returns `false` if it wasn't part of
+    /// the cluster.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of the server.
+    pub fn remove_node(&mut self, address: &str) -> bool {
+        self.ring.remove(address);
+        self.nodes.remove(address).is_some()
+    }
+
+    /// Get the connection with the server a key belongs to.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the record.
+    pub fn node_for(&self, key: &str) -> FireflyResult<&FireflyStream> {
+        self.ring
+            .node_for(key)
+            .and_then(|address| self.nodes.get(address))
+            .map(Arc::as_ref)
+            .ok_or_else(|| FireflyError::NoNodes.into())
+    }
+
+    /// The addresses of all servers in the cluster.
+    pub fn nodes(&self) -> Vec<&str> {
+        self.nodes.keys().map(String::as_str).collect()
+    }
+
+    /// Execute a query on every server at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - Executes the query on a single server.
+    async fn on_every_node<T, F, Fut>(&self, query: F) -> FireflyResult<Vec<T>>
+    where
+        T: Send + 'static,
+        F: Fn(Arc<FireflyStream>) -> Fut,
+        Fut: Future<Output = FireflyResult<T>> + Send + 'static,
+    {
+        if self.nodes.is_empty() {
+            return Err(FireflyError::NoNodes.into());
+        }
+
+        let mut tasks = JoinSet::new();
+        for node in self.nodes.values() {
+            tasks.spawn(query(node.clone()));
+        }
+
+        let mut results = Vec::with_capacity(self.nodes.len());
+        while let Some(result) = tasks.join_next().await {
+            results.push(result??);
+        }
+
+        Ok(results)
+    }
+
+    /// Execute a query for multiple keys, the keys of each server get
+    /// queried at once. The results are in the same order as the keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - The keys of the records.
+    /// * `query` - Executes the query for a single key.
+    async fn per_key<T, F, Fut>(&self, keys: &[&str], query: F) -> FireflyResult<Vec<T>>
+    where
+        T: Send + 'static,
+        F: Fn(Arc<FireflyStream>, String) -> Fut + Send + Sync + Clone + 'static,
+        Fut: Future<Output = FireflyResult<T>> + Send + 'static,
+    {
+        let mut groups: HashMap<&str, Vec<(usize, String)>> = HashMap::new();
+        for (index, key) in keys.iter().enumerate() {
+            let node = self.ring.node_for(key).ok_or(FireflyError::NoNodes)?;
+            groups
+                .entry(node)
+                .or_default()
+                .push((index, key.to_string()));
+        }
+
+        let mut tasks = JoinSet::new();
+        for (node, keys) in groups {
+            let node = self.nodes[node].clone();
+            let query = query.clone();
+
+            tasks.spawn(async move {
+                let mut results = Vec::with_capacity(keys.len());
+                for (index, key) in keys {
+                    results.push((index, query(node.clone(), key).await?));
+                }
+                Ok::<_, GenericError>(results)
+            });
+        }
+
+        let mut results: Vec<Option<T>> = (0..keys.len()).map(|_| None).collect();
+        while let Some(result) = tasks.join_next().await {
+            for (index, result) in result?? {
+                results[index] = Some(result);
+            }
+        }
+
+        Ok(results.into_iter().flatten().collect())
+    }
+
+    /// Authenticate the connections with every server as a user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The name of the user.
+    /// * `password` - The password of the user.
+    pub async fn auth(&self, user: &str, password: &str) -> OptResult {
+        let (user, password) = (user.to_string(), password.to_string());
+        self.on_every_node(|node| {
+            let (user, password) = (user.clone(), password.clone());
+            async move { node.auth(&user, &password).await }
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Create a new record with the default TTL, on the server that owns the
+    /// key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Your unique key for the record.
+    /// * `value` - The value of the record.
+    #[allow(clippy::new_ret_no_self)]
+    pub async fn new(&self, key: &str, value: &str) -> OptResult {
+        let mut ttl = self.default_ttl;
+
+        if ttl != 0 {
+            ttl += current_epoch();
+        }
+
+        self.new_with_ttl(key, value, ttl).await
+    }
+
+    /// Same as `FireflyCluster::new`, but with a custom TTL.
+    /// The TTL is the timestamp since the UNIX epoch.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Your unique key for the record.
+    /// * `value` - The value of the record.
+    /// * `ttl` - The timestamp since the UNIX epoch for the data to expire. (0 = never)
+    pub async fn new_with_ttl(&self, key: &str, value: &str, ttl: usize) -> OptResult {
+        self.node_for(key)?.new_with_ttl(key, value, ttl).await
+    }
+
+    /// Get a record and its TTL.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the record.
+    pub async fn get(&self, key: &str) -> FireflyResult<(String, usize)> {
+        self.node_for(key)?.get(key).await
+    }
+
+    /// Get the value of a record.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the record.
+    pub async fn get_value(&self, key: &str) -> StringResult {
+        self.node_for(key)?.get_value(key).await
+    }
+
+    /// Get the TTL of a record.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the record.
+    pub async fn get_ttl(&self, key: &str) -> FireflyResult<usize> {
+        self.node_for(key)?.get_ttl(key).await
+    }
+
+    /// Remove a record.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the record.
+    pub async fn drop(&self, key: &str) -> OptResult {
+        self.node_for(key)?.drop(key).await
+    }
+
+    /// Create multiple records with the default TTL.
+    ///
+    /// # Arguments
+    ///
+    /// * `records` - The keys and values of the records.
+    pub async fn new_many(&self, records: &[(&str, &str)]) -> OptResult {
+        let mut ttl = self.default_ttl;
+
+        if ttl != 0 {
+            ttl += current_epoch();
+        }
+
+        let values: HashMap<String, String> = records
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let values = Arc::new(values);
+        let keys: Vec<&str> = records.iter().map(|(key, _)| *key).collect();
+
+        self.per_key(&keys, move |node, key| {
+            let values = values.clone();
+            async move { node.new_with_ttl(&key, &values[&key], ttl).await }
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Get the values of multiple records, in the same order as the keys.
+    /// Keys without a record result in `None`.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - The keys of the records.
+    pub async fn get_values(&self, keys: &[&str]) -> FireflyResult<Vec<Option<String>>> {
+        self.per_key(
+            keys,
+            |node, key| async move { node.try_get_value(&key).await },
+        )
+        .await
+    }
+
+    /// Remove multiple records.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - The keys of the records.
+    pub async fn drop_many(&self, keys: &[&str]) -> OptResult {
+        self.per_key(keys, |node, key| async move {
+            FireflyStream::drop(&node, &key).await
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Remove ALL records that have a certain value, on every server.
+    /// Using this method is generally discouraged. As it is a heavy operation.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value of ANY record that should be removed.
+    pub async fn drop_values(&self, value: &str) -> OptResult {
+        let value = value.to_string();
+        self.on_every_node(|node| {
+            let value = value.clone();
+            async move { node.drop_values(&value).await }
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Switch the logical database of the connections with every server.
+    ///
+    /// # Arguments
+    ///
+    /// * `database` - The name of the database.
+    pub async fn select(&self, database: &str) -> OptResult {
+        let database = database.to_string();
+        self.on_every_node(|node| {
+            let database = database.clone();
+            async move { node.select(&database).await }
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Get the amount of records in the selected database, of all servers
+    /// together.
+    pub async fn db_size(&self) -> FireflyResult<usize> {
+        let sizes = self
+            .on_every_node(|node| async move { node.db_size().await })
+            .await?;
+        Ok(sizes.into_iter().sum())
+    }
+
+    /// Remove all records of the selected database, on every server.
+    pub async fn flush_db(&self) -> OptResult {
+        self.on_every_node(|node| async move { node.flush_db().await })
+            .await?;
+        Ok(())
+    }
+
+    /// Remove all records of all databases, on every server.
+    /// When the servers have users configured, this requires the admin
+    /// permission.
+    pub async fn flush_all(&self) -> OptResult {
+        self.on_every_node(|node| async move { node.flush_all().await })
+            .await?;
+        Ok(())
+    }
+}
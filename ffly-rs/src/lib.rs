@@ -1,17 +1,40 @@
 // TODO: Write tests
 use core::fmt;
 use std::{
+    collections::HashMap,
     error::Error,
+    str::FromStr,
     sync::Arc,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+#[cfg(unix)]
+use tokio::net::UnixStream;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     net::TcpStream,
     sync::Mutex,
 };
 
+mod cluster;
+
+pub use cluster::{FireflyCluster, HashRing, DEFAULT_VIRTUAL_NODES};
+
+#[cfg(test)]
+mod test_cluster;
+
+#[cfg(test)]
+mod test_dump;
+
+#[cfg(test)]
+mod test_error;
+
+#[cfg(test)]
+mod test_info;
+
+#[cfg(test)]
+mod test_sentinel;
+
 /// Catch-all error type
 pub type GenericError = Box<dyn Error + Send + Sync + 'static>;
 
@@ -19,9 +42,13 @@ pub type FireflyResult<T> = Result<T, GenericError>;
 pub type OptResult = FireflyResult<()>;
 pub type StringResult = FireflyResult<String>;
 
+/// Any stream the Firefly protocol can be spoken over. (TCP or Unix socket)
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
 pub struct FireflyStream {
     /// The stream to the Firefly server.
-    tcp_stream: Arc<Mutex<TcpStream>>,
+    stream: Arc<Mutex<Box<dyn Connection>>>,
 
     /// The maximum length of the response. (size for response buffer)
     max_buffer_size: usize,
@@ -30,12 +57,183 @@ pub struct FireflyStream {
     /// If this value is not zero, it will be added to the current timestamp.
     /// So this is the TTL from when the new is executed.
     pub default_ttl: usize,
+
+    /// The maximum key size in bytes the server accepts. (0 = unchecked)
+    /// Longer keys get refused before they are sent to the server.
+    pub max_key_size: usize,
+
+    /// The maximum value size in bytes the server accepts. (0 = unchecked)
+    /// Longer values get refused before they are sent to the server.
+    pub max_value_size: usize,
+}
+
+/// The state of a Firefly server, as returned by `FireflyStream::info`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerInfo {
+    /// The version of the server.
+    pub version: String,
+    /// Seconds since the server started.
+    pub uptime: u64,
+    /// The amount of records.
+    pub keys: usize,
+    /// The amount of records that have a TTL.
+    pub keys_with_ttl: usize,
+    /// An estimate of the memory the records use, in bytes.
+    pub used_memory: usize,
+    /// When the last snapshot got written. (seconds since the UNIX epoch, 0 = never)
+    pub last_save: u64,
+    /// How long it took to write the last snapshot.
+    pub last_save_duration: Duration,
+    /// The amount of changes that haven't been written to disk yet.
+    pub pending_changes: usize,
+    /// The amount of clients that are currently connected.
+    pub connected_clients: usize,
+    /// The path of the snapshot file.
+    pub out: String,
+    /// The interval (in seconds) to check for changes to write.
+    pub save_every: u64,
+    /// The interval (in seconds) to check for expired records. (0 = disabled)
+    pub clear_every: u64,
+}
+
+impl FromStr for ServerInfo {
+    type Err = GenericError;
+
+    fn from_str(response: &str) -> Result<Self, Self::Err> {
+        let fields: HashMap<&str, &str> = response
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .collect();
+
+        fn field<T: FromStr>(fields: &HashMap<&str, &str>, name: &str) -> FireflyResult<T> {
+            fields
+                .get(name)
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(|| FireflyError::UnexpectedResponseError.into())
+        }
+
+        Ok(Self {
+            version: field(&fields, "version")?,
+            uptime: field(&fields, "uptime")?,
+            keys: field(&fields, "keys")?,
+            keys_with_ttl: field(&fields, "keys_with_ttl")?,
+            used_memory: field(&fields, "used_memory")?,
+            last_save: field(&fields, "last_save")?,
+            last_save_duration: Duration::from_micros(field(&fields, "last_save_duration_us")?),
+            pending_changes: field(&fields, "pending_changes")?,
+            connected_clients: field(&fields, "connected_clients")?,
+            out: field(&fields, "out")?,
+            save_every: field(&fields, "save_every")?,
+            clear_every: field(&fields, "clear_every")?,
+        })
+    }
+}
+
+/// The size of the buffer for the response to `INFO`, which is longer than the
+/// responses to queries.
+const INFO_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The response of the server when there is no record with the key.
+const KEY_NOT_FOUND: &str = "Error: Key not found!";
+
+/// Check a response of the server, known errors get their typed error.
+///
+/// # Arguments
+///
+/// * `response` - The response of the server.
+/// * `expected` - A closure predicate that returns true if the response is valid.
+fn check_response(response: String, expected: fn(&str) -> bool) -> StringResult {
+    if let Some(error) = FireflyError::from_response(&response) {
+        return Err(error.into());
+    }
+
+    if expected(&response) {
+        return Ok(response);
+    }
+
+    Err(FireflyError::UnexpectedResponseError.into())
+}
+
+/// The size of the chunks a dump gets restored in.
+const DUMP_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Read the response to a query which switches to a streaming protocol, and
+/// fail if it isn't "Ok". Only the response gets read, not what follows it.
+///
+/// # Arguments
+///
+/// * `stream` - The stream to the Firefly server.
+async fn expect_ok(stream: &mut Box<dyn Connection>) -> OptResult {
+    let mut response = [0; 2];
+    stream.read_exact(&mut response).await?;
+    if &response == b"Ok" {
+        return Ok(());
+    }
+
+    let mut rest = vec![0; 512];
+    let size = stream.read(&mut rest).await?;
+    let response = format!(
+        "{}{}",
+        String::from_utf8_lossy(&response),
+        String::from_utf8_lossy(&rest[..size])
+    );
+
+    match FireflyError::from_response(&response) {
+        Some(error) => Err(error.into()),
+        None => Err(FireflyError::UnexpectedResponseError.into()),
+    }
 }
 
 #[derive(Debug)]
 pub enum FireflyError {
     /// The server returned a value which was not in the expected format.
     UnexpectedResponseError,
+    /// The server refused the query because the connection or user exceeded
+    /// its rate limit.
+    RateLimited,
+    /// The key is longer than the maximum key size.
+    KeyTooLong,
+    /// The value is longer than the maximum value size.
+    ValueTooLong,
+    /// The server is a replica, which refuses writes. After a failover the
+    /// current primary can be found through the sentinels.
+    ReadOnlyReplica,
+    /// None of the sentinels knew the address of the primary.
+    NoPrimaryFound,
+    /// The cluster has no servers to send the query to.
+    NoNodes,
+    /// The server is a Raft cluster node, but not the leader.
+    NotLeader,
+    /// The server couldn't replicate the write to a majority of its cluster in
+    /// time, it might or might not have been stored.
+    WriteNotCommitted,
+    /// The server couldn't commit the writes the read could see in time, e.g.
+    /// because the leader lost its majority.
+    ReadNotCommitted,
+    /// The server refused to restore a dump which is not a valid snapshot.
+    InvalidSnapshot,
+}
+
+impl FireflyError {
+    /// Get the typed error for a response of the server, if it is a known
+    /// error.
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - The response of the server.
+    fn from_response(response: &str) -> Option<Self> {
+        match response {
+            "Error: Rate limited!" => Some(Self::RateLimited),
+            "Error: Key too long!" => Some(Self::KeyTooLong),
+            "Error: Value too long!" => Some(Self::ValueTooLong),
+            "Error: Read-only replica!" => Some(Self::ReadOnlyReplica),
+            "Error: Not the leader!" => Some(Self::NotLeader),
+            "Error: Write not committed!" => Some(Self::WriteNotCommitted),
+            "Error: Read not committed!" => Some(Self::ReadNotCommitted),
+            "Error: Invalid snapshot!" => Some(Self::InvalidSnapshot),
+            _ => None,
+        }
+    }
 }
 
 impl Error for FireflyError {}
@@ -45,6 +243,34 @@ impl fmt::Display for FireflyError {
     }
 }
 
+/// Ask sentinels for the address of the current primary. The first sentinel
+/// that answers is used.
+///
+/// # Arguments
+///
+/// * `sentinels` - The addresses of the sentinels. (e.g. ["127.0.0.1:46700"])
+pub async fn discover_primary(sentinels: &[&str]) -> StringResult {
+    for sentinel in sentinels {
+        let asking = async {
+            let mut stream = TcpStream::connect(sentinel).await?;
+            stream.write_all(b"PRIMARY").await?;
+            stream.shutdown().await?;
+
+            let mut primary = String::new();
+            stream.read_to_string(&mut primary).await?;
+            Ok::<_, GenericError>(primary)
+        };
+
+        if let Ok(primary) = asking.await {
+            if !primary.is_empty() && !primary.starts_with("Error") {
+                return Ok(primary);
+            }
+        }
+    }
+
+    Err(FireflyError::NoPrimaryFound.into())
+}
+
 /// Get the amount of seconds since the UNIX epoch.
 fn current_epoch() -> usize {
     SystemTime::now()
@@ -76,11 +302,66 @@ impl FireflyStream {
         address: &str,
         max_buffer_size: usize,
     ) -> FireflyResult<Self> {
-        let tcp_stream = Arc::new(Mutex::new(TcpStream::connect(address).await?));
+        let stream = TcpStream::connect(address).await?;
+        Self::from_connection(Box::new(stream), max_buffer_size).await
+    }
+
+    /// Connect to the current primary, of which the address is discovered
+    /// through the sentinels. Reconnect this way when the primary goes down,
+    /// or refuses writes because it got replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `sentinels` - The addresses of the sentinels. (e.g. ["127.0.0.1:46700"])
+    pub async fn connect_via_sentinels(sentinels: &[&str]) -> FireflyResult<Self> {
+        let primary = discover_primary(sentinels).await?;
+        Self::connect(&primary).await
+    }
+
+    /// Instantiate a new connection with a Firefly server over a Unix socket.
+    /// Fails if the connection cannot be established. The expected buffer
+    /// size is set to 512.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the Firefly server its socket. (e.g. "/run/ffly.sock")
+    #[cfg(unix)]
+    pub async fn connect_unix(path: &str) -> FireflyResult<Self> {
+        Self::connect_unix_with_max_buffer(path, 512).await
+    }
+
+    /// Same as `FireflyStream::connect_unix`, but with a custom buffer size.
+    /// The buffer size is the maximum expected response size.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the Firefly server its socket. (e.g. "/run/ffly.sock")
+    /// * `max_buffer_size` - The maximum expected response size.
+    #[cfg(unix)]
+    pub async fn connect_unix_with_max_buffer(
+        path: &str,
+        max_buffer_size: usize,
+    ) -> FireflyResult<Self> {
+        let stream = UnixStream::connect(path).await?;
+        Self::from_connection(Box::new(stream), max_buffer_size).await
+    }
+
+    /// Wrap an established connection and switch it to bitwise queries.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - The connected stream to the Firefly server.
+    /// * `max_buffer_size` - The maximum expected response size.
+    async fn from_connection(
+        connection: Box<dyn Connection>,
+        max_buffer_size: usize,
+    ) -> FireflyResult<Self> {
         let client = Self {
             max_buffer_size,
-            tcp_stream,
+            stream: Arc::new(Mutex::new(connection)),
             default_ttl: 0,
+            max_key_size: 0,
+            max_value_size: 0,
         };
 
         client.send_ok("QUERY TYPE BITWISE;".as_bytes()).await?;
@@ -94,8 +375,8 @@ impl FireflyStream {
     ///
     /// * `data` - The slice of bytes to send.
     async fn send_no_check(&self, data: &[u8]) -> StringResult {
-        let mut stream = self.tcp_stream.lock().await;
-        stream.write(data).await?;
+        let mut stream = self.stream.lock().await;
+        stream.write_all(data).await?;
 
         let mut buffer = vec![0; self.max_buffer_size];
         let response_size = stream.read(&mut buffer).await?;
@@ -112,12 +393,7 @@ impl FireflyStream {
     /// * `expected` - A closure predicate that returns true if the response is valid.
     async fn send(&self, data: &[u8], expected: fn(&str) -> bool) -> StringResult {
         let response = self.send_no_check(data).await?;
-
-        if expected(&response) {
-            return Ok(response);
-        }
-
-        Err(FireflyError::UnexpectedResponseError.into())
+        check_response(response, expected)
     }
 
     /// Same as send, but checks if the response contains "Ok" or doesn't
@@ -133,6 +409,45 @@ impl FireflyStream {
         .await
     }
 
+    /// Check if a key fits within the maximum key size.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to check.
+    fn check_key(&self, key: &str) -> OptResult {
+        if self.max_key_size != 0 && key.len() > self.max_key_size {
+            return Err(FireflyError::KeyTooLong.into());
+        }
+
+        Ok(())
+    }
+
+    /// Check if a value fits within the maximum value size.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to check.
+    fn check_value(&self, value: &str) -> OptResult {
+        if self.max_value_size != 0 && value.len() > self.max_value_size {
+            return Err(FireflyError::ValueTooLong.into());
+        }
+
+        Ok(())
+    }
+
+    /// Authenticate the connection as a user. This is only required if the
+    /// server has users configured.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The name of the user.
+    /// * `password` - The password of the user.
+    pub async fn auth(&self, user: &str, password: &str) -> OptResult {
+        self.send_ok(format!("8{user}\0{password}").as_bytes())
+            .await?;
+        Ok(())
+    }
+
     /// Create a new record with the default TTL.
     /// The default TTL is 0. (record lasts for ever)
     ///
@@ -140,6 +455,7 @@ impl FireflyStream {
     ///
     /// * `key` - Your unique key for the record.
     /// * `value` - The value of the record.
+    #[allow(clippy::new_ret_no_self)]
     pub async fn new(&self, key: &str, value: &str) -> OptResult {
         let mut ttl = self.default_ttl;
 
@@ -159,6 +475,9 @@ impl FireflyStream {
     /// * `value` - The value of the record.
     /// * `ttl` - The timestamp since the UNIX epoch for the data to expire. (0 = never)
     pub async fn new_with_ttl(&self, key: &str, value: &str, ttl: usize) -> OptResult {
+        self.check_key(key)?;
+        self.check_value(value)?;
+
         let query = format!("0{key}\0{value}\0{ttl}");
         self.send_ok(query.as_bytes()).await?;
 
@@ -172,6 +491,7 @@ impl FireflyStream {
     ///
     /// * `key` - The key of the record.
     pub async fn get(&self, key: &str) -> FireflyResult<(String, usize)> {
+        self.check_key(key)?;
         let query = format!("1{key}");
         let data = self
             .send(query.as_bytes(), |response| response.contains(0 as char))
@@ -189,15 +509,36 @@ impl FireflyStream {
     ///
     /// * `key` - The key of the record.
     pub async fn get_value(&self, key: &str) -> StringResult {
+        self.check_key(key)?;
         self.send_ok(format!("2{key}").as_bytes()).await
     }
 
+    /// Same as `FireflyStream::get_value`, but returns `None` if there is no
+    /// record with the key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the record.
+    pub async fn try_get_value(&self, key: &str) -> FireflyResult<Option<String>> {
+        self.check_key(key)?;
+        let response = self.send_no_check(format!("2{key}").as_bytes()).await?;
+        if response == KEY_NOT_FOUND {
+            return Ok(None);
+        }
+
+        check_response(response, |response| {
+            response.contains("Ok") || !response.contains("Error")
+        })
+        .map(Some)
+    }
+
     /// Same as `FireflyStream::get`, but only returns the ttl.
     ///
     /// # Arguments
     ///
     /// * `key` - The key of the record.
     pub async fn get_ttl(&self, key: &str) -> FireflyResult<usize> {
+        self.check_key(key)?;
         let ttl = self.send_ok(format!("3{key}").as_bytes()).await?;
         Ok(ttl.parse()?)
     }
@@ -208,6 +549,7 @@ impl FireflyStream {
     ///
     /// * `key` - The key of the record.
     pub async fn drop(&self, key: &str) -> OptResult {
+        self.check_key(key)?;
         self.send_ok(format!("4{key}").as_bytes()).await?;
         Ok(())
     }
@@ -219,7 +561,155 @@ impl FireflyStream {
     ///
     /// * `value` - The valy of ANY record that should be removed.
     pub async fn drop_values(&self, value: &str) -> OptResult {
+        self.check_value(value)?;
         self.send_ok(format!("5{value}").as_bytes()).await?;
         Ok(())
     }
+
+    /// Switch the logical database this connection uses. Databases are
+    /// created when records get written to them. (default database: "0")
+    ///
+    /// # Arguments
+    ///
+    /// * `database` - The name of the database.
+    pub async fn select(&self, database: &str) -> OptResult {
+        self.send_ok(format!("G{database}").as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Remove all records of the selected database.
+    pub async fn flush_db(&self) -> OptResult {
+        self.send_ok("H".as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Get the amount of records in the selected database.
+    pub async fn db_size(&self) -> FireflyResult<usize> {
+        let size = self.send_ok("J".as_bytes()).await?;
+        Ok(size.parse()?)
+    }
+
+    /// Write a snapshot of all databases to disk, and wait until it is written.
+    /// When the server has users configured, this requires the admin permission.
+    pub async fn save(&self) -> OptResult {
+        self.send_ok("K".as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Start writing a snapshot of all databases to disk in the background.
+    /// When the server has users configured, this requires the admin permission.
+    pub async fn background_save(&self) -> OptResult {
+        self.send_ok("L".as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Get when the last snapshot got written. (seconds since the UNIX epoch,
+    /// 0 = never) When the server has users configured, this requires the
+    /// admin permission.
+    pub async fn last_save(&self) -> FireflyResult<u64> {
+        let epoch = self.send_ok("M".as_bytes()).await?;
+        Ok(epoch.parse()?)
+    }
+
+    /// Remove all records of all databases.
+    /// When the server has users configured, this requires the admin permission.
+    pub async fn flush_all(&self) -> OptResult {
+        self.send_ok("N".as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Send a query as it is, and return the response without checking it.
+    /// This is meant for tools which let users type queries, the connection
+    /// starts in bitwise mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The query to send, a string or bitwise query.
+    pub async fn query(&self, query: &[u8]) -> StringResult {
+        self.send_no_check(query).await
+    }
+
+    /// Stream a consistent snapshot of all databases to a writer, e.g. a
+    /// backup file. The dump has the format of the snapshot file of the
+    /// server, so it can be used as `--out` file or restored with
+    /// `FireflyStream::restore`. Returns the amount of bytes that were written.
+    /// When the server has users configured, this requires the admin permission.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Where the dump gets written to.
+    pub async fn dump<W: AsyncWrite + Unpin>(&self, writer: &mut W) -> FireflyResult<usize> {
+        let mut stream = self.stream.lock().await;
+        stream.write_all("S".as_bytes()).await?;
+        expect_ok(&mut stream).await?;
+
+        let mut bytes = 0;
+        loop {
+            let length = stream.read_u64().await? as usize;
+            if length == 0 {
+                break;
+            }
+
+            let mut chunk = vec![0; length];
+            stream.read_exact(&mut chunk).await?;
+            writer.write_all(&chunk).await?;
+            bytes += length;
+        }
+
+        writer.flush().await?;
+        Ok(bytes)
+    }
+
+    /// Replace all databases of the server with a dump, which got created by
+    /// `FireflyStream::dump` or is a snapshot file of a server.
+    /// When the server has users configured, this requires the admin permission.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Where the dump gets read from.
+    pub async fn restore<R: AsyncRead + Unpin>(&self, reader: &mut R) -> OptResult {
+        let mut stream = self.stream.lock().await;
+        stream.write_all("T".as_bytes()).await?;
+        expect_ok(&mut stream).await?;
+
+        let mut chunk = vec![0; DUMP_CHUNK_SIZE];
+        loop {
+            let length = reader.read(&mut chunk).await?;
+            stream.write_u64(length as u64).await?;
+            if length == 0 {
+                break;
+            }
+            stream.write_all(&chunk[..length]).await?;
+        }
+
+        expect_ok(&mut stream).await
+    }
+
+    /// Get information about the state of the server.
+    /// When the server has users configured, this requires the admin permission.
+    pub async fn info(&self) -> FireflyResult<ServerInfo> {
+        let mut stream = self.stream.lock().await;
+        stream.write_all(b"9").await?;
+
+        // The response can arrive in parts, so read until all fields are there.
+        let mut buffer = vec![0; INFO_BUFFER_SIZE.max(self.max_buffer_size)];
+        let mut size = 0;
+        loop {
+            let read = stream.read(&mut buffer[size..]).await?;
+            size += read;
+
+            let response = String::from_utf8_lossy(&buffer[..size]).to_string();
+            if response.starts_with("Error") {
+                let error = FireflyError::from_response(&response)
+                    .unwrap_or(FireflyError::UnexpectedResponseError);
+                return Err(error.into());
+            }
+
+            match response.parse() {
+                Ok(info) => return Ok(info),
+                Err(e) if read == 0 || size == buffer.len() => return Err(e),
+                Err(_) => {}
+            }
+        }
+    }
 }
@@ -0,0 +1,166 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+use crate::{FireflyCluster, HashRing, DEFAULT_VIRTUAL_NODES};
+
+type Records = Arc<Mutex<HashMap<String, String>>>;
+
+/// Start a fake server which understands the bitwise queries the cluster
+/// tests use, and return its address and records.
+async fn fake_server() -> (String, Records) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let records = Records::default();
+    let server_records = records.clone();
+
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let records = server_records.clone();
+            tokio::spawn(async move {
+                let mut buf = [0; 512];
+                while let Ok(size @ 1..) = stream.read(&mut buf).await {
+                    let query = String::from_utf8(buf[..size].to_vec()).unwrap();
+                    let args: Vec<&str> = query[1..].split('\0').collect();
+
+                    let response = {
+                        let mut records = records.lock().unwrap();
+                        match query.as_bytes()[0] {
+                            b'Q' => "Ok".to_string(),
+                            b'0' => {
+                                records.insert(args[0].to_string(), args[1].to_string());
+                                "Ok".to_string()
+                            }
+                            b'2' => match records.get(args[0]) {
+                                Some(value) => value.clone(),
+                                None => "Error: Key not found!".to_string(),
+                            },
+                            b'4' => {
+                                records.remove(args[0]);
+                                "Ok".to_string()
+                            }
+                            b'5' => {
+                                records.retain(|_, value| value != args[0]);
+                                "Ok".to_string()
+                            }
+                            b'J' => records.len().to_string(),
+                            _ => "Error: Unknown query!".to_string(),
+                        }
+                    };
+
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                }
+            });
+        }
+    });
+
+    (address, records)
+}
+
+/// The node of every key.
+fn owners(ring: &HashRing, keys: &[String]) -> Vec<String> {
+    keys.iter()
+        .map(|key| ring.node_for(key).unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn test_hash_ring_moves_bounded_share() {
+    let keys: Vec<String> = (0..10_000).map(|index| format!("key-{index}")).collect();
+    let mut ring = HashRing::new(160);
+    assert!(ring.node_for("key").is_none());
+
+    for node in ["10.0.0.1:46600", "10.0.0.2:46600", "10.0.0.3:46600"] {
+        ring.add(node);
+    }
+    let before = owners(&ring, &keys);
+
+    // Every node owns a fair share of the keys.
+    for node in ["10.0.0.1:46600", "10.0.0.2:46600", "10.0.0.3:46600"] {
+        let owned = before.iter().filter(|owner| *owner == node).count();
+        assert!((2_500..4_200).contains(&owned), "{node} owns {owned} keys");
+    }
+
+    // Adding a node only moves keys to that node, about a quarter of them.
+    ring.add("10.0.0.4:46600");
+    let after = owners(&ring, &keys);
+    let moved: Vec<_> = before
+        .iter()
+        .zip(&after)
+        .filter(|(before, after)| before != after)
+        .collect();
+    assert!(moved.iter().all(|(_, after)| *after == "10.0.0.4:46600"));
+    assert!(
+        (1_500..3_500).contains(&moved.len()),
+        "{} moved",
+        moved.len()
+    );
+
+    // Removing it again moves exactly those keys back.
+    ring.remove("10.0.0.4:46600");
+    assert_eq!(owners(&ring, &keys), before);
+}
+
+#[tokio::test]
+async fn test_cluster_routing() {
+    let servers = [
+        fake_server().await,
+        fake_server().await,
+        fake_server().await,
+    ];
+    let addresses: Vec<&str> = servers
+        .iter()
+        .map(|(address, _)| address.as_str())
+        .collect();
+    let cluster = FireflyCluster::connect(&addresses).await.unwrap();
+
+    let keys: Vec<String> = (0..60).map(|index| format!("key-{index}")).collect();
+    let records: Vec<(&str, &str)> = keys
+        .iter()
+        .map(|key| {
+            (
+                key.as_str(),
+                if key.ends_with('0') { "ten" } else { "value" },
+            )
+        })
+        .collect();
+    cluster.new_many(&records).await.unwrap();
+
+    // Every key is stored on the server the ring picked, and nowhere else.
+    let mut ring = HashRing::new(DEFAULT_VIRTUAL_NODES);
+    for address in &addresses {
+        ring.add(address);
+    }
+    for key in &keys {
+        let owner = ring.node_for(key).unwrap();
+        for (address, records) in &servers {
+            let stored = records.lock().unwrap().contains_key(key);
+            assert_eq!(stored, address == owner);
+        }
+    }
+    assert!(servers
+        .iter()
+        .all(|(_, records)| !records.lock().unwrap().is_empty()));
+
+    assert_eq!(cluster.db_size().await.unwrap(), 60);
+    assert_eq!(cluster.get_value("key-1").await.unwrap(), "value");
+    assert_eq!(
+        cluster
+            .get_values(&["key-10", "missing", "key-2"])
+            .await
+            .unwrap(),
+        vec![Some("ten".to_string()), None, Some("value".to_string())]
+    );
+
+    cluster.drop_values("ten").await.unwrap();
+    assert_eq!(cluster.db_size().await.unwrap(), 54);
+
+    cluster.drop_many(&["key-1", "key-2"]).await.unwrap();
+    assert_eq!(cluster.db_size().await.unwrap(), 52);
+}
@@ -0,0 +1,54 @@
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+use crate::{FireflyError, FireflyStream};
+
+#[tokio::test]
+async fn test_dump_and_restore() {
+    let (connection, mut server) = duplex(512);
+
+    let server = tokio::spawn(async move {
+        let mut buf = [0; 19];
+        server.read_exact(&mut buf).await.unwrap();
+        server.write_all(b"Ok").await.unwrap();
+
+        // The dump, in two chunks.
+        assert_eq!(server.read_u8().await.unwrap(), b'S');
+        server.write_all(b"Ok").await.unwrap();
+        for chunk in [&b"FFLY"[..], b"DBS1", b""] {
+            server.write_u64(chunk.len() as u64).await.unwrap();
+            server.write_all(chunk).await.unwrap();
+        }
+
+        // The restore, which gets refused.
+        assert_eq!(server.read_u8().await.unwrap(), b'T');
+        server.write_all(b"Ok").await.unwrap();
+        let mut restored = Vec::new();
+        loop {
+            let length = server.read_u64().await.unwrap() as usize;
+            if length == 0 {
+                break;
+            }
+            let mut chunk = vec![0; length];
+            server.read_exact(&mut chunk).await.unwrap();
+            restored.extend(chunk);
+        }
+        server.write_all(b"Error: Invalid snapshot!").await.unwrap();
+
+        restored
+    });
+
+    let firefly = FireflyStream::from_connection(Box::new(connection), 512)
+        .await
+        .unwrap();
+
+    let mut dump = Vec::new();
+    assert_eq!(firefly.dump(&mut dump).await.unwrap(), 8);
+    assert_eq!(dump, b"FFLYDBS1");
+
+    let error = firefly.restore(&mut &dump[..]).await.unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<FireflyError>(),
+        Some(FireflyError::InvalidSnapshot)
+    ));
+    assert_eq!(server.await.unwrap(), b"FFLYDBS1");
+}
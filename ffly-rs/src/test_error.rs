@@ -0,0 +1,88 @@
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+use crate::{FireflyError, FireflyStream};
+
+#[test]
+fn test_error_from_response() {
+    assert!(matches!(
+        FireflyError::from_response("Error: Rate limited!"),
+        Some(FireflyError::RateLimited)
+    ));
+    assert!(matches!(
+        FireflyError::from_response("Error: Key too long!"),
+        Some(FireflyError::KeyTooLong)
+    ));
+    assert!(matches!(
+        FireflyError::from_response("Error: Not the leader!"),
+        Some(FireflyError::NotLeader)
+    ));
+    assert!(matches!(
+        FireflyError::from_response("Error: Read not committed!"),
+        Some(FireflyError::ReadNotCommitted)
+    ));
+    assert!(FireflyError::from_response("Ok").is_none());
+    assert!(FireflyError::from_response("Error: Permission denied!").is_none());
+    assert!(FireflyError::from_response("Error: Key not found!").is_none());
+}
+
+#[tokio::test]
+async fn test_missing_key() {
+    let (connection, mut server) = duplex(512);
+
+    // Answer every query as if the key doesn't exist.
+    tokio::spawn(async move {
+        let mut buf = [0; 19];
+        server.read_exact(&mut buf).await.unwrap();
+        server.write_all(b"Ok").await.unwrap();
+        while server.read(&mut buf).await.unwrap() != 0 {
+            server.write_all(b"Error: Key not found!").await.unwrap();
+        }
+    });
+
+    let firefly = FireflyStream::from_connection(Box::new(connection), 512)
+        .await
+        .unwrap();
+
+    // The error `get_value` always returned stays the same.
+    let error = firefly.get_value("key").await.unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<FireflyError>(),
+        Some(FireflyError::UnexpectedResponseError)
+    ));
+    assert_eq!(firefly.try_get_value("key").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_size_limits() {
+    let (connection, mut server) = duplex(512);
+
+    // Accept the switch to bitwise queries, nothing else should be sent.
+    let server = tokio::spawn(async move {
+        let mut buf = [0; 19];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"QUERY TYPE BITWISE;");
+        server.write_all(b"Ok").await.unwrap();
+        server.read(&mut buf).await.unwrap()
+    });
+
+    let mut firefly = FireflyStream::from_connection(Box::new(connection), 512)
+        .await
+        .unwrap();
+    firefly.max_key_size = 3;
+    firefly.max_value_size = 3;
+
+    let error = firefly.new("long key", "a").await.unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<FireflyError>(),
+        Some(FireflyError::KeyTooLong)
+    ));
+
+    let error = firefly.new("key", "long value").await.unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<FireflyError>(),
+        Some(FireflyError::ValueTooLong)
+    ));
+
+    drop(firefly);
+    assert_eq!(server.await.unwrap(), 0);
+}
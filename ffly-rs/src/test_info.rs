@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+use crate::{FireflyStream, ServerInfo};
+
+static INFO: &str = "version:0.0.3\nuptime:12\nkeys:3\nkeys_with_ttl:1\nused_memory:300\nlast_save:1700000000\nlast_save_duration_us:250\npending_changes:2\nconnected_clients:1\nout:data.bincode\nsave_every:1\nclear_every:10";
+
+#[test]
+fn test_info_parse() {
+    let info: ServerInfo = INFO.parse().unwrap();
+    assert_eq!(info.version, "0.0.3");
+    assert_eq!(info.keys, 3);
+    assert_eq!(info.last_save_duration, Duration::from_micros(250));
+    assert_eq!(info.out, "data.bincode");
+}
+
+#[test]
+fn test_info_parse_failure() {
+    assert!("Error: Permission denied!".parse::<ServerInfo>().is_err());
+}
+
+#[tokio::test]
+async fn test_info_query() {
+    let (connection, mut server) = duplex(512);
+    let out = format!("/var/lib/ffly/{}.bincode", "snapshot".repeat(100));
+    let response = INFO.replace("data.bincode", &out);
+
+    // The response is longer than the default buffer, and arrives in parts.
+    tokio::spawn(async move {
+        let mut buf = [0; 19];
+        server.read_exact(&mut buf).await.unwrap();
+        server.write_all(b"Ok").await.unwrap();
+
+        let mut query = [0; 1];
+        server.read_exact(&mut query).await.unwrap();
+        assert_eq!(&query, b"9");
+        for part in response.as_bytes().chunks(200) {
+            server.write_all(part).await.unwrap();
+            server.flush().await.unwrap();
+            tokio::task::yield_now().await;
+        }
+    });
+
+    let firefly = FireflyStream::from_connection(Box::new(connection), 512)
+        .await
+        .unwrap();
+    let info = firefly.info().await.unwrap();
+    assert_eq!(info.out, out);
+    assert_eq!(info.clear_every, 10);
+}
@@ -0,0 +1,43 @@
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+use crate::{discover_primary, FireflyError};
+
+/// Start a fake sentinel which answers every request with the same response.
+async fn fake_sentinel(response: &'static str) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut request = String::new();
+            stream.read_to_string(&mut request).await.unwrap();
+            assert_eq!(request, "PRIMARY");
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+
+    address
+}
+
+#[tokio::test]
+async fn test_discover_primary() {
+    let broken = fake_sentinel("Error: Unknown request!").await;
+    let sentinel = fake_sentinel("127.0.0.1:46601").await;
+
+    let primary = discover_primary(&["127.0.0.1:1", &broken, &sentinel])
+        .await
+        .unwrap();
+    assert_eq!(primary, "127.0.0.1:46601");
+}
+
+#[tokio::test]
+async fn test_discover_primary_failure() {
+    let err = discover_primary(&["127.0.0.1:1"]).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<FireflyError>(),
+        Some(FireflyError::NoPrimaryFound)
+    ));
+}
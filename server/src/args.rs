@@ -0,0 +1,291 @@
+use clap::{Parser, Subcommand};
+
+use crate::{
+    config::{Config, Persistence},
+    convert::{ExportArgs, ImportArgs},
+    eviction::EvictionPolicy,
+    inspect::InspectArgs,
+    sentinel::SentinelArgs,
+};
+
+/// Every flag can also be defined in the config file, the flag (or its
+/// environment variable) takes precedence over the file.
+#[derive(Parser, Debug, Clone)]
+#[clap(author, version, about, disable_help_flag = true)]
+pub struct Args {
+    /// Print help.
+    #[clap(long, action = clap::ArgAction::Help)]
+    pub help: Option<bool>,
+
+    /// The path to a TOML config file. Send a SIGHUP to reload it.
+    #[clap(long, env = "FFLY_CONFIG")]
+    pub config: Option<String>,
+
+    /// The host (ip) the server should bind to. [default: 127.0.0.1]
+    #[clap(short, long, env = "FFLY_HOST")]
+    pub host: Option<String>,
+
+    /// The port the server should bind to. [default: 46600]
+    #[clap(short, long, env = "FFLY_PORT")]
+    pub port: Option<u16>,
+
+    /// Also listen on a Unix socket at this path.
+    #[clap(long, env = "FFLY_SOCKET")]
+    pub socket: Option<String>,
+
+    /// The file permissions of the Unix socket. (octal) [default: 660]
+    #[clap(long, env = "FFLY_SOCKET_PERMISSIONS", value_parser = parse_permissions)]
+    pub socket_permissions: Option<u32>,
+
+    /// Don't bind to the host and port, only listen on the Unix socket.
+    #[clap(long, env = "FFLY_NO_TCP")]
+    pub no_tcp: bool,
+
+    /// The path to the database file. [default: data.bincode]
+    #[clap(short, long, env = "FFLY_OUT")]
+    pub out: Option<String>,
+
+    /// How the records get persisted. `read-only` loads the database file but
+    /// never writes to it, `disabled` keeps the records in memory only.
+    /// [default: snapshot]
+    #[clap(long, env = "FFLY_PERSISTENCE", value_enum)]
+    pub persistence: Option<Persistence>,
+
+    /// Save the database every N seconds. [default: 1]
+    #[clap(short, long, env = "FFLY_SAVE_EVERY")]
+    pub save_every: Option<u64>,
+
+    /// Check if there are expired keys every N seconds.
+    /// 0 disables this. [default: 10]
+    #[clap(short, long, env = "FFLY_CLEAR_EVERY")]
+    pub clear_every: Option<u64>,
+
+    /// Max query size in bytes. [default: 512]
+    #[clap(short, long, env = "FFLY_MAX_QUERY_SIZE")]
+    pub max_query_size: Option<usize>,
+
+    /// The maximum key size in bytes. 0 means unlimited. [default: 0]
+    #[clap(long, env = "FFLY_MAX_KEY_SIZE")]
+    pub max_key_size: Option<usize>,
+
+    /// The maximum value size in bytes. 0 means unlimited. [default: 0]
+    #[clap(long, env = "FFLY_MAX_VALUE_SIZE")]
+    pub max_value_size: Option<usize>,
+
+    /// The maximum amount of logical databases. 0 means unlimited.
+    /// [default: 256]
+    #[clap(long, env = "FFLY_MAX_DATABASES")]
+    pub max_databases: Option<usize>,
+
+    /// The maximum amount of connected clients, new clients get rejected
+    /// once it is reached. 0 means unlimited. [default: 10000]
+    #[clap(long, env = "FFLY_MAX_CLIENTS")]
+    pub max_clients: Option<usize>,
+
+    /// Close connections which haven't executed a valid query for N seconds.
+    /// 0 disables this. [default: 0]
+    #[clap(long, env = "FFLY_IDLE_TIMEOUT")]
+    pub idle_timeout: Option<u64>,
+
+    /// Close connections which haven't sent anything for N seconds.
+    /// 0 disables this. [default: 0]
+    #[clap(long, env = "FFLY_READ_TIMEOUT")]
+    pub read_timeout: Option<u64>,
+
+    /// The maximum amount of memory the records may use, in bytes.
+    /// 0 means unlimited. [default: 0]
+    #[clap(long, env = "FFLY_MAX_MEMORY")]
+    pub max_memory: Option<usize>,
+
+    /// What to do when a new record would exceed the memory limit.
+    /// [default: noeviction]
+    #[clap(long, env = "FFLY_EVICTION_POLICY", value_enum)]
+    pub eviction_policy: Option<EvictionPolicy>,
+
+    /// The allowed read queries per second per connection.
+    /// 0 means unlimited. [default: 0]
+    #[clap(long, env = "FFLY_CLIENT_READ_RATE")]
+    pub client_read_rate: Option<u64>,
+
+    /// The allowed write queries per second per connection.
+    /// 0 means unlimited. [default: 0]
+    #[clap(long, env = "FFLY_CLIENT_WRITE_RATE")]
+    pub client_write_rate: Option<u64>,
+
+    /// The allowed read queries per second per authenticated user, shared by
+    /// all its connections. 0 means unlimited. [default: 0]
+    #[clap(long, env = "FFLY_USER_READ_RATE")]
+    pub user_read_rate: Option<u64>,
+
+    /// The allowed write queries per second per authenticated user, shared by
+    /// all its connections. 0 means unlimited. [default: 0]
+    #[clap(long, env = "FFLY_USER_WRITE_RATE")]
+    pub user_write_rate: Option<u64>,
+
+    /// Wait up to N seconds for the connections to finish their queries and
+    /// for the final snapshot to be written when shutting down. [default: 10]
+    #[clap(long, env = "FFLY_SHUTDOWN_TIMEOUT")]
+    pub shutdown_timeout: Option<u64>,
+
+    /// Serve Prometheus metrics over HTTP on this address. (e.g. 127.0.0.1:9100)
+    #[clap(long, env = "FFLY_METRICS_ADDR")]
+    pub metrics_addr: Option<String>,
+
+    /// Log queries and background tasks which take longer than N
+    /// microseconds in the slow log. [default: 10000]
+    #[clap(long, env = "FFLY_SLOWLOG_THRESHOLD")]
+    pub slowlog_threshold: Option<u64>,
+
+    /// The maximum amount of entries in the slow log. 0 disables it.
+    /// [default: 128]
+    #[clap(long, env = "FFLY_SLOWLOG_MAX_LEN")]
+    pub slowlog_max_len: Option<usize>,
+
+    /// Redact the values of the queries streamed to monitors.
+    #[clap(long, env = "FFLY_MONITOR_REDACT_VALUES")]
+    pub monitor_redact_values: bool,
+
+    /// Replicate the primary at this address (host:port), the server becomes
+    /// a read-only replica.
+    #[clap(long, env = "FFLY_REPLICA_OF")]
+    pub replica_of: Option<String>,
+
+    /// The user the replica authenticates as on its primary.
+    #[clap(long, env = "FFLY_PRIMARY_USER")]
+    pub primary_user: Option<String>,
+
+    /// The password the replica authenticates with on its primary.
+    #[clap(long, env = "FFLY_PRIMARY_PASSWORD")]
+    pub primary_password: Option<String>,
+
+    /// The address (host:port) the other cluster nodes reach this node on.
+    /// [default: the host and port]
+    #[clap(long, env = "FFLY_CLUSTER_ADDR")]
+    pub cluster_addr: Option<String>,
+
+    /// The address of another cluster node, enables the Raft cluster mode.
+    /// (can be repeated, or comma separated)
+    #[clap(
+        long = "cluster-peer",
+        env = "FFLY_CLUSTER_PEERS",
+        value_delimiter = ','
+    )]
+    pub cluster_peers: Vec<String>,
+
+    /// Let cluster followers answer reads, which might be stale.
+    #[clap(long, env = "FFLY_CLUSTER_STALE_READS")]
+    pub cluster_stale_reads: bool,
+
+    /// Log level (TRACE, DEBUG, INFO, WARN, ERROR). [default: INFO]
+    #[clap(short, long)]
+    pub log_level: Option<String>,
+
+    /// If the log level got set by the `LOG_LEVEL` environment variable.
+    #[clap(skip)]
+    pub log_level_from_env: bool,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Other modes the binary can run in, instead of a server.
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Watch a primary and promote one of its replicas when it goes down.
+    Sentinel(SentinelArgs),
+    /// Convert the records of a snapshot to JSON Lines or CSV.
+    Export(ExportArgs),
+    /// Import records from JSON Lines or CSV, into a snapshot or a server.
+    Import(ImportArgs),
+    /// Print statistics about a snapshot, validate it and repair it.
+    Inspect(InspectArgs),
+}
+
+impl Args {
+    /// Override the settings of a configuration with the defined flags.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration to override.
+    pub(crate) fn apply(&self, config: &mut Config) {
+        macro_rules! apply {
+            ($($field:ident),*) => {
+                $(if let Some(value) = &self.$field {
+                    config.$field = value.clone();
+                })*
+            };
+        }
+
+        apply!(
+            host,
+            port,
+            socket_permissions,
+            out,
+            persistence,
+            save_every,
+            clear_every,
+            max_query_size,
+            max_key_size,
+            max_value_size,
+            max_databases,
+            max_clients,
+            idle_timeout,
+            read_timeout,
+            max_memory,
+            eviction_policy,
+            client_read_rate,
+            client_write_rate,
+            user_read_rate,
+            user_write_rate,
+            shutdown_timeout,
+            slowlog_threshold,
+            slowlog_max_len,
+            log_level
+        );
+
+        if self.socket.is_some() {
+            config.socket = self.socket.clone();
+        }
+
+        if self.metrics_addr.is_some() {
+            config.metrics_addr = self.metrics_addr.clone();
+        }
+
+        if self.replica_of.is_some() {
+            config.replica_of = self.replica_of.clone();
+        }
+
+        if self.primary_user.is_some() {
+            config.primary_user = self.primary_user.clone();
+        }
+
+        if self.primary_password.is_some() {
+            config.primary_password = self.primary_password.clone();
+        }
+
+        if self.cluster_addr.is_some() {
+            config.cluster_addr = self.cluster_addr.clone();
+        }
+
+        if !self.cluster_peers.is_empty() {
+            config.cluster_peers = self.cluster_peers.clone();
+        }
+
+        if self.no_tcp {
+            config.no_tcp = true;
+        }
+
+        if self.cluster_stale_reads {
+            config.cluster_stale_reads = true;
+        }
+
+        if self.monitor_redact_values {
+            config.monitor_redact_values = true;
+        }
+    }
+}
+
+/// Parse an octal file mode. (e.g. "660")
+fn parse_permissions(mode: &str) -> Result<u32, String> {
+    u32::from_str_radix(mode, 8).map_err(|_| format!("Invalid octal file mode: {}", mode))
+}
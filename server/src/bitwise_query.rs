@@ -1,5 +1,5 @@
 use anyhow::{anyhow, Result};
-use std::{string::FromUtf8Error, u8};
+use std::string::FromUtf8Error;
 
 use crate::query::{parse_query_arguments, QueryType};
 
@@ -16,7 +16,7 @@ pub(crate) fn get_query_type(query: String) -> Option<QueryType> {
     let mut first_byte: Option<u8> = None;
 
     for byte in query.bytes() {
-        if byte == '\n' as u8 || byte == ' ' as u8 {
+        if byte == b'\n' || byte == b' ' {
             continue;
         }
 
@@ -24,10 +24,10 @@ pub(crate) fn get_query_type(query: String) -> Option<QueryType> {
         break;
     }
 
-    return match first_byte {
+    match first_byte {
         Some(byte) => QueryType::from_byte(byte),
         None => None,
-    };
+    }
 }
 
 /// Retrieve the query arguments from a query string.
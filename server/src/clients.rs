@@ -0,0 +1,197 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use tokio::sync::Notify;
+
+use crate::query::QueryType;
+
+/// What the server knows about a connected client.
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub id: u64,
+    pub address: String,
+    /// The name the client gave its connection.
+    pub name: Option<String>,
+    pub connected_at: Instant,
+    pub last_active: Instant,
+    pub is_bitwise: bool,
+    pub queries: u64,
+    pub last_command: Option<QueryType>,
+    /// Notified when the client should be disconnected.
+    kill: Arc<Notify>,
+}
+
+impl ClientInfo {
+    /// Describe the client on a single line.
+    pub fn describe(&self) -> String {
+        format!(
+            "id={} addr={} name={} age={} idle={} mode={} queries={} cmd={}",
+            self.id,
+            self.address,
+            self.name.as_deref().unwrap_or_default(),
+            self.connected_at.elapsed().as_secs(),
+            self.last_active.elapsed().as_secs(),
+            if self.is_bitwise { "bitwise" } else { "string" },
+            self.queries,
+            match self.last_command {
+                Some(query_type) => query_type.to_string(),
+                None => "NULL".to_string(),
+            }
+        )
+    }
+}
+
+/// A registry of all live client connections.
+#[derive(Default)]
+pub struct ClientRegistry {
+    clients: Mutex<HashMap<u64, ClientInfo>>,
+    next_id: AtomicU64,
+}
+
+/// The registration of a connection. The client gets removed from the registry
+/// once this is dropped.
+pub struct ClientHandle {
+    pub id: u64,
+    /// The address the client is listed and killed by.
+    pub address: String,
+    kill: Arc<Notify>,
+    registry: Arc<ClientRegistry>,
+}
+
+impl ClientRegistry {
+    /// Register a new client connection. Returns `None` if the maximum
+    /// amount of clients is already connected. Unix socket clients share the
+    /// same address, so their id gets appended to it. (e.g. `unix:<path>#3`)
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - The registry to add the client to.
+    /// * `address` - The address of the client.
+    /// * `max_clients` - The maximum amount of connected clients. (0 means unlimited)
+    pub fn register(
+        registry: &Arc<ClientRegistry>,
+        address: &str,
+        max_clients: usize,
+    ) -> Option<ClientHandle> {
+        let mut clients = registry.clients.lock().unwrap();
+        if max_clients != 0 && clients.len() >= max_clients {
+            return None;
+        }
+
+        let id = registry.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let kill = Arc::new(Notify::new());
+        let now = Instant::now();
+        let address = match address.starts_with("unix:") {
+            true => format!("{}#{}", address, id),
+            false => address.to_string(),
+        };
+
+        clients.insert(
+            id,
+            ClientInfo {
+                id,
+                address: address.clone(),
+                name: None,
+                connected_at: now,
+                last_active: now,
+                is_bitwise: false,
+                queries: 0,
+                last_command: None,
+                kill: kill.clone(),
+            },
+        );
+
+        Some(ClientHandle {
+            id,
+            address,
+            kill,
+            registry: registry.clone(),
+        })
+    }
+
+    /// Register a query a client executed.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier of the client.
+    /// * `query_type` - The type of the query, `None` if it was invalid or refused.
+    /// * `is_bitwise` - If the client is (now) using bitwise queries.
+    pub fn record_query(&self, id: u64, query_type: Option<QueryType>, is_bitwise: bool) {
+        if let Some(client) = self.clients.lock().unwrap().get_mut(&id) {
+            client.queries += 1;
+            client.last_active = Instant::now();
+            client.is_bitwise = is_bitwise;
+            if query_type.is_some() {
+                client.last_command = query_type;
+            }
+        }
+    }
+
+    /// Give a client connection a name.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier of the client.
+    /// * `name` - The new name of the connection.
+    pub fn set_name(&self, id: u64, name: &str) {
+        if let Some(client) = self.clients.lock().unwrap().get_mut(&id) {
+            client.name = Some(name.to_string());
+        }
+    }
+
+    /// Disconnect all clients with a certain id or address. Returns the amount
+    /// of clients that got disconnected.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The id or address of the client(s).
+    pub fn kill(&self, target: &str) -> usize {
+        let id = target.parse::<u64>().ok();
+        let clients = self.clients.lock().unwrap();
+        let mut killed = 0;
+
+        for client in clients.values() {
+            if Some(client.id) == id || client.address == target {
+                client.kill.notify_one();
+                killed += 1;
+            }
+        }
+
+        killed
+    }
+
+    /// Get all connected clients, ordered by their id.
+    pub fn list(&self) -> Vec<ClientInfo> {
+        let mut clients: Vec<_> = self.clients.lock().unwrap().values().cloned().collect();
+        clients.sort_by_key(|client| client.id);
+        clients
+    }
+
+    /// Describe all connected clients, one client per line.
+    pub fn describe(&self) -> String {
+        self.list()
+            .iter()
+            .map(ClientInfo::describe)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl ClientHandle {
+    /// Wait until the client gets killed.
+    pub async fn killed(&self) {
+        self.kill.notified().await
+    }
+}
+
+impl Drop for ClientHandle {
+    fn drop(&mut self) {
+        self.registry.clients.lock().unwrap().remove(&self.id);
+    }
+}
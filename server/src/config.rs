@@ -0,0 +1,372 @@
+use std::{fs, str::FromStr};
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use log::LevelFilter;
+use serde::Deserialize;
+
+use crate::{eviction::EvictionPolicy, Args, Settings};
+
+/// The permissions a user can be granted.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    /// Fetch records.
+    Read,
+    /// Create and drop records.
+    Write,
+    /// Manage the server itself.
+    Admin,
+}
+
+/// How the records get persisted to the snapshot file.
+#[derive(Debug, Clone, Copy, Default, Deserialize, ValueEnum, PartialEq, Eq)]
+pub enum Persistence {
+    /// Load the snapshot at startup and write the changes back to it.
+    #[default]
+    #[serde(rename = "snapshot")]
+    #[value(name = "snapshot")]
+    Snapshot,
+    /// Load the snapshot at startup, but never write to it.
+    #[serde(rename = "read-only")]
+    #[value(name = "read-only")]
+    ReadOnly,
+    /// Keep the records in memory only, nothing gets loaded or written.
+    #[serde(rename = "disabled")]
+    #[value(name = "disabled")]
+    Disabled,
+}
+
+impl Persistence {
+    /// If the snapshot gets loaded at startup.
+    pub fn loads(&self) -> bool {
+        *self != Persistence::Disabled
+    }
+
+    /// If changes get written to the snapshot.
+    pub fn writes(&self) -> bool {
+        *self == Persistence::Snapshot
+    }
+}
+
+/// A user which can authenticate itself with the `AUTH` query.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct User {
+    pub name: String,
+    pub password: String,
+    pub permissions: Vec<Permission>,
+}
+
+/// All server settings. These can be defined in a TOML file, and get
+/// overridden by their command line flag or environment variable.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub host: String,
+    pub port: u16,
+    pub socket: Option<String>,
+    pub socket_permissions: u32,
+    pub no_tcp: bool,
+    pub out: String,
+    pub persistence: Persistence,
+    pub save_every: u64,
+    pub clear_every: u64,
+    pub max_query_size: usize,
+    /// In bytes, 0 means unlimited.
+    pub max_key_size: usize,
+    /// In bytes, 0 means unlimited.
+    pub max_value_size: usize,
+    /// 0 means unlimited.
+    pub max_databases: usize,
+    /// 0 means unlimited.
+    pub max_clients: usize,
+    /// In seconds, 0 disables it.
+    pub idle_timeout: u64,
+    /// In seconds, 0 disables it.
+    pub read_timeout: u64,
+    /// In bytes, 0 means unlimited.
+    pub max_memory: usize,
+    pub eviction_policy: EvictionPolicy,
+    /// Queries per second, 0 means unlimited.
+    pub client_read_rate: u64,
+    pub client_write_rate: u64,
+    pub user_read_rate: u64,
+    pub user_write_rate: u64,
+    pub shutdown_timeout: u64,
+    pub metrics_addr: Option<String>,
+    /// In microseconds.
+    pub slowlog_threshold: u64,
+    pub slowlog_max_len: usize,
+    pub monitor_redact_values: bool,
+    /// The address of the primary, if the server is a replica.
+    pub replica_of: Option<String>,
+    pub primary_user: Option<String>,
+    pub primary_password: Option<String>,
+    /// The address the other cluster nodes reach this node on, defaults to
+    /// the host and port.
+    pub cluster_addr: Option<String>,
+    /// The addresses of the other cluster nodes, cluster mode is enabled when
+    /// there are any.
+    pub cluster_peers: Vec<String>,
+    pub cluster_stale_reads: bool,
+    pub log_level: String,
+    /// When no users are defined, every client can execute every query.
+    pub users: Vec<User>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: 46600,
+            socket: None,
+            socket_permissions: 0o660,
+            no_tcp: false,
+            out: "data.bincode".to_string(),
+            persistence: Persistence::Snapshot,
+            save_every: 1,
+            clear_every: 10,
+            max_query_size: 512,
+            max_key_size: 0,
+            max_value_size: 0,
+            max_databases: 256,
+            max_clients: 10_000,
+            idle_timeout: 0,
+            read_timeout: 0,
+            max_memory: 0,
+            eviction_policy: EvictionPolicy::NoEviction,
+            client_read_rate: 0,
+            client_write_rate: 0,
+            user_read_rate: 0,
+            user_write_rate: 0,
+            shutdown_timeout: 10,
+            metrics_addr: None,
+            slowlog_threshold: 10_000,
+            slowlog_max_len: 128,
+            monitor_redact_values: false,
+            replica_of: None,
+            primary_user: None,
+            primary_password: None,
+            cluster_addr: None,
+            cluster_peers: Vec::new(),
+            cluster_stale_reads: false,
+            log_level: "INFO".to_string(),
+            users: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Build the configuration from the config file (if any) and apply the
+    /// command line flags and environment variables on top of it.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The parsed command line flags.
+    pub fn load(args: &Args) -> Result<Self> {
+        let mut config = match &args.config {
+            Some(path) => {
+                let content = fs::read_to_string(path)
+                    .with_context(|| format!("Could not read config file {}", path))?;
+                toml::from_str(&content)
+                    .with_context(|| format!("Could not parse config file {}", path))?
+            }
+            None => Config::default(),
+        };
+
+        args.apply(&mut config);
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Check if the settings can be combined, the server refuses to start
+    /// otherwise.
+    pub fn validate(&self) -> Result<()> {
+        self.log_level_filter()?;
+
+        if self.replica_of.is_some() && !self.cluster_peers.is_empty() {
+            return Err(anyhow!("A cluster node can't be a replica as well"));
+        }
+
+        if !self.cluster_peers.is_empty() && !self.persistence.writes() {
+            return Err(anyhow!(
+                "A cluster node requires the snapshot persistence, to store its Raft state"
+            ));
+        }
+
+        if cfg!(unix) && self.no_tcp && self.socket.is_none() {
+            return Err(anyhow!("No listener left, no_tcp requires a socket"));
+        }
+
+        Ok(())
+    }
+
+    /// Get the log level as a filter for the logger.
+    pub fn log_level_filter(&self) -> Result<LevelFilter> {
+        const LOG_LEVELS: &[&str] = &["TRACE", "DEBUG", "INFO", "WARN", "ERROR"];
+
+        let level = self.log_level.to_uppercase();
+        if !LOG_LEVELS.contains(&level.as_str()) {
+            return Err(anyhow!(
+                "Invalid log level: {} (valid log levels: {:?})",
+                self.log_level,
+                LOG_LEVELS
+            ));
+        }
+
+        Ok(LevelFilter::from_str(&level)?)
+    }
+
+    /// Find a user by its credentials.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the user.
+    /// * `password` - The password of the user.
+    pub fn authenticate(&self, name: &str, password: &str) -> Option<&User> {
+        self.users
+            .iter()
+            .find(|user| user.name == name && user.password == password)
+    }
+
+    /// Check if a client may perform an action that requires a permission.
+    /// If no users are configured everything is allowed.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The name of the user the client authenticated as.
+    /// * `permission` - The required permission.
+    pub fn is_allowed(&self, user: Option<&str>, permission: Permission) -> bool {
+        if self.users.is_empty() {
+            return true;
+        }
+
+        match user {
+            Some(name) => self
+                .users
+                .iter()
+                .any(|user| user.name == name && user.permissions.contains(&permission)),
+            None => false,
+        }
+    }
+
+    /// The names of the settings which differ from another configuration, but
+    /// can't be changed while the server is running.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The configuration to compare with.
+    fn restart_required(&self, other: &Config) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        let mut check = |name, differs| {
+            if differs {
+                changed.push(name);
+            }
+        };
+
+        check("host", self.host != other.host);
+        check("port", self.port != other.port);
+        check("socket", self.socket != other.socket);
+        check(
+            "socket_permissions",
+            self.socket_permissions != other.socket_permissions,
+        );
+        check("no_tcp", self.no_tcp != other.no_tcp);
+        check("out", self.out != other.out);
+        check("persistence", self.persistence != other.persistence);
+        check(
+            "max_query_size",
+            self.max_query_size != other.max_query_size,
+        );
+        check(
+            "shutdown_timeout",
+            self.shutdown_timeout != other.shutdown_timeout,
+        );
+        check("metrics_addr", self.metrics_addr != other.metrics_addr);
+        check("replica_of", self.replica_of != other.replica_of);
+        check("cluster_addr", self.cluster_addr != other.cluster_addr);
+        check("cluster_peers", self.cluster_peers != other.cluster_peers);
+
+        changed
+    }
+}
+
+/// Reload the settings which can safely be changed while the server is
+/// running. (log level, save/clear intervals, key/value size limits, client
+/// limits and timeouts, memory limit, rate limits, slow log, monitor redaction,
+/// primary credentials, stale cluster reads and users)
+///
+/// # Arguments
+///
+/// * `settings` - The running server its settings.
+/// * `args` - The command line flags, these still take precedence.
+pub fn reload(settings: &Settings, args: &Args) -> Result<()> {
+    let new = Config::load(args)?;
+    let mut current = settings.write().unwrap();
+
+    for name in current.restart_required(&new) {
+        warn!("Changing '{}' requires a restart, ignoring it", name);
+    }
+
+    if current.log_level != new.log_level {
+        if args.log_level_from_env {
+            warn!("The log level is set by the LOG_LEVEL environment variable, ignoring it");
+        } else {
+            log::set_max_level(new.log_level_filter()?);
+            current.log_level = new.log_level;
+        }
+    }
+
+    current.save_every = new.save_every;
+    current.clear_every = new.clear_every;
+    current.max_key_size = new.max_key_size;
+    current.max_value_size = new.max_value_size;
+    current.max_databases = new.max_databases;
+    current.max_clients = new.max_clients;
+    current.idle_timeout = new.idle_timeout;
+    current.read_timeout = new.read_timeout;
+    current.max_memory = new.max_memory;
+    current.eviction_policy = new.eviction_policy;
+    current.client_read_rate = new.client_read_rate;
+    current.client_write_rate = new.client_write_rate;
+    current.user_read_rate = new.user_read_rate;
+    current.user_write_rate = new.user_write_rate;
+    current.slowlog_threshold = new.slowlog_threshold;
+    current.slowlog_max_len = new.slowlog_max_len;
+    current.monitor_redact_values = new.monitor_redact_values;
+    current.primary_user = new.primary_user;
+    current.primary_password = new.primary_password;
+    current.cluster_stale_reads = new.cluster_stale_reads;
+    current.users = new.users;
+
+    Ok(())
+}
+
+/// Reload the settings every time the process receives a SIGHUP.
+///
+/// # Arguments
+///
+/// * `settings` - The running server its settings.
+/// * `args` - The command line flags, these still take precedence.
+#[cfg(unix)]
+pub fn reload_on_hangup(settings: Settings, args: Args) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => return error!("Could not listen for SIGHUP: {}", e),
+        };
+
+        while hangup.recv().await.is_some() {
+            info!("Received SIGHUP, reloading the configuration...");
+
+            match reload(&settings, &args) {
+                Ok(_) => info!("Reloaded the configuration"),
+                Err(e) => error!("Could not reload the configuration: {:#}", e),
+            }
+        }
+    });
+}
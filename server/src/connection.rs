@@ -1,9 +1,64 @@
+use std::{io, time::Duration};
+
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
+    time::{sleep_until, Instant},
+};
+
+use crate::{
+    clients::ClientRegistry,
+    database::{process_query, save},
+    dump::{dump, restore},
+    keyspace::DEFAULT_DATABASE,
+    listener::{Listener, Socket},
+    monitor::stream_entries,
+    query::QueryType,
+    raft::{is_peer, serve_peer},
+    ratelimit::RateLimits,
+    replication::serve_replica,
+    shutdown::ShutdownListener,
+    Changed, State,
 };
 
-use crate::{database::process_query, query::QueryType, Changed, Db};
+/// The state of a client session.
+#[derive(Debug)]
+pub struct Session {
+    /// The identifier of the client in the client registry.
+    pub id: u64,
+
+    /// The address of the client.
+    pub address: String,
+
+    /// If the session is using bitwise queries.
+    pub is_bitwise: bool,
+
+    /// The user the session authenticated as.
+    pub user: Option<String>,
+
+    /// The logical database the session uses.
+    pub database: String,
+
+    /// The rate limits of the connection.
+    pub rate_limits: RateLimits,
+
+    /// The Raft index (and generation) the last write has to wait for,
+    /// before it gets acknowledged.
+    pub pending_commit: Option<(u64, u64)>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            address: String::new(),
+            is_bitwise: false,
+            user: None,
+            database: DEFAULT_DATABASE.to_string(),
+            rate_limits: RateLimits::default(),
+            pending_commit: None,
+        }
+    }
+}
 
 /// Handle if a query has changed the query type (string or bitwise) or if it
 /// has changed data.
@@ -23,7 +78,7 @@ fn check_query_impact(
     match query_type {
         Some(QueryTypeBitwise) => *is_bitwise = true,
         Some(QueryTypeString) => *is_bitwise = false,
-        Some(New | Drop | DropAll) => *changed_data = true,
+        Some(New | Drop | DropAll | FlushDb | FlushAll) => *changed_data = true,
         _ => (),
     };
 }
@@ -47,34 +102,212 @@ fn process_query_impact(query_type: Option<QueryType>, is_bitwise: &mut bool, ch
     }
 }
 
-/// Handle a TCP stream/session. This contains the client interaction logic.
+/// When the next query must have been read, based on the configured read and
+/// idle timeouts. Returns `None` if neither timeout is enabled.
 ///
 /// # Arguments
 ///
-/// * `socket` - The TCP stream/session.
-/// * `max_query_size` - The maximum expected query size, this is the default
-///                         vector allocation size.
-/// * `db` - The database.
-/// * `changed` - A mutex that is used to let the server know if the query has changed the database.
-pub fn handle_connection(mut socket: TcpStream, max_query_size: usize, db: Db, changed: Changed) {
+/// * `state` - The server state.
+/// * `last_query` - When the session last executed a valid query.
+fn read_deadline(state: &State, last_query: Instant) -> Option<Instant> {
+    let (read_timeout, idle_timeout) = {
+        let settings = state.settings.read().unwrap();
+        (settings.read_timeout, settings.idle_timeout)
+    };
+
+    let read_deadline =
+        (read_timeout != 0).then(|| Instant::now() + Duration::from_secs(read_timeout));
+    let idle_deadline = (idle_timeout != 0).then(|| last_query + Duration::from_secs(idle_timeout));
+
+    read_deadline.into_iter().chain(idle_deadline).min()
+}
+
+/// Handle a client stream/session. This contains the client interaction logic.
+///
+/// # Arguments
+///
+/// * `socket` - The TCP or Unix socket stream/session.
+/// * `address` - The address of the client.
+/// * `state` - The server state. The maximum expected query size of its
+///   settings is the default vector allocation size.
+/// * `shutdown` - The listener which tells the session to stop once its
+///   in-flight query has been answered.
+pub fn handle_connection<S: Socket>(
+    mut socket: S,
+    address: String,
+    state: State,
+    mut shutdown: ShutdownListener,
+) {
     tokio::spawn(async move {
+        let (max_query_size, max_clients) = {
+            let settings = state.settings.read().unwrap();
+            (settings.max_query_size, settings.max_clients)
+        };
+
+        let client = match ClientRegistry::register(&state.clients, &address, max_clients) {
+            Some(client) => client,
+            None => {
+                warn!(
+                    "Rejected {}, the maximum amount of clients is connected",
+                    address
+                );
+                state.metrics.client_rejected();
+                let _ = socket
+                    .write_all(b"Error: Max number of clients reached!")
+                    .await;
+                let _ = socket.shutdown().await;
+                return;
+            }
+        };
+
         let mut buf = vec![0; max_query_size];
-        let mut is_bitwise = false;
+        // Log lines and the monitor use the same address as the client list.
+        let mut session = Session {
+            id: client.id,
+            address: client.address.clone(),
+            ..Session::default()
+        };
+        let mut last_query = Instant::now();
+        state.metrics.client_connected();
 
         loop {
-            let incoming = match socket.read(&mut buf).await {
-                Ok(n) if n == 0 => return,
+            let deadline = read_deadline(&state, last_query);
+            let read = tokio::select! {
+                read = socket.read(&mut buf) => read,
+                _ = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    info!("Closing the connection from {}, it timed out", session.address);
+                    break;
+                }
+                _ = client.killed() => break,
+                _ = shutdown.recv() => break,
+            };
+
+            let incoming = match read {
+                Ok(0) => break,
                 Ok(n) => n,
                 Err(_) => break,
             };
 
-            let (query_type, res) = process_query(db.clone(), &buf[..incoming], is_bitwise);
+            let (mut query_type, mut res) = process_query(&state, &buf[..incoming], &mut session);
+            if query_type == Some(QueryType::Save) && res.is_empty() {
+                res = save(&state).await;
+            }
+            if query_type == Some(QueryType::Raft) && !is_peer(&state, &session.address).await {
+                warn!(
+                    "Refused cluster requests from {}, it isn't a peer",
+                    session.address
+                );
+                query_type = None;
+                res = "Error: Not a cluster peer!".to_string();
+            }
+            if let (Some(pending), Some(raft)) = (session.pending_commit.take(), &state.raft) {
+                if !raft.wait_for_commit(pending).await {
+                    res = match query_type {
+                        Some(query_type) if query_type.is_read() => "Error: Read not committed!",
+                        _ => "Error: Write not committed!",
+                    }
+                    .to_string();
+                }
+            }
+
             let response = socket.write_all(res.as_bytes()).await;
-            process_query_impact(query_type, &mut is_bitwise, changed.clone());
+            process_query_impact(query_type, &mut session.is_bitwise, state.changed.clone());
+            state
+                .clients
+                .record_query(session.id, query_type, session.is_bitwise);
 
-            if let Err(_) = response {
+            if response.is_err() {
+                break;
+            }
+
+            if query_type.is_some() {
+                last_query = Instant::now();
+            }
+
+            if query_type == Some(QueryType::Dump) {
+                if let Err(e) = dump(&mut socket, &state).await {
+                    warn!("Could not send the dump to {}: {}", session.address, e);
+                    break;
+                }
+            }
+
+            if query_type == Some(QueryType::Restore) {
+                let res = match restore(&mut socket, &state).await {
+                    Ok(res) => res,
+                    Err(e) => {
+                        warn!("Could not receive the dump of {}: {}", session.address, e);
+                        break;
+                    }
+                };
+
+                if socket.write_all(res.as_bytes()).await.is_err() {
+                    break;
+                }
+            }
+
+            if query_type == Some(QueryType::Monitor) {
+                tokio::select! {
+                    _ = stream_entries(&mut socket, &state, &mut shutdown) => {},
+                    _ = client.killed() => {},
+                }
+                break;
+            }
+
+            if query_type == Some(QueryType::Raft) && state.raft.is_some() {
+                tokio::select! {
+                    result = serve_peer(&mut socket, &state, &mut shutdown) => if let Err(e) = result {
+                        debug!("Lost the connection with node {}: {}", session.address, e);
+                    },
+                    _ = client.killed() => {},
+                }
+                break;
+            }
+
+            if query_type == Some(QueryType::Sync) {
+                info!("Replica {} started syncing", session.address);
+                tokio::select! {
+                    result = serve_replica(&mut socket, &state, &mut shutdown) => match result {
+                        Ok(_) => info!("Replica {} disconnected", session.address),
+                        Err(e) => warn!("Stopped replicating to {}: {}", session.address, e),
+                    },
+                    _ = client.killed() => {},
+                }
                 break;
             }
         }
+
+        state.metrics.client_disconnected();
     });
 }
+
+/// Accept new clients on a listener and hand every one of them over to
+/// `handle_connection`, until the shutdown gets triggered. Returns the amount
+/// of accepted clients.
+///
+/// # Arguments
+///
+/// * `listener` - The (TCP or Unix) listener to accept clients on.
+/// * `state` - The server state.
+/// * `shutdown` - The listener which tells the server to stop accepting clients.
+pub async fn accept_connections(
+    listener: Listener,
+    state: State,
+    mut shutdown: ShutdownListener,
+) -> io::Result<usize> {
+    info!(
+        "Started listening for connections... ({})",
+        listener.describe()
+    );
+    let mut accepted = 0;
+
+    loop {
+        let (socket, addr) = tokio::select! {
+            accept = listener.accept() => accept?,
+            _ = shutdown.recv() => return Ok(accepted),
+        };
+
+        info!("New connection from {}", addr);
+        accepted += 1;
+        handle_connection(socket, addr, state.clone(), shutdown.clone());
+    }
+}
@@ -0,0 +1,344 @@
+use std::{
+    fs::{rename, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+    sync::Arc,
+    time::Instant,
+};
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use ffly_rs::FireflyStream;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinSet;
+
+use crate::keyspace::{Databases, DEFAULT_DATABASE};
+
+/// The formats records can be exported to and imported from.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One JSON object per line.
+    Jsonl,
+    /// Comma separated values, with a header.
+    Csv,
+}
+
+/// A record as it gets exported and imported.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedRecord {
+    pub key: String,
+    pub value: String,
+    /// When the record expires. (seconds since the UNIX epoch, 0 = never)
+    pub ttl: u64,
+}
+
+/// Convert the records of a snapshot to JSON Lines or CSV.
+#[derive(clap::Args, Debug, Clone)]
+pub struct ExportArgs {
+    /// Print help.
+    #[clap(long, action = clap::ArgAction::Help)]
+    pub help: Option<bool>,
+
+    /// The snapshot file to export.
+    #[clap(long, default_value = "./data.bincode")]
+    pub snapshot: String,
+
+    /// The database to export.
+    #[clap(long, default_value = DEFAULT_DATABASE)]
+    pub database: String,
+
+    /// The format to export to.
+    #[clap(long, value_enum, default_value_t = Format::Jsonl)]
+    pub format: Format,
+
+    /// The file to write to. [default: stdout]
+    #[clap(long)]
+    pub output: Option<String>,
+}
+
+/// Import records from JSON Lines or CSV, into a snapshot or a live server.
+#[derive(clap::Args, Debug, Clone)]
+#[clap(group(clap::ArgGroup::new("target").required(true).args(["snapshot", "server"])))]
+pub struct ImportArgs {
+    /// Print help.
+    #[clap(long, action = clap::ArgAction::Help)]
+    pub help: Option<bool>,
+
+    /// The file to import. [default: stdin]
+    #[clap(long)]
+    pub input: Option<String>,
+
+    /// The format of the file to import.
+    #[clap(long, value_enum, default_value_t = Format::Jsonl)]
+    pub format: Format,
+
+    /// The database to import the records into.
+    #[clap(long, default_value = DEFAULT_DATABASE)]
+    pub database: String,
+
+    /// The snapshot file to import into, existing records are kept.
+    #[clap(long)]
+    pub snapshot: Option<String>,
+
+    /// The address (host:port) of a running server to import into.
+    #[clap(long)]
+    pub server: Option<String>,
+
+    /// The user to authenticate as on the server.
+    #[clap(long, env = "FFLY_IMPORT_USER")]
+    pub user: Option<String>,
+
+    /// The password of the user.
+    #[clap(long, env = "FFLY_IMPORT_PASSWORD")]
+    pub password: Option<String>,
+
+    /// The amount of records between two progress reports. Every record is
+    /// still sent as a query of its own.
+    #[clap(long, default_value_t = 1000)]
+    pub progress_every: usize,
+
+    /// The amount of connections the records get spread over.
+    #[clap(long, default_value_t = 4)]
+    pub connections: usize,
+}
+
+/// Open a file to read from, or stdin if no file is given.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file.
+fn open_input(path: Option<&str>) -> Result<Box<dyn BufRead>> {
+    Ok(match path {
+        Some(path) => Box::new(BufReader::new(
+            File::open(path).with_context(|| format!("Could not open {}", path))?,
+        )),
+        None => Box::new(BufReader::new(io::stdin())),
+    })
+}
+
+/// Read a snapshot file.
+///
+/// # Arguments
+///
+/// * `path` - The path of the snapshot.
+fn read_snapshot(path: &str) -> Result<Databases> {
+    let mut data = Vec::new();
+    File::open(path)
+        .with_context(|| format!("Could not open {}", path))?
+        .read_to_end(&mut data)?;
+
+    if data.is_empty() {
+        return Ok(Databases::default());
+    }
+
+    Databases::from_snapshot(&data).with_context(|| format!("{} is not a valid snapshot", path))
+}
+
+/// Write records in a format.
+///
+/// # Arguments
+///
+/// * `records` - The records to write.
+/// * `format` - The format to write them in.
+/// * `writer` - Where the records get written to.
+pub fn write_records<W: Write>(
+    records: impl IntoIterator<Item = ExportedRecord>,
+    format: Format,
+    mut writer: W,
+) -> Result<usize> {
+    let mut count = 0;
+
+    match format {
+        Format::Jsonl => {
+            for record in records {
+                serde_json::to_writer(&mut writer, &record)?;
+                writer.write_all(b"\n")?;
+                count += 1;
+            }
+            writer.flush()?;
+        }
+        Format::Csv => {
+            let mut csv = csv::Writer::from_writer(writer);
+            for record in records {
+                csv.serialize(record)?;
+                count += 1;
+            }
+            csv.flush()?;
+        }
+    }
+
+    Ok(count)
+}
+
+/// Read records in a format. Returns an error naming the line of the first
+/// record which could not be read.
+///
+/// # Arguments
+///
+/// * `reader` - Where the records get read from.
+/// * `format` - The format of the records.
+pub fn read_records<R: BufRead>(reader: R, format: Format) -> Result<Vec<ExportedRecord>> {
+    match format {
+        Format::Jsonl => reader
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+            .map(|(index, line)| {
+                serde_json::from_str(&line?)
+                    .with_context(|| format!("Invalid record on line {}", index + 1))
+            })
+            .collect(),
+        Format::Csv => csv::Reader::from_reader(reader)
+            .deserialize()
+            .enumerate()
+            .map(|(index, record)| {
+                // The header is the first line.
+                record.with_context(|| format!("Invalid record on line {}", index + 2))
+            })
+            .collect(),
+    }
+}
+
+/// Export the records of a database in a snapshot.
+///
+/// # Arguments
+///
+/// * `args` - What to export, and how.
+pub fn export(args: &ExportArgs) -> Result<usize> {
+    let databases = read_snapshot(&args.snapshot)?;
+    let records = databases
+        .get(&args.database)
+        .into_iter()
+        .flat_map(|keyspace| keyspace.iter())
+        .map(|(key, record)| {
+            Ok(ExportedRecord {
+                key: key.clone(),
+                value: record.value.clone(),
+                ttl: record
+                    .ttl
+                    .parse()
+                    .with_context(|| format!("Record {} has an invalid TTL", key))?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    match &args.output {
+        Some(path) => write_records(records, args.format, BufWriter::new(File::create(path)?)),
+        None => write_records(records, args.format, io::stdout().lock()),
+    }
+}
+
+/// Add records to a database of a snapshot file. The previous file (if any)
+/// gets kept as a `.bak` file.
+///
+/// # Arguments
+///
+/// * `records` - The records to add.
+/// * `database` - The database to add them to.
+/// * `path` - The path of the snapshot.
+fn import_into_snapshot(records: Vec<ExportedRecord>, database: &str, path: &str) -> Result<()> {
+    let mut databases = match Path::new(path).exists() {
+        true => read_snapshot(path)?,
+        false => Databases::default(),
+    };
+
+    let keyspace = databases.keyspace(database);
+    for record in records {
+        keyspace.insert(record.key, record.value, record.ttl.to_string());
+    }
+
+    let data = databases.to_snapshot()?;
+    if Path::new(path).exists() {
+        rename(path, format!("{}{}", path, ".bak"))?;
+    }
+
+    let mut file = File::create(path)?;
+    file.write_all(&data)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Import records into a running server, spread over multiple connections. The
+/// progress gets reported every `progress_every` records.
+///
+/// # Arguments
+///
+/// * `records` - The records to import.
+/// * `args` - The server to import into, and how.
+async fn import_into_server(records: Vec<ExportedRecord>, args: &ImportArgs) -> Result<()> {
+    let address = args.server.as_deref().expect("the target is a server");
+    let mut connections = Vec::new();
+
+    for _ in 0..args.connections.max(1) {
+        let firefly = FireflyStream::connect(address)
+            .await
+            .map_err(|e| anyhow!("Could not connect to {}: {}", address, e))?;
+
+        if let (Some(user), Some(password)) = (&args.user, &args.password) {
+            firefly
+                .auth(user, password)
+                .await
+                .map_err(|e| anyhow!("Could not authenticate: {}", e))?;
+        }
+
+        firefly
+            .select(&args.database)
+            .await
+            .map_err(|e| anyhow!("Could not select database {}: {}", args.database, e))?;
+        connections.push(Arc::new(firefly));
+    }
+
+    let start = Instant::now();
+    let total = records.len();
+    let mut imported = 0;
+
+    for batch in records.chunks(args.progress_every.max(1)) {
+        let mut sending = JoinSet::new();
+        let share = batch.len().div_ceil(connections.len());
+
+        for (firefly, records) in connections.iter().zip(batch.chunks(share)) {
+            let (firefly, records) = (firefly.clone(), records.to_vec());
+            sending.spawn(async move {
+                for record in records {
+                    firefly
+                        .new_with_ttl(&record.key, &record.value, record.ttl as usize)
+                        .await
+                        .map_err(|e| anyhow!("Could not import {}: {}", record.key, e))?;
+                }
+                Ok::<_, anyhow::Error>(())
+            });
+        }
+
+        while let Some(result) = sending.join_next().await {
+            result??;
+        }
+
+        imported += batch.len();
+        eprintln!(
+            "Imported {}/{} records ({:.0}%) in {:.2?}",
+            imported,
+            total,
+            imported as f64 / total as f64 * 100.0,
+            start.elapsed()
+        );
+    }
+
+    Ok(())
+}
+
+/// Import records into a snapshot file or a running server.
+///
+/// # Arguments
+///
+/// * `args` - What to import, and where to.
+pub async fn import(args: &ImportArgs) -> Result<usize> {
+    let records = read_records(open_input(args.input.as_deref())?, args.format)?;
+    let count = records.len();
+
+    match &args.snapshot {
+        Some(path) => import_into_snapshot(records, &args.database, path)?,
+        None => import_into_server(records, args).await?,
+    }
+
+    Ok(count)
+}
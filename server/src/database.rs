@@ -1,13 +1,49 @@
+use anyhow::Result;
 use std::{
     fs::{rename, File},
     io::{Read, Write},
+    mem::size_of,
     path::Path,
-    sync::MutexGuard,
     time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use tokio::time::sleep;
 
-use crate::{bitwise_query, query, query::QueryType, Changed, Db, Map};
+use crate::{
+    bitwise_query,
+    config::Persistence,
+    connection::Session,
+    eviction::make_room,
+    info::server_info,
+    keyspace::{Databases, Keyspace, Record},
+    monitor::broadcast,
+    query,
+    query::QueryType,
+    raft::check_role,
+    ratelimit::check_rate_limit,
+    replication::{is_replica, replica_of, replicate, Mutation},
+    shutdown::ShutdownListener,
+    slowlog::{format_entries, log_slow},
+    Db, Settings, State,
+};
+
+/// Get the amount of seconds since the UNIX epoch.
+pub fn current_epoch() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Woah, your system time is before the UNIX EPOCH!")
+        .as_secs()
+}
+
+/// An estimate of the amount of memory a record uses.
+///
+/// # Arguments
+///
+/// * `key` - The key of the record.
+/// * `value` - The value of the record.
+/// * `ttl` - The TTL of the record.
+pub fn record_size(key: &str, value: &str, ttl: &str) -> usize {
+    size_of::<(String, (String, String))>() + key.len() + value.len() + ttl.len()
+}
 
 /// Try to get a value from the database. If the value is not found, return
 /// an error string.
@@ -17,72 +53,272 @@ use crate::{bitwise_query, query, query::QueryType, Changed, Db, Map};
 ///
 /// # Arguments
 ///
-/// * `db` - The database to get the value from.
+/// * `db` - The databases.
+/// * `database` - The name of the database to get the value from.
 /// * `key` - The key to get the value from.
 /// * `format` - A closure which should format the expected response.
-fn get_value<F>(db: MutexGuard<Map>, key: &str, format: F) -> String
+fn get_value<F>(db: &mut Databases, database: &str, key: &str, format: F) -> String
 where
-    F: Fn((String, String)) -> String,
+    F: Fn(&Record) -> String,
 {
-    match db.get(key) {
-        Some(value) => format(value.to_owned()),
+    match db.get_record(database, key) {
+        Some(record) => format(record),
         None => "Error: Key not found!".to_string(),
     }
 }
 
+/// Create or overwrite a record, the database gets created if it doesn't
+/// exist yet. Evicts records when the memory limit would be exceeded.
+///
+/// # Arguments
+///
+/// * `state` - The server state, which contains the limits.
+/// * `db` - The (locked) databases.
+/// * `database` - The name of the database to write the record to.
+/// * `arguments` - The key, value and TTL of the record.
+fn insert_record(
+    state: &State,
+    db: &mut Databases,
+    database: &str,
+    arguments: &[String],
+) -> String {
+    let (max_key_size, max_value_size, max_databases, max_memory, policy) = {
+        let settings = state.settings.read().unwrap();
+        (
+            settings.max_key_size,
+            settings.max_value_size,
+            settings.max_databases,
+            settings.max_memory,
+            settings.eviction_policy,
+        )
+    };
+
+    if max_key_size != 0 && arguments[0].len() > max_key_size {
+        return "Error: Key too long!".to_string();
+    }
+
+    if max_value_size != 0 && arguments[1].len() > max_value_size {
+        return "Error: Value too long!".to_string();
+    }
+
+    if max_databases != 0 && db.get(database).is_none() && db.count() >= max_databases {
+        return "Error: Too many databases!".to_string();
+    }
+
+    let used_memory = db.used_memory();
+    let keyspace = db.keyspace(database);
+
+    // The limit applies to all databases, but only records of the database
+    // the record gets written to are evicted.
+    let max_memory = match max_memory {
+        0 => 0,
+        max_memory => max_memory
+            .saturating_sub(used_memory - keyspace.used_memory())
+            .max(1),
+    };
+
+    let (evicted, fits) = make_room(
+        keyspace,
+        policy,
+        max_memory,
+        &arguments[0],
+        &arguments[1],
+        &arguments[2],
+    );
+    state.metrics.record_evictions(evicted.len());
+    let evicted_any = !evicted.is_empty();
+    for key in evicted {
+        replicate(state, || Mutation::Remove {
+            database: database.to_string(),
+            key,
+        });
+    }
+
+    if !fits {
+        // The query gets refused, but the evicted records are gone.
+        if evicted_any {
+            *state.changed.lock().unwrap() += 1;
+        }
+
+        if keyspace.is_empty() {
+            db.remove(database);
+        }
+        return "Error: Out of memory!".to_string();
+    }
+
+    keyspace.insert(
+        arguments[0].to_owned(),
+        arguments[1].to_owned(),
+        arguments[2].to_owned(),
+    );
+    replicate(state, || Mutation::Insert {
+        database: database.to_string(),
+        key: arguments[0].to_owned(),
+        value: arguments[1].to_owned(),
+        ttl: arguments[2].to_owned(),
+    });
+    "Ok".to_string()
+}
+
 /// Perform the proper action for the given query.
 ///
 /// # Arguments
 ///
 /// * `query_type` - The type of query to perform.
 /// * `arguments` - The arguments to the query.
-/// * `db` - The database to perform the action on.
-fn execute_query(query_type: QueryType, arguments: Vec<String>, db: &Db) -> String {
-    let mut db = db.lock().unwrap();
+/// * `state` - The server state, which contains the databases and the memory
+///   limit.
+/// * `database` - The name of the database to perform the action on.
+fn execute_query(
+    query_type: QueryType,
+    arguments: &[String],
+    state: &State,
+    database: &str,
+) -> String {
+    let mut db = state.db.lock().unwrap();
 
+    // Only writing a record creates a database, and the records only get
+    // changed (and copied when a dump shares them) if anything gets removed.
     match query_type {
-        QueryType::New => {
-            db.insert(
-                arguments[0].to_owned(),
-                (arguments[1].to_owned(), arguments[2].to_owned()),
-            );
-            return "Ok".to_string();
-        }
-        QueryType::Get => get_value(db, &arguments[0], |(value, ttl)| {
-            format!("{}\0{}", value, ttl)
+        QueryType::New => insert_record(state, &mut db, database, arguments),
+        QueryType::Get => get_value(&mut db, database, &arguments[0], |record| {
+            format!("{}\0{}", record.value, record.ttl)
+        }),
+        QueryType::GetValue => get_value(&mut db, database, &arguments[0], |record| {
+            record.value.clone()
+        }),
+        QueryType::GetTTL => get_value(&mut db, database, &arguments[0], |record| {
+            record.ttl.clone()
         }),
-        QueryType::GetValue => get_value(db, &arguments[0], |(value, _)| value),
-        QueryType::GetTTL => get_value(db, &arguments[0], |(_, ttl)| ttl),
         QueryType::Drop => {
-            db.remove(&arguments[0]);
-            return "Ok".to_string();
+            let exists = db
+                .get(database)
+                .is_some_and(|keyspace| keyspace.peek(&arguments[0]).is_some());
+
+            if let (true, Some(keyspace)) = (exists, db.get_mut(database)) {
+                keyspace.remove(&arguments[0]);
+                replicate(state, || Mutation::Remove {
+                    database: database.to_string(),
+                    key: arguments[0].to_owned(),
+                });
+            }
+            "Ok".to_string()
         }
         QueryType::DropAll => {
-            db.retain(|_, (value, _)| *value != arguments[0]);
-            return "Ok".to_string();
+            let exists = db.get(database).is_some_and(|keyspace| {
+                keyspace
+                    .iter()
+                    .any(|(_, record)| record.value == arguments[0])
+            });
+
+            if let (true, Some(keyspace)) = (exists, db.get_mut(database)) {
+                keyspace.retain(|_, record| record.value != arguments[0]);
+                replicate(state, || Mutation::RemoveValues {
+                    database: database.to_string(),
+                    value: arguments[0].to_owned(),
+                });
+            }
+            "Ok".to_string()
+        }
+        QueryType::FlushDb => {
+            if db.remove(database).is_some() {
+                replicate(state, || Mutation::FlushDb {
+                    database: database.to_string(),
+                });
+            }
+            "Ok".to_string()
         }
+        QueryType::DbSize => db.get(database).map_or(0, Keyspace::len).to_string(),
         QueryType::QueryTypeString => "Ok".to_string(),
         QueryType::QueryTypeBitwise => "Ok".to_string(),
+        QueryType::Auth
+        | QueryType::Info
+        | QueryType::SlowlogGet
+        | QueryType::SlowlogReset
+        | QueryType::Monitor
+        | QueryType::ClientList
+        | QueryType::ClientSetName
+        | QueryType::ClientKill
+        | QueryType::Select
+        | QueryType::Save
+        | QueryType::BgSave
+        | QueryType::LastSave
+        | QueryType::FlushAll
+        | QueryType::Sync
+        | QueryType::ReplicaOf
+        | QueryType::Raft
+        | QueryType::Dump
+        | QueryType::Restore => {
+            unreachable!("{:?} queries are handled by `process_query`", query_type)
+        }
+    }
+}
+
+/// Authenticate the session as a user. The session stays unauthenticated if
+/// the credentials are invalid.
+///
+/// # Arguments
+///
+/// * `arguments` - The name and password of the user.
+/// * `session` - The session to authenticate.
+/// * `settings` - The server settings, which contain the users.
+fn authenticate(arguments: &[String], session: &mut Session, settings: &Settings) -> String {
+    let settings = settings.read().unwrap();
+
+    match settings.authenticate(&arguments[0], &arguments[1]) {
+        Some(user) => {
+            session.user = Some(user.name.clone());
+            "Ok".to_string()
+        }
+        None => {
+            session.user = None;
+            "Error: Invalid credentials!".to_string()
+        }
     }
 }
 
+/// Switch the logical database a session uses.
+///
+/// # Arguments
+///
+/// * `name` - The name of the database.
+/// * `session` - The session which switches database.
+fn select_database(name: &str, session: &mut Session) -> String {
+    if name.is_empty() {
+        return "Error: Invalid database name!".to_string();
+    }
+
+    session.database = name.to_string();
+    "Ok".to_string()
+}
+
 /// Try to parse and execute a query. If something failed it will return an
 /// error describing the problem.
 ///
+/// Queries which the session is not allowed to execute are refused, and
+/// return no query type. Just like writes which got refused (e.g. because of
+/// the memory limit), as these didn't change anything.
+///
 /// # Arguments
 ///
-/// * `db` - The database to execute the query on.
+/// * `state` - The server state, which contains the database to execute the
+///   query on.
 /// * `bytes` - The received query bytes.
-/// * `is_bitwise` - If the query should be parsed as a bitwise query.
-pub fn process_query(db: Db, bytes: &[u8], is_bitwise: bool) -> (Option<QueryType>, String) {
+/// * `session` - The state of the client session that sent the query.
+pub fn process_query(
+    state: &State,
+    bytes: &[u8],
+    session: &mut Session,
+) -> (Option<QueryType>, String) {
+    let start = Instant::now();
     let message = String::from_utf8(bytes.to_vec()).unwrap_or_default();
     let mut res = String::default();
 
-    let valid_message = message != "" && message != "\n" && message.is_ascii();
+    let valid_message = !message.is_empty() && message != "\n" && message.is_ascii();
     let mut query_type = None;
 
     if valid_message {
-        let parsed = if is_bitwise {
+        let parsed = if session.is_bitwise {
             bitwise_query::parse_query(message.clone())
         } else {
             if message.contains(0 as char) {
@@ -92,9 +328,96 @@ pub fn process_query(db: Db, bytes: &[u8], is_bitwise: bool) -> (Option<QueryTyp
         };
 
         if let Ok((qt, arguments)) = parsed {
-            query_type = Some(qt);
-            let result = execute_query(qt, arguments, &db);
-            res.push_str(&result);
+            let allowed = match qt.permission() {
+                Some(permission) => state
+                    .settings
+                    .read()
+                    .unwrap()
+                    .is_allowed(session.user.as_deref(), permission),
+                None => true,
+            };
+
+            if !allowed {
+                res = "Error: Permission denied!".to_string();
+            } else if is_replica(state) && qt.is_write() {
+                res = "Error: Read-only replica!".to_string();
+            } else if let Some(error) = check_role(state, qt) {
+                res = error.to_string();
+            } else if !check_rate_limit(state, session, qt) {
+                state.metrics.record_rate_limited();
+                res = "Error: Rate limited!".to_string();
+            } else {
+                query_type = Some(qt);
+                let result = match qt {
+                    QueryType::Auth => authenticate(&arguments, session, &state.settings),
+                    QueryType::Info => server_info(state),
+                    QueryType::SlowlogGet => format_entries(&state.slowlog.entries()),
+                    QueryType::SlowlogReset => {
+                        state.slowlog.reset();
+                        "Ok".to_string()
+                    }
+                    QueryType::Monitor => "Ok".to_string(),
+                    QueryType::ClientList => state.clients.describe(),
+                    QueryType::ClientSetName => {
+                        state.clients.set_name(session.id, &arguments[0]);
+                        "Ok".to_string()
+                    }
+                    QueryType::ClientKill => match state.clients.kill(&arguments[0]) {
+                        0 => "Error: No such client!".to_string(),
+                        _ => "Ok".to_string(),
+                    },
+                    QueryType::Select => select_database(&arguments[0], session),
+                    QueryType::Save | QueryType::BgSave if !persistence(state).writes() => {
+                        match persistence(state) {
+                            Persistence::ReadOnly => {
+                                "Error: The snapshot is read-only!".to_string()
+                            }
+                            _ => "Error: Persistence is disabled!".to_string(),
+                        }
+                    }
+                    // The connection writes the snapshot (`save`), without
+                    // blocking the other connections.
+                    QueryType::Save => String::new(),
+                    QueryType::BgSave => background_save(state),
+                    QueryType::LastSave => match state.metrics.last_snapshot() {
+                        Some((epoch, _)) => epoch.to_string(),
+                        None => "0".to_string(),
+                    },
+                    QueryType::FlushAll => {
+                        let mut db = state.db.lock().unwrap();
+                        db.clear();
+                        replicate(state, || Mutation::FlushAll);
+                        "Ok".to_string()
+                    }
+                    QueryType::Sync | QueryType::Dump | QueryType::Restore => "Ok".to_string(),
+                    QueryType::ReplicaOf => replica_of(state, &arguments[0]),
+                    QueryType::Raft => match state.raft {
+                        Some(_) => "Ok".to_string(),
+                        None => "Error: Cluster mode is disabled!".to_string(),
+                    },
+                    _ => execute_query(qt, &arguments, state, &session.database),
+                };
+                if qt.is_write() && result.starts_with("Error") {
+                    query_type = None;
+                }
+
+                // Writes only get acknowledged once the cluster stored them.
+                // The leader applies writes before they are committed, so
+                // its reads wait until what they read got committed as well.
+                if let (Some(raft), Some(qt)) = (&state.raft, query_type) {
+                    if qt.is_write() || (qt.is_read() && raft.is_leader()) {
+                        session.pending_commit = Some(raft.pending());
+                    }
+                }
+
+                res.push_str(&result);
+
+                let key = arguments.first().map(String::as_str);
+                log_slow(state, qt, key, start.elapsed());
+                if query_type.is_some() {
+                    broadcast(state, &session.address, qt, &arguments);
+                }
+            }
         } else {
             res = "Could not properly parse query!".to_string();
         }
@@ -103,8 +426,9 @@ pub fn process_query(db: Db, bytes: &[u8], is_bitwise: bool) -> (Option<QueryTyp
     }
 
     trace!("{:?}", res);
+    state.metrics.record_query(query_type, start.elapsed());
 
-    return (query_type, res);
+    (query_type, res)
 }
 
 /// Try to parse and fill a database from a file path. If the file does not
@@ -124,7 +448,7 @@ pub fn load_db(db: Db, path: &str) {
         let mut data = Vec::new();
         file.read_to_end(&mut data).unwrap();
 
-        if data.len() < 1 {
+        if data.is_empty() {
             warn!("No data found in file");
         } else {
             info!(
@@ -133,59 +457,197 @@ pub fn load_db(db: Db, path: &str) {
                 start.elapsed()
             );
             start = Instant::now();
-            let map: Map = bincode::deserialize(&mut data).unwrap();
+            let databases = Databases::from_snapshot(&data).unwrap_or_else(|e| {
+                panic!(
+                    "Could not read {}: {} (`ffly inspect --snapshot {}` shows what can be read)",
+                    path, e, path
+                )
+            });
             info!(
                 "Deserialised {} items in {:.2?}, finished loading in {:.2?}",
-                map.len(),
+                databases.len(),
                 start.elapsed(),
                 start_load.elapsed()
             );
 
             let mut db = db.lock().unwrap();
-            *db = map;
+            *db = databases;
         }
     }
 }
 
+/// Write the database to a file. The previous file (if any) gets kept as a
+/// `.bak` file. Returns the amount of records and bytes that were written.
+///
+/// # Arguments
+///
+/// * `db` - The database to write.
+/// * `file_path` - The path to the file to write to.
+pub fn save_db(db: &Db, file_path: &str) -> Result<(usize, usize)> {
+    // The copy shares the records, so the writers only wait for the copy and
+    // not for the serialization.
+    let db = db.lock().unwrap().clone();
+    let records = db.len();
+    let buffer = db.to_snapshot()?;
+
+    if Path::new(file_path).exists() {
+        rename(file_path, format!("{}{}", file_path, ".bak"))?;
+    }
+
+    let mut file = File::create(file_path)?;
+    file.write_all(&buffer)?;
+    file.sync_all()?;
+
+    Ok((records, buffer.len()))
+}
+
+/// Write a snapshot of all databases to the configured file, and register it
+/// in the metrics and slow log. The caller must hold the `saving` lock.
+/// Returns the amount of records and bytes that were written.
+///
+/// # Arguments
+///
+/// * `state` - The server state, which contains the databases, the changed
+///   counter and the file path.
+fn write_snapshot(state: &State) -> Result<(usize, usize)> {
+    let file_path = state.settings.read().unwrap().out.clone();
+    let changes = std::mem::take(&mut *state.changed.lock().unwrap());
+
+    let start = Instant::now();
+    match save_db(&state.db, &file_path) {
+        Ok((records, bytes)) => {
+            state.metrics.record_snapshot(start.elapsed(), bytes);
+            log_slow(state, "Snapshot", None, start.elapsed());
+            Ok((records, bytes))
+        }
+        Err(e) => {
+            // The changes still have to be written.
+            *state.changed.lock().unwrap() += changes;
+            Err(e)
+        }
+    }
+}
+
+/// Write a snapshot on a blocking thread, so the connections sharing the
+/// worker thread don't have to wait. Only one snapshot gets written at a
+/// time. Returns the amount of records and bytes that were written.
+///
+/// # Arguments
+///
+/// * `state` - The server state, which contains the databases, the changed
+///   counter and the file path.
+pub async fn snapshot(state: &State) -> Result<(usize, usize)> {
+    let saving = state.saving.clone().lock_owned().await;
+    let state = state.clone();
+
+    tokio::task::spawn_blocking(move || {
+        let _saving = saving;
+        write_snapshot(&state)
+    })
+    .await?
+}
+
+/// Write a snapshot for a `SAVE` query, and return its response.
+///
+/// # Arguments
+///
+/// * `state` - The server state.
+pub async fn save(state: &State) -> String {
+    match snapshot(state).await {
+        Ok(_) => "Ok".to_string(),
+        Err(e) => {
+            error!("Could not write the snapshot: {}", e);
+            "Error: Could not write the snapshot!".to_string()
+        }
+    }
+}
+
+/// How the server persists its records.
+///
+/// # Arguments
+///
+/// * `state` - The server state.
+fn persistence(state: &State) -> Persistence {
+    state.settings.read().unwrap().persistence
+}
+
+/// Write a snapshot in the background. Returns an error message if a snapshot
+/// is already being written.
+///
+/// # Arguments
+///
+/// * `state` - The server state.
+fn background_save(state: &State) -> String {
+    let Ok(saving) = state.saving.clone().try_lock_owned() else {
+        return "Error: A snapshot is already being written!".to_string();
+    };
+
+    let state = state.clone();
+    tokio::task::spawn_blocking(move || {
+        let _saving = saving;
+        if let Err(e) = write_snapshot(&state) {
+            error!("Could not write the background snapshot: {}", e);
+        }
+    });
+
+    "Ok".to_string()
+}
+
 /// Check if there were any changes detected. If there were write the data to a
 /// file. If no changes were detected do nothing.
 ///
-/// This spawns a new tokio thread to check this every x seconds.
+/// This spawns a new tokio thread to check this every x seconds. Once the
+/// shutdown gets triggered a final snapshot gets written, regardless of the
+/// detected changes.
 ///
 /// # Arguments
 ///
-/// * `db` - The database to check for changes.
-/// * `changed` -  A mutex to check if there were any changes.
-/// * `file_path` - The path to the file to write to.
-/// * `interval` - The interval to check for changes.
-pub fn detect_changes(db: Db, changed: Changed, file_path: String, interval: u64) {
+/// * `state` - The server state, which contains the database to check for
+///   changes, the changed counter and the settings. (file path and interval)
+/// * `shutdown` - The listener which tells the task to write its final snapshot.
+pub fn detect_changes(state: State, mut shutdown: ShutdownListener) {
     tokio::spawn(async move {
-        // TODO: Work away the unwraps
-        let duration = Duration::from_secs(interval);
+        let settings = state.settings.clone();
+        let mut interval = settings.read().unwrap().save_every;
         info!("Check for record changes every {} seconds", interval);
 
         loop {
-            sleep(duration).await;
-            trace!("Checking if any data has been changed!");
+            let configured = settings.read().unwrap().save_every;
+            if configured != interval {
+                interval = configured;
+                info!("Check for record changes every {} seconds", interval);
+            }
+
+            tokio::select! {
+                _ = sleep(Duration::from_secs(interval)) => {},
+                _ = shutdown.recv() => break,
+            }
 
-            let mut changed = changed.lock().unwrap();
-            if *changed != 0 {
-                debug!("{} record(s) changed... writing the data!", *changed);
-                *changed = 0;
-                drop(changed);
+            trace!("Checking if any data has been changed!");
 
-                let db = db.lock().unwrap();
-                let buffer = bincode::serialize(&db.to_owned()).unwrap();
-                drop(db);
+            let changed = *state.changed.lock().unwrap();
+            if changed != 0 {
+                debug!("{} record(s) changed... writing the data!", changed);
 
-                if Path::new(&file_path).exists() {
-                    rename(&file_path, format!("{}{}", file_path, ".bak")).unwrap();
+                if let Err(e) = snapshot(&state).await {
+                    error!("Could not write the snapshot: {}", e);
                 }
-
-                let mut file = File::create(&file_path).unwrap();
-                file.write_all(&buffer).unwrap();
             }
         }
+
+        info!("Writing the final snapshot...");
+        let start = Instant::now();
+
+        match snapshot(&state).await {
+            Ok((records, bytes)) => info!(
+                "Wrote {} records ({} bytes) to {} in {:.2?}",
+                records,
+                bytes,
+                settings.read().unwrap().out,
+                start.elapsed()
+            ),
+            Err(e) => error!("Could not write the final snapshot: {}", e),
+        }
     });
 }
 
@@ -194,38 +656,94 @@ pub fn detect_changes(db: Db, changed: Changed, file_path: String, interval: u64
 ///
 /// # Arguments
 ///
-/// * `db` - The database to check for expired values.
-/// * `changed` - A mutex to check if there were any changes.
-/// * `interval` - The interval to check for expired values.
-pub fn detect_expirations(db: Db, changed: Changed, interval: u64) {
+/// * `state` - The server state, which contains the database to check for
+///   expired values, the changed counter and the settings. (the interval, 0
+///   disables the check)
+/// * `shutdown` - The listener which tells the task to stop.
+pub fn detect_expirations(state: State, mut shutdown: ShutdownListener) {
     tokio::spawn(async move {
         // TODO: Work away the unwraps
-        let duration = Duration::from_secs(interval);
-        info!("Checking for record expirations every {} seconds", interval);
+        let State {
+            db,
+            changed,
+            settings,
+            metrics,
+            ..
+        } = state.clone();
+        let mut interval = settings.read().unwrap().clear_every;
+        if interval > 0 {
+            info!("Checking for record expirations every {} seconds", interval);
+        }
 
         loop {
-            sleep(duration).await;
+            let configured = settings.read().unwrap().clear_every;
+            if configured != interval {
+                interval = configured;
+                match interval {
+                    0 => info!("Stopped checking for record expirations"),
+                    _ => info!("Checking for record expirations every {} seconds", interval),
+                }
+            }
+
+            // When disabled, check every second if it got enabled again.
+            let duration = Duration::from_secs(interval.max(1));
+            tokio::select! {
+                _ = sleep(duration) => {},
+                _ = shutdown.recv() => break,
+            }
+
+            // Replicas receive the expirations from their primary, cluster
+            // followers from their leader.
+            let is_follower = state.raft.as_ref().is_some_and(|raft| !raft.is_leader());
+            if interval == 0 || is_replica(&state) || is_follower {
+                continue;
+            }
+
             trace!("Checking if record's got expired.");
-            let mut db = db.lock().unwrap();
-            let records = db.to_owned();
+            let start = Instant::now();
+            let current_epoch = current_epoch().to_string();
+            let mut expired = 0;
 
-            let current_epoch = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Woah, your system time is before the UNIX EPOCH!")
-                .as_secs()
-                .to_string();
+            let expires = |record: &Record| record.ttl != "0" && record.ttl <= current_epoch;
+
+            // Only databases with expired records get changed, so records
+            // which a dump shares don't get copied for nothing.
+            let mut db = db.lock().unwrap();
+            let databases: Vec<String> = db
+                .iter()
+                .filter(|(_, keyspace)| keyspace.iter().any(|(_, record)| expires(record)))
+                .map(|(database, _)| database.clone())
+                .collect();
 
-            for (key, (_, ttl)) in records {
-                if ttl == "0" || ttl > current_epoch {
+            for database in databases {
+                let Some(keyspace) = db.get_mut(&database) else {
                     continue;
-                }
+                };
+
+                let mut keys = Vec::new();
+                keyspace.retain(|key, record| {
+                    if !expires(record) {
+                        return true;
+                    }
 
-                trace!("Dropping record with key {}", key);
-                db.remove(&key);
+                    trace!(
+                        "Dropping record with key {} from database {}",
+                        key,
+                        database
+                    );
+                    keys.push(key.to_string());
+                    false
+                });
 
-                let mut changed = changed.lock().unwrap();
-                *changed += 1;
+                expired += keys.len();
+                replicate(&state, || Mutation::Expire { database, keys });
             }
+            drop(db);
+
+            *changed.lock().unwrap() += expired;
+
+            metrics.record_sweep(expired);
+            log_slow(&state, "ExpirationSweep", None, start.elapsed());
         }
     });
 }
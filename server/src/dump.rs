@@ -0,0 +1,196 @@
+use std::{
+    io::{self, Write},
+    time::Instant,
+};
+
+use anyhow::{anyhow, Result};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+    task::spawn_blocking,
+};
+
+use crate::{
+    keyspace::Databases,
+    replication::{replicate, Mutation},
+    slowlog::log_slow,
+    State,
+};
+
+/// The size of the chunks a dump gets sent in.
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The largest chunk a restore accepts, anything bigger is not a dump.
+const MAX_CHUNK_SIZE: usize = 16 * CHUNK_SIZE;
+
+/// The amount of serialized chunks which wait to be sent.
+const CHUNK_BUFFER: usize = 4;
+
+/// Collects the serialized snapshot into chunks, and hands every full chunk
+/// over to the task which sends them.
+struct ChunkWriter {
+    chunk: Vec<u8>,
+    chunks: mpsc::Sender<Vec<u8>>,
+}
+
+impl ChunkWriter {
+    /// Hand the current chunk over, if it contains anything.
+    fn send(&mut self) -> io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+
+        let chunk = std::mem::replace(&mut self.chunk, Vec::with_capacity(CHUNK_SIZE));
+        self.chunks
+            .blocking_send(chunk)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the dump got cancelled"))
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.chunk.extend_from_slice(buf);
+        if self.chunk.len() >= CHUNK_SIZE {
+            self.send()?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()
+    }
+}
+
+/// Write a chunk of a dump, prefixed with its length. An empty chunk ends the
+/// dump.
+///
+/// # Arguments
+///
+/// * `socket` - The stream to write to.
+/// * `chunk` - The chunk to write.
+async fn write_chunk<S: AsyncWrite + Unpin>(socket: &mut S, chunk: &[u8]) -> Result<()> {
+    socket
+        .write_all(&(chunk.len() as u64).to_be_bytes())
+        .await?;
+    socket.write_all(chunk).await?;
+    Ok(())
+}
+
+/// Read a chunk of a dump. Returns an empty chunk at the end of the dump.
+///
+/// # Arguments
+///
+/// * `socket` - The stream to read from.
+async fn read_chunk<S: AsyncRead + Unpin>(socket: &mut S) -> Result<Vec<u8>> {
+    let mut length = [0; 8];
+    socket.read_exact(&mut length).await?;
+
+    let length = u64::from_be_bytes(length) as usize;
+    if length > MAX_CHUNK_SIZE {
+        return Err(anyhow!("a chunk of {} bytes is too large", length));
+    }
+
+    let mut chunk = vec![0; length];
+    socket.read_exact(&mut chunk).await?;
+    Ok(chunk)
+}
+
+/// Stream a point-in-time snapshot of all databases, in the format of the
+/// snapshot file, as length prefixed chunks. The dump shares the records with
+/// the server, the first write to a database while it gets sent copies the
+/// records of that database. Returns the amount of bytes that were sent.
+///
+/// # Arguments
+///
+/// * `socket` - The stream of the client.
+/// * `state` - The server state.
+pub async fn dump<S: AsyncWrite + Unpin>(socket: &mut S, state: &State) -> Result<usize> {
+    let start = Instant::now();
+    let databases = state.db.lock().unwrap().clone();
+    log_slow(state, "Dump", None, start.elapsed());
+
+    let (sender, mut chunks) = mpsc::channel(CHUNK_BUFFER);
+    let serializing = spawn_blocking(move || {
+        let mut writer = ChunkWriter {
+            chunk: Vec::with_capacity(CHUNK_SIZE),
+            chunks: sender,
+        };
+        databases.write_snapshot(&mut writer)?;
+        writer.flush()?;
+        Ok::<_, anyhow::Error>(databases.len())
+    });
+
+    let mut bytes = 0;
+    while let Some(chunk) = chunks.recv().await {
+        write_chunk(socket, &chunk).await?;
+        bytes += chunk.len();
+    }
+
+    let records = serializing.await??;
+    write_chunk(socket, &[]).await?;
+
+    info!(
+        "Dumped {} records ({} bytes) in {:.2?}",
+        records,
+        bytes,
+        start.elapsed()
+    );
+    Ok(bytes)
+}
+
+/// Receive a dump and replace all databases with it. Returns the response for
+/// the client, an error is only returned if the stream failed.
+///
+/// # Arguments
+///
+/// * `socket` - The stream of the client.
+/// * `state` - The server state.
+pub async fn restore<S: AsyncRead + Unpin>(socket: &mut S, state: &State) -> Result<String> {
+    let mut data = Vec::new();
+    loop {
+        let chunk = read_chunk(socket).await?;
+        if chunk.is_empty() {
+            break;
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    // Deserializing happens before the databases get locked.
+    let (data, restored) = spawn_blocking(move || {
+        let restored = Databases::from_snapshot(&data);
+        (data, restored)
+    })
+    .await?;
+
+    let restored = match restored {
+        Ok(restored) => restored,
+        Err(e) => {
+            warn!("Refused to restore an invalid snapshot: {}", e);
+            return Ok("Error: Invalid snapshot!".to_string());
+        }
+    };
+
+    let max_memory = state.settings.read().unwrap().max_memory;
+    if max_memory != 0 && restored.used_memory() > max_memory {
+        return Ok("Error: Out of memory!".to_string());
+    }
+
+    let records = restored.len();
+    {
+        let mut db = state.db.lock().unwrap();
+        *db = restored;
+        replicate(state, || Mutation::Restore { snapshot: data });
+    }
+    *state.changed.lock().unwrap() += 1;
+    info!("Restored {} records", records);
+
+    // Like every other write, a cluster only acknowledges it once committed.
+    if let Some(raft) = &state.raft {
+        if !raft.wait_for_commit(raft.pending()).await {
+            return Ok("Error: Write not committed!".to_string());
+        }
+    }
+
+    Ok("Ok".to_string())
+}
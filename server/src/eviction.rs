@@ -0,0 +1,138 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::{
+    database::record_size,
+    keyspace::{Keyspace, Record},
+};
+
+/// The amount of records that get sampled to pick a record to evict.
+const EVICTION_SAMPLES: usize = 5;
+
+/// What to do when a new record would exceed the memory limit.
+#[derive(Debug, Clone, Copy, Default, Deserialize, ValueEnum, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Refuse the new record.
+    #[default]
+    #[serde(rename = "noeviction")]
+    #[value(name = "noeviction")]
+    NoEviction,
+    /// Evict the least recently used records.
+    #[serde(rename = "allkeys-lru")]
+    #[value(name = "allkeys-lru")]
+    AllKeysLru,
+    /// Evict the least frequently used records.
+    #[serde(rename = "allkeys-lfu")]
+    #[value(name = "allkeys-lfu")]
+    AllKeysLfu,
+    /// Evict the records with a TTL that expire first.
+    #[serde(rename = "volatile-ttl")]
+    #[value(name = "volatile-ttl")]
+    VolatileTtl,
+    /// Evict random records.
+    #[serde(rename = "allkeys-random")]
+    #[value(name = "allkeys-random")]
+    AllKeysRandom,
+}
+
+/// Pick the record to evict, by sampling a few random records and picking the
+/// best candidate among them. (like Redis does) Returns `None` if nothing can be evicted.
+///
+/// # Arguments
+///
+/// * `keyspace` - The records to pick from.
+/// * `policy` - The eviction policy.
+fn pick_victim(keyspace: &Keyspace, policy: EvictionPolicy) -> Option<String> {
+    if keyspace.is_empty() {
+        return None;
+    }
+
+    // Small keyspaces get checked entirely.
+    let samples: Vec<_> = if keyspace.len() <= EVICTION_SAMPLES {
+        keyspace.iter().collect()
+    } else {
+        (0..EVICTION_SAMPLES)
+            .filter_map(|_| keyspace.get_index(fastrand::usize(..keyspace.len())))
+            .collect()
+    };
+    let samples = samples.into_iter();
+
+    let victim = match policy {
+        EvictionPolicy::NoEviction => None,
+        EvictionPolicy::AllKeysLru => samples.min_by_key(|(_, record)| record.last_access),
+        EvictionPolicy::AllKeysLfu => {
+            samples.min_by_key(|(_, record)| (record.hits, record.last_access))
+        }
+        // Any record is as good as the others, so pick one out of all of them.
+        EvictionPolicy::AllKeysRandom => keyspace.get_index(fastrand::usize(..keyspace.len())),
+        EvictionPolicy::VolatileTtl => {
+            let expires = |(_, record): &(&String, &Record)| {
+                record.ttl.parse::<u64>().ok().filter(|ttl| *ttl != 0)
+            };
+
+            // The sample might not contain a record with a TTL, then all
+            // records get checked.
+            samples
+                .filter(|entry| expires(entry).is_some())
+                .min_by_key(|entry| expires(entry))
+                .or_else(|| {
+                    keyspace
+                        .iter()
+                        .filter(|entry| expires(entry).is_some())
+                        .min_by_key(|entry| expires(entry))
+                })
+        }
+    };
+
+    victim.map(|(key, _)| key.clone())
+}
+
+/// Evict records until a new record fits within the memory limit. Returns the
+/// keys of the evicted records, and if the new record fits now.
+///
+/// # Arguments
+///
+/// * `keyspace` - The records to evict from.
+/// * `policy` - The eviction policy.
+/// * `max_memory` - The memory limit in bytes. (0 means unlimited)
+/// * `key` - The key of the new record.
+/// * `value` - The value of the new record.
+/// * `ttl` - The TTL of the new record.
+pub fn make_room(
+    keyspace: &mut Keyspace,
+    policy: EvictionPolicy,
+    max_memory: usize,
+    key: &str,
+    value: &str,
+    ttl: &str,
+) -> (Vec<String>, bool) {
+    if max_memory == 0 {
+        return (Vec::new(), true);
+    }
+
+    let size = record_size(key, value, ttl);
+    if size > max_memory {
+        return (Vec::new(), false);
+    }
+
+    let mut evicted = Vec::new();
+    loop {
+        // An overwritten record frees its own memory.
+        let replaced = keyspace
+            .peek(key)
+            .map(|record| record_size(key, &record.value, &record.ttl))
+            .unwrap_or_default();
+
+        if keyspace.used_memory() - replaced + size <= max_memory {
+            return (evicted, true);
+        }
+
+        match pick_victim(keyspace, policy) {
+            Some(victim) => {
+                keyspace.remove(&victim);
+                evicted.push(victim);
+            }
+            None => return (evicted, false),
+        }
+    }
+}
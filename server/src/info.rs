@@ -0,0 +1,98 @@
+use clap::ValueEnum;
+
+use crate::State;
+
+/// Describe the state of the server. Every line contains a `name:value` pair.
+///
+/// # Arguments
+///
+/// * `state` - The server state.
+pub fn server_info(state: &State) -> String {
+    let (keys, keys_with_ttl, used_memory, databases) = {
+        let db = state.db.lock().unwrap();
+        let keys_with_ttl = db
+            .iter()
+            .flat_map(|(_, keyspace)| keyspace.iter())
+            .filter(|(_, record)| record.ttl != "0")
+            .count();
+        let databases = db
+            .iter()
+            .filter(|(_, keyspace)| !keyspace.is_empty())
+            .count();
+
+        (db.len(), keys_with_ttl, db.used_memory(), databases)
+    };
+
+    let (last_save, last_save_duration) = match state.metrics.last_snapshot() {
+        Some((epoch, duration)) => (epoch, duration.as_micros()),
+        None => (0, 0),
+    };
+
+    let pending_changes = *state.changed.lock().unwrap();
+    let (cluster_role, cluster_term, cluster_leader, cluster_commit) = match &state.raft {
+        Some(raft) => {
+            let (role, term, leader) = raft.status();
+            (role.to_string(), term, leader, raft.commit_index())
+        }
+        None => ("disabled".to_string(), 0, None, 0),
+    };
+    let settings = state.settings.read().unwrap();
+
+    let info = [
+        ("version", env!("CARGO_PKG_VERSION").to_string()),
+        ("uptime", state.started.elapsed().as_secs().to_string()),
+        ("keys", keys.to_string()),
+        ("keys_with_ttl", keys_with_ttl.to_string()),
+        ("databases", databases.to_string()),
+        ("used_memory", used_memory.to_string()),
+        ("max_memory", settings.max_memory.to_string()),
+        (
+            "eviction_policy",
+            settings
+                .eviction_policy
+                .to_possible_value()
+                .map(|value| value.get_name().to_string())
+                .unwrap_or_default(),
+        ),
+        ("evicted_keys", state.metrics.evicted().to_string()),
+        ("last_save", last_save.to_string()),
+        ("last_save_duration_us", last_save_duration.to_string()),
+        ("pending_changes", pending_changes.to_string()),
+        (
+            "connected_clients",
+            state.metrics.connected_clients().to_string(),
+        ),
+        (
+            "role",
+            match settings.replica_of {
+                Some(_) => "replica".to_string(),
+                None => "primary".to_string(),
+            },
+        ),
+        ("primary", settings.replica_of.clone().unwrap_or_default()),
+        (
+            "connected_replicas",
+            state.replication.receiver_count().to_string(),
+        ),
+        ("cluster_role", cluster_role),
+        ("cluster_leader", cluster_leader.unwrap_or_default()),
+        ("cluster_term", cluster_term.to_string()),
+        ("cluster_commit_index", cluster_commit.to_string()),
+        ("out", settings.out.clone()),
+        (
+            "persistence",
+            settings
+                .persistence
+                .to_possible_value()
+                .map(|value| value.get_name().to_string())
+                .unwrap_or_default(),
+        ),
+        ("save_every", settings.save_every.to_string()),
+        ("clear_every", settings.clear_every.to_string()),
+    ];
+
+    info.iter()
+        .map(|(name, value)| format!("{}:{}", name, value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
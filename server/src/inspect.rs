@@ -0,0 +1,315 @@
+use std::{fmt, fs};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{
+    database::current_epoch,
+    keyspace::{Databases, DEFAULT_DATABASE, SNAPSHOT_HEADER},
+};
+
+/// Inspect, validate and repair a snapshot, without starting a server.
+#[derive(clap::Args, Debug, Clone)]
+pub struct InspectArgs {
+    /// Print help.
+    #[clap(long, action = clap::ArgAction::Help)]
+    pub help: Option<bool>,
+
+    /// The snapshot file to inspect.
+    #[clap(long, default_value = "./data.bincode")]
+    pub snapshot: String,
+
+    /// The amount of largest values to list.
+    #[clap(long, default_value_t = 10)]
+    pub largest: usize,
+
+    /// Write a repaired copy to this file, without the unreadable data.
+    #[clap(long)]
+    pub repair: Option<String>,
+
+    /// Leave the expired records out of the repaired copy.
+    #[clap(long, requires = "repair")]
+    pub drop_expired: bool,
+}
+
+/// The records which could be read from a snapshot.
+#[derive(Debug, Default)]
+pub struct Salvaged {
+    pub databases: Databases,
+    /// If the snapshot is from before databases existed.
+    pub legacy: bool,
+    /// The amount of bytes which could be read.
+    pub readable: usize,
+    /// Why the rest of the snapshot could not be read.
+    pub problem: Option<String>,
+}
+
+/// Reads the snapshot format value by value, so everything before a problem
+/// can still be used.
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    /// Take the next bytes of the snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `length` - The amount of bytes to take.
+    fn take(&mut self, length: u64) -> Result<&[u8]> {
+        let remaining = (self.data.len() - self.position) as u64;
+        if length > remaining {
+            return Err(anyhow!(
+                "needs {} bytes at byte {}, but only {} are left",
+                length,
+                self.position,
+                remaining
+            ));
+        }
+
+        let start = self.position;
+        self.position += length as usize;
+        Ok(&self.data[start..self.position])
+    }
+
+    /// Read a length, as bincode writes it.
+    fn length(&mut self) -> Result<u64> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_le_bytes(
+            bytes.try_into().expect("8 bytes got taken"),
+        ))
+    }
+
+    /// Read a string, prefixed with its length.
+    fn string(&mut self) -> Result<String> {
+        let start = self.position;
+        let length = self.length()?;
+        let bytes = self.take(length)?.to_vec();
+
+        String::from_utf8(bytes).map_err(|_| anyhow!("invalid UTF-8 at byte {}", start))
+    }
+
+    /// Read the records of a database, every complete record gets added even
+    /// if a later one can't be read.
+    ///
+    /// # Arguments
+    ///
+    /// * `databases` - Where the records get added to.
+    /// * `database` - The name of the database.
+    fn records(&mut self, databases: &mut Databases, database: &str) -> Result<()> {
+        let count = self.length()?;
+
+        for _ in 0..count {
+            let key = self.string()?;
+            let value = self.string()?;
+            let ttl = self.string()?;
+            databases.keyspace(database).insert(key, value, ttl);
+        }
+
+        Ok(())
+    }
+
+    /// Read all databases.
+    ///
+    /// # Arguments
+    ///
+    /// * `databases` - Where the records get added to.
+    fn databases(&mut self, databases: &mut Databases) -> Result<()> {
+        let count = self.length()?;
+
+        for _ in 0..count {
+            let name = self.string()?;
+            self.records(databases, &name)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Read as many records of a snapshot as possible.
+///
+/// # Arguments
+///
+/// * `data` - The content of the snapshot.
+pub fn salvage(data: &[u8]) -> Salvaged {
+    let mut salvaged = Salvaged::default();
+
+    let mut reader = Reader { data, position: 0 };
+    let result = match data.starts_with(SNAPSHOT_HEADER) {
+        true => {
+            reader.position = SNAPSHOT_HEADER.len();
+            reader.databases(&mut salvaged.databases)
+        }
+        false => {
+            salvaged.legacy = true;
+            reader.records(&mut salvaged.databases, DEFAULT_DATABASE)
+        }
+    };
+
+    salvaged.readable = reader.position;
+    salvaged.problem = match result {
+        Err(e) => Some(e.to_string()),
+        Ok(_) if salvaged.readable < data.len() => Some(format!(
+            "{} bytes of trailing data",
+            data.len() - salvaged.readable
+        )),
+        Ok(_) => None,
+    };
+
+    salvaged
+}
+
+/// How the TTLs of the records are distributed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TtlDistribution {
+    pub never: usize,
+    /// Expired, but not yet removed.
+    pub expired: usize,
+    pub within_hour: usize,
+    pub within_day: usize,
+    pub within_week: usize,
+    pub later: usize,
+    /// TTLs which are not a number.
+    pub invalid: usize,
+}
+
+/// Statistics about the records of a snapshot.
+#[derive(Debug, Default)]
+pub struct Statistics {
+    /// The amount of records per database.
+    pub databases: Vec<(String, usize)>,
+    pub records: usize,
+    pub ttl: TtlDistribution,
+    /// The largest values. (size, database, key)
+    pub largest: Vec<(usize, String, String)>,
+}
+
+impl Statistics {
+    /// Gather the statistics of databases.
+    ///
+    /// # Arguments
+    ///
+    /// * `databases` - The databases to gather the statistics of.
+    /// * `largest` - The amount of largest values to keep.
+    /// * `now` - The current time. (seconds since the UNIX epoch)
+    pub fn gather(databases: &Databases, largest: usize, now: u64) -> Self {
+        let mut statistics = Self::default();
+
+        for (name, keyspace) in databases.iter() {
+            statistics.databases.push((name.clone(), keyspace.len()));
+            statistics.records += keyspace.len();
+
+            for (key, record) in keyspace.iter() {
+                let ttl = &mut statistics.ttl;
+                match record.ttl.parse::<u64>() {
+                    Ok(0) => ttl.never += 1,
+                    Ok(expiry) if expiry <= now => ttl.expired += 1,
+                    Ok(expiry) if expiry - now <= 60 * 60 => ttl.within_hour += 1,
+                    Ok(expiry) if expiry - now <= 24 * 60 * 60 => ttl.within_day += 1,
+                    Ok(expiry) if expiry - now <= 7 * 24 * 60 * 60 => ttl.within_week += 1,
+                    Ok(_) => ttl.later += 1,
+                    Err(_) => ttl.invalid += 1,
+                }
+
+                statistics
+                    .largest
+                    .push((record.value.len(), name.clone(), key.clone()));
+            }
+        }
+
+        statistics.largest.sort_by(|a, b| {
+            b.0.cmp(&a.0)
+                .then_with(|| a.1.cmp(&b.1))
+                .then_with(|| a.2.cmp(&b.2))
+        });
+        statistics.largest.truncate(largest);
+        statistics
+    }
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Records: {}", self.records)?;
+        for (name, records) in &self.databases {
+            writeln!(f, "  database {}: {}", name, records)?;
+        }
+
+        writeln!(f, "TTLs:")?;
+        writeln!(f, "  never expire: {}", self.ttl.never)?;
+        writeln!(f, "  expired, but present: {}", self.ttl.expired)?;
+        writeln!(f, "  expire within an hour: {}", self.ttl.within_hour)?;
+        writeln!(f, "  expire within a day: {}", self.ttl.within_day)?;
+        writeln!(f, "  expire within a week: {}", self.ttl.within_week)?;
+        writeln!(f, "  expire later: {}", self.ttl.later)?;
+        writeln!(f, "  invalid: {}", self.ttl.invalid)?;
+
+        writeln!(f, "Largest values:")?;
+        for (size, database, key) in &self.largest {
+            writeln!(f, "  {} bytes: {} (database {})", size, key, database)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Print the statistics of a snapshot and validate it, and optionally write a
+/// repaired copy. Returns if the snapshot is valid.
+///
+/// # Arguments
+///
+/// * `args` - What to inspect, and how.
+pub fn inspect(args: &InspectArgs) -> Result<bool> {
+    let data =
+        fs::read(&args.snapshot).with_context(|| format!("Could not read {}", args.snapshot))?;
+    let mut salvaged = salvage(&data);
+
+    println!(
+        "File: {} ({} bytes, {})",
+        args.snapshot,
+        data.len(),
+        match salvaged.legacy {
+            true => "single database format",
+            false => "multiple databases format",
+        }
+    );
+
+    let now = current_epoch();
+    print!(
+        "{}",
+        Statistics::gather(&salvaged.databases, args.largest, now)
+    );
+
+    // The server reads the snapshot in one go, which is what counts.
+    let loads = Databases::from_snapshot(&data).is_ok();
+    let valid = loads && salvaged.problem.is_none();
+    match &salvaged.problem {
+        None => println!("Status: valid"),
+        Some(problem) if loads => println!("Status: loads, but has {}", problem),
+        Some(problem) => println!(
+            "Status: invalid, only the first {} bytes can be read: {}",
+            salvaged.readable, problem
+        ),
+    }
+
+    if let Some(path) = &args.repair {
+        if path == &args.snapshot {
+            return Err(anyhow!("The repaired copy must be written to another file"));
+        }
+
+        if args.drop_expired {
+            let mut dropped = 0;
+            for (_, keyspace) in salvaged.databases.iter_mut() {
+                dropped += keyspace.retain(|_, record| {
+                    !matches!(record.ttl.parse::<u64>(), Ok(expiry) if expiry != 0 && expiry <= now)
+                });
+            }
+            println!("Dropped {} expired records", dropped);
+        }
+
+        fs::write(path, salvaged.databases.to_snapshot()?)
+            .with_context(|| format!("Could not write {}", path))?;
+        println!("Wrote {} records to {}", salvaged.databases.len(), path);
+    }
+
+    Ok(valid)
+}
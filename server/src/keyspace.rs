@@ -0,0 +1,342 @@
+use std::{collections::BTreeMap, io::Write, sync::Arc};
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::database::record_size;
+
+/// A value and its TTL, with the access statistics the eviction policies use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub value: String,
+    /// When the record expires. (seconds since the UNIX epoch, "0" = never)
+    pub ttl: String,
+    /// The keyspace its clock when the record got accessed last.
+    pub last_access: u64,
+    /// How often the record got accessed.
+    pub hits: u32,
+}
+
+/// Only the value and TTL get stored, this keeps the snapshot format the same
+/// as a `HashMap<String, (String, String)>`.
+impl Serialize for Record {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (&self.value, &self.ttl).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Record {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (value, ttl) = <(String, String)>::deserialize(deserializer)?;
+
+        Ok(Self {
+            value,
+            ttl,
+            last_access: 0,
+            hits: 0,
+        })
+    }
+}
+
+/// All records of the database. This keeps track of an estimate of the memory
+/// the records use and of how they get accessed.
+#[derive(Debug, Default, Clone)]
+pub struct Keyspace {
+    records: IndexMap<String, Record>,
+    used_memory: usize,
+    /// Increments on every access, to know which record got used least recently.
+    clock: u64,
+}
+
+impl Keyspace {
+    /// The amount of records.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// If there are no records.
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// An estimate of the amount of memory the records use, in bytes.
+    pub fn used_memory(&self) -> usize {
+        self.used_memory
+    }
+
+    /// Iterate over all records.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Record)> {
+        self.records.iter()
+    }
+
+    /// Get the record at a position. (used to sample records)
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The position of the record.
+    pub fn get_index(&self, index: usize) -> Option<(&String, &Record)> {
+        self.records.get_index(index)
+    }
+
+    /// Get a record, without registering that it got accessed.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the record.
+    pub fn peek(&self, key: &str) -> Option<&Record> {
+        self.records.get(key)
+    }
+
+    /// Get a record and register that it got accessed.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the record.
+    pub fn get(&mut self, key: &str) -> Option<&Record> {
+        self.clock += 1;
+        let record = self.records.get_mut(key)?;
+        record.last_access = self.clock;
+        record.hits = record.hits.saturating_add(1);
+
+        Some(record)
+    }
+
+    /// Create or overwrite a record.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the record.
+    /// * `value` - The value of the record.
+    /// * `ttl` - When the record expires.
+    pub fn insert(&mut self, key: String, value: String, ttl: String) {
+        self.clock += 1;
+        self.used_memory += record_size(&key, &value, &ttl);
+
+        let record = Record {
+            value,
+            ttl,
+            last_access: self.clock,
+            hits: 0,
+        };
+
+        if let Some(old) = self.records.insert(key.clone(), record) {
+            self.used_memory -= record_size(&key, &old.value, &old.ttl);
+        }
+    }
+
+    /// Remove a record.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the record.
+    pub fn remove(&mut self, key: &str) -> Option<Record> {
+        let record = self.records.swap_remove(key)?;
+        self.used_memory -= record_size(key, &record.value, &record.ttl);
+
+        Some(record)
+    }
+
+    /// Only keep the records for which the predicate returns true. Returns the
+    /// amount of removed records.
+    ///
+    /// # Arguments
+    ///
+    /// * `keep` - The predicate which decides if a record is kept.
+    pub fn retain(&mut self, mut keep: impl FnMut(&str, &Record) -> bool) -> usize {
+        let before = self.records.len();
+        let mut freed = 0;
+
+        self.records.retain(|key, record| {
+            let kept = keep(key, record);
+            if !kept {
+                freed += record_size(key, &record.value, &record.ttl);
+            }
+            kept
+        });
+
+        self.used_memory -= freed;
+        before - self.records.len()
+    }
+}
+
+impl Serialize for Keyspace {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.records.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Keyspace {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let records = IndexMap::<String, Record>::deserialize(deserializer)?;
+        let used_memory = records
+            .iter()
+            .map(|(key, record)| record_size(key, &record.value, &record.ttl))
+            .sum();
+
+        Ok(Self {
+            records,
+            used_memory,
+            clock: 0,
+        })
+    }
+}
+
+/// The database new connections use.
+pub const DEFAULT_DATABASE: &str = "0";
+
+/// Snapshots which contain multiple databases start with this, older
+/// snapshots only contain the records of the default database.
+pub const SNAPSHOT_HEADER: &[u8] = b"FFLYDBS1";
+
+/// All logical databases, every database has its own records. The records
+/// are shared between clones (e.g. of a dump or snapshot) until they get
+/// changed, only then they get copied.
+#[derive(Debug, Default, Clone)]
+pub struct Databases {
+    keyspaces: BTreeMap<String, Arc<Keyspace>>,
+}
+
+impl Databases {
+    /// Get the records of a database, `None` if it doesn't exist yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the database.
+    pub fn get(&self, name: &str) -> Option<&Keyspace> {
+        self.keyspaces.get(name).map(|keyspace| &**keyspace)
+    }
+
+    /// Get the records of a database mutably, `None` if it doesn't exist yet.
+    /// The records get copied if they are shared.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the database.
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Keyspace> {
+        self.keyspaces.get_mut(name).map(Arc::make_mut)
+    }
+
+    /// Get a record and register that it got accessed. While the records are
+    /// shared the access doesn't get registered, as that would copy them.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the database.
+    /// * `key` - The key of the record.
+    pub fn get_record(&mut self, name: &str, key: &str) -> Option<&Record> {
+        let keyspace = self.keyspaces.get_mut(name)?;
+        if Arc::strong_count(keyspace) == 1 {
+            Arc::make_mut(keyspace).get(key)
+        } else {
+            keyspace.peek(key)
+        }
+    }
+
+    /// Get the records of a database, the database gets created if it doesn't
+    /// exist yet. The records get copied if they are shared.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the database.
+    pub fn keyspace(&mut self, name: &str) -> &mut Keyspace {
+        Arc::make_mut(self.keyspaces.entry(name.to_string()).or_default())
+    }
+
+    /// Remove a database and its records.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the database.
+    pub fn remove(&mut self, name: &str) -> Option<Arc<Keyspace>> {
+        self.keyspaces.remove(name)
+    }
+
+    /// The amount of databases.
+    pub fn count(&self) -> usize {
+        self.keyspaces.len()
+    }
+
+    /// Iterate over all databases.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Keyspace)> {
+        self.keyspaces
+            .iter()
+            .map(|(name, keyspace)| (name, &**keyspace))
+    }
+
+    /// Iterate mutably over all databases. The records get copied if they are
+    /// shared.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&String, &mut Keyspace)> {
+        self.keyspaces
+            .iter_mut()
+            .map(|(name, keyspace)| (name, Arc::make_mut(keyspace)))
+    }
+
+    /// The amount of records in all databases.
+    pub fn len(&self) -> usize {
+        self.keyspaces.values().map(|keyspace| keyspace.len()).sum()
+    }
+
+    /// If there are no records in any database.
+    pub fn is_empty(&self) -> bool {
+        self.keyspaces.values().all(|keyspace| keyspace.is_empty())
+    }
+
+    /// Remove all databases and their records.
+    pub fn clear(&mut self) {
+        self.keyspaces.clear();
+    }
+
+    /// An estimate of the amount of memory the records of all databases use,
+    /// in bytes.
+    pub fn used_memory(&self) -> usize {
+        self.keyspaces
+            .values()
+            .map(|keyspace| keyspace.used_memory())
+            .sum()
+    }
+
+    /// Serialize all databases which contain records for a snapshot.
+    pub fn to_snapshot(&self) -> bincode::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.write_snapshot(&mut data)?;
+        Ok(data)
+    }
+
+    /// Serialize all databases which contain records for a snapshot, and
+    /// write it to a writer while it gets serialized.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Where the snapshot gets written to.
+    pub fn write_snapshot<W: Write>(&self, mut writer: W) -> bincode::Result<()> {
+        let keyspaces: BTreeMap<_, _> = self
+            .keyspaces
+            .iter()
+            .filter(|(_, keyspace)| !keyspace.is_empty())
+            .map(|(name, keyspace)| (name, &**keyspace))
+            .collect();
+
+        writer.write_all(SNAPSHOT_HEADER)?;
+        bincode::serialize_into(writer, &keyspaces)
+    }
+
+    /// Deserialize the databases from a snapshot. Snapshots from before
+    /// databases existed get loaded into the default database.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The content of the snapshot.
+    pub fn from_snapshot(data: &[u8]) -> bincode::Result<Self> {
+        let keyspaces: BTreeMap<String, Keyspace> = match data.strip_prefix(SNAPSHOT_HEADER) {
+            Some(data) => bincode::deserialize(data)?,
+            None => BTreeMap::from([(DEFAULT_DATABASE.to_string(), bincode::deserialize(data)?)]),
+        };
+
+        Ok(Self {
+            keyspaces: keyspaces
+                .into_iter()
+                .map(|(name, keyspace)| (name, Arc::new(keyspace)))
+                .collect(),
+        })
+    }
+}
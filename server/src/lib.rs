@@ -0,0 +1,173 @@
+#[macro_use]
+extern crate log;
+
+use tokio::sync::{broadcast, Notify};
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+
+use crate::clients::ClientRegistry;
+use crate::config::Config;
+use crate::keyspace::Databases;
+use crate::metrics::Metrics;
+use crate::monitor::{Monitor, MONITOR_CAPACITY};
+use crate::raft::Raft;
+use crate::ratelimit::UserRateLimits;
+use crate::replication::{Replication, REPLICATION_CAPACITY};
+use crate::slowlog::SlowLog;
+
+pub use crate::args::{Args, Command};
+pub use crate::server::{Server, ServerHandle};
+
+pub mod args;
+pub mod bitwise_query;
+pub mod clients;
+pub mod config;
+pub mod connection;
+pub mod convert;
+pub mod database;
+pub mod dump;
+pub mod eviction;
+pub mod info;
+pub mod inspect;
+pub mod keyspace;
+pub mod listener;
+pub mod metrics;
+pub mod monitor;
+pub mod query;
+pub mod raft;
+pub mod ratelimit;
+pub mod replication;
+pub mod sentinel;
+pub mod server;
+pub mod shutdown;
+pub mod slowlog;
+
+#[cfg(test)]
+mod test_args;
+
+#[cfg(test)]
+mod test_clients;
+
+#[cfg(test)]
+mod test_config;
+
+#[cfg(test)]
+mod test_connection;
+
+#[cfg(test)]
+mod test_convert;
+
+#[cfg(test)]
+mod test_database;
+
+#[cfg(test)]
+mod test_dump;
+
+#[cfg(test)]
+mod test_eviction;
+
+#[cfg(test)]
+mod test_info;
+
+#[cfg(test)]
+mod test_inspect;
+
+#[cfg(all(test, unix))]
+mod test_listener;
+
+#[cfg(test)]
+mod test_metrics;
+
+#[cfg(test)]
+mod test_monitor;
+
+#[cfg(test)]
+mod test_query;
+
+#[cfg(test)]
+mod test_bitwise_query;
+
+#[cfg(test)]
+mod test_raft;
+
+#[cfg(test)]
+mod test_ratelimit;
+
+#[cfg(test)]
+mod test_replication;
+
+#[cfg(test)]
+mod test_sentinel;
+
+#[cfg(test)]
+mod test_server;
+
+#[cfg(test)]
+mod test_shutdown;
+
+#[cfg(test)]
+mod test_slowlog;
+
+#[cfg(test)]
+mod test_util;
+
+pub type Db = Arc<Mutex<Databases>>;
+pub type Changed = Arc<Mutex<usize>>;
+pub type Settings = Arc<RwLock<Config>>;
+
+/// Everything the connections and background tasks share.
+#[derive(Clone)]
+pub struct State {
+    pub db: Db,
+    pub changed: Changed,
+    pub settings: Settings,
+    pub metrics: Arc<Metrics>,
+    pub slowlog: Arc<SlowLog>,
+    pub monitor: Monitor,
+    pub replication: Replication,
+    /// Notified when a `REPLICAOF` query changes the role of the server.
+    pub role_changed: Arc<Notify>,
+    /// The Raft node, if cluster mode is enabled.
+    pub raft: Option<Arc<Raft>>,
+    pub clients: Arc<ClientRegistry>,
+    pub user_rate_limits: Arc<UserRateLimits>,
+    /// Held while a snapshot gets written.
+    pub saving: Arc<tokio::sync::Mutex<()>>,
+    pub started: Instant,
+}
+
+impl State {
+    /// Create the state of a server with an empty database.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The server settings.
+    pub fn new(config: Config) -> Self {
+        let raft = (!config.cluster_peers.is_empty()).then(|| {
+            let id = config
+                .cluster_addr
+                .clone()
+                .unwrap_or_else(|| format!("{}:{}", config.host, config.port));
+            let path = PathBuf::from(format!("{}.raft", config.out));
+            Arc::new(Raft::new(id, config.cluster_peers.clone(), Some(path)))
+        });
+
+        Self {
+            db: Arc::new(Mutex::new(Databases::default())),
+            changed: Arc::new(Mutex::new(0)),
+            settings: Arc::new(RwLock::new(config)),
+            metrics: Arc::new(Metrics::default()),
+            slowlog: Arc::new(SlowLog::default()),
+            monitor: broadcast::channel(MONITOR_CAPACITY).0,
+            replication: broadcast::channel(REPLICATION_CAPACITY).0,
+            role_changed: Arc::new(Notify::new()),
+            raft,
+            clients: Arc::new(ClientRegistry::default()),
+            user_rate_limits: Arc::new(UserRateLimits::default()),
+            saving: Arc::new(tokio::sync::Mutex::new(())),
+            started: Instant::now(),
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::{io, net::SocketAddr};
+
+#[cfg(unix)]
+use std::{
+    fs,
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+};
+
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+};
+
+/// Any stream a client can talk to the server over. (TCP or Unix socket)
+pub trait Socket: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + 'static> Socket for T {}
+
+/// A listener which accepts new client connections.
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, String),
+}
+
+impl Listener {
+    /// Bind a TCP listener to a `host:port` address.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address to bind to. (e.g. "127.0.0.1:46600")
+    pub async fn bind_tcp(address: &str) -> io::Result<Self> {
+        Ok(Self::Tcp(TcpListener::bind(address).await?))
+    }
+
+    /// Bind a Unix socket listener to a path. If a stale socket file exists
+    /// at the path it gets removed first, any other file results in an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the socket file.
+    /// * `permissions` - The file mode of the socket file. (e.g. 0o660)
+    #[cfg(unix)]
+    pub fn bind_unix(path: &str, permissions: u32) -> io::Result<Self> {
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)?,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", path),
+                ))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(permissions))?;
+
+        Ok(Self::Unix(listener, path.to_string()))
+    }
+
+    /// Wait for a new client and return its stream together with a printable
+    /// address of the client.
+    pub async fn accept(&self) -> io::Result<(Box<dyn Socket>, String)> {
+        match self {
+            Self::Tcp(listener) => {
+                let (socket, addr) = listener.accept().await?;
+                Ok((Box::new(socket), addr.to_string()))
+            }
+            #[cfg(unix)]
+            Self::Unix(listener, path) => {
+                let (socket, _) = listener.accept().await?;
+                Ok((Box::new(socket), format!("unix:{}", path)))
+            }
+        }
+    }
+
+    /// The address a TCP listener is bound to, `None` for a Unix socket.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Self::Tcp(listener) => listener.local_addr().ok(),
+            #[cfg(unix)]
+            Self::Unix(..) => None,
+        }
+    }
+
+    /// A human readable description of where the listener is bound to.
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Tcp(listener) => match listener.local_addr() {
+                Ok(addr) => addr.to_string(),
+                Err(_) => "tcp".to_string(),
+            },
+            #[cfg(unix)]
+            Self::Unix(_, path) => format!("unix:{}", path),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Self::Unix(_, path) = self {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
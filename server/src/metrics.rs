@@ -0,0 +1,357 @@
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicI64, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use strum::{EnumCount, IntoEnumIterator};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{database::current_epoch, query::QueryType, shutdown::ShutdownListener, State};
+
+/// The upper bounds (in seconds) of the query latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 10] = [
+    0.000_01, 0.000_05, 0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.05, 0.1, 1.0,
+];
+
+/// Counters which describe what the server is doing.
+#[derive(Default)]
+pub struct Metrics {
+    queries: [AtomicU64; QueryType::COUNT],
+    invalid_queries: AtomicU64,
+    rate_limited_queries: AtomicU64,
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    latency_count: AtomicU64,
+    latency_sum_nanos: AtomicU64,
+    expired_total: AtomicU64,
+    expired_last_sweep: AtomicU64,
+    sweeps_total: AtomicU64,
+    evicted_total: AtomicU64,
+    snapshots_total: AtomicU64,
+    snapshot_duration_nanos: AtomicU64,
+    snapshot_size_bytes: AtomicU64,
+    snapshot_epoch: AtomicU64,
+    connections_total: AtomicU64,
+    rejected_connections_total: AtomicU64,
+    connected_clients: AtomicI64,
+}
+
+impl Metrics {
+    /// Register a processed query.
+    ///
+    /// # Arguments
+    ///
+    /// * `query_type` - The type of the query, `None` if it was invalid or refused.
+    /// * `elapsed` - How long it took to process the query.
+    pub fn record_query(&self, query_type: Option<QueryType>, elapsed: Duration) {
+        match query_type {
+            Some(query_type) => &self.queries[query_type as usize],
+            None => &self.invalid_queries,
+        }
+        .fetch_add(1, Ordering::Relaxed);
+
+        let seconds = elapsed.as_secs_f64();
+        for (bucket, bound) in self.latency_buckets.iter().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        self.latency_count.fetch_add(1, Ordering::Relaxed);
+        self.latency_sum_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Register a query that got refused because of a rate limit.
+    pub fn record_rate_limited(&self) {
+        self.rate_limited_queries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Register an expiration sweep.
+    ///
+    /// # Arguments
+    ///
+    /// * `expired` - The amount of records that got dropped.
+    pub fn record_sweep(&self, expired: usize) {
+        self.sweeps_total.fetch_add(1, Ordering::Relaxed);
+        self.expired_total
+            .fetch_add(expired as u64, Ordering::Relaxed);
+        self.expired_last_sweep
+            .store(expired as u64, Ordering::Relaxed);
+    }
+
+    /// Register records that got evicted to stay within the memory limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `evicted` - The amount of records that got evicted.
+    pub fn record_evictions(&self, evicted: usize) {
+        self.evicted_total
+            .fetch_add(evicted as u64, Ordering::Relaxed);
+    }
+
+    /// The amount of records that got evicted to stay within the memory limit.
+    pub fn evicted(&self) -> u64 {
+        self.evicted_total.load(Ordering::Relaxed)
+    }
+
+    /// Register a written snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `elapsed` - How long it took to write the snapshot.
+    /// * `bytes` - The size of the snapshot.
+    pub fn record_snapshot(&self, elapsed: Duration, bytes: usize) {
+        self.snapshots_total.fetch_add(1, Ordering::Relaxed);
+        self.snapshot_duration_nanos
+            .store(elapsed.as_nanos() as u64, Ordering::Relaxed);
+        self.snapshot_size_bytes
+            .store(bytes as u64, Ordering::Relaxed);
+        self.snapshot_epoch
+            .store(current_epoch(), Ordering::Relaxed);
+    }
+
+    /// When the last snapshot got written (seconds since the UNIX epoch) and
+    /// how long it took. Returns `None` if no snapshot has been written yet.
+    pub fn last_snapshot(&self) -> Option<(u64, Duration)> {
+        match self.snapshot_epoch.load(Ordering::Relaxed) {
+            0 => None,
+            epoch => Some((
+                epoch,
+                Duration::from_nanos(self.snapshot_duration_nanos.load(Ordering::Relaxed)),
+            )),
+        }
+    }
+
+    /// Register a new client connection.
+    pub fn client_connected(&self) {
+        self.connections_total.fetch_add(1, Ordering::Relaxed);
+        self.connected_clients.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Register a connection that got rejected because the maximum amount of
+    /// clients is connected.
+    pub fn client_rejected(&self) {
+        self.rejected_connections_total
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Register a closed client connection.
+    pub fn client_disconnected(&self) {
+        self.connected_clients.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// The amount of clients that are currently connected.
+    pub fn connected_clients(&self) -> i64 {
+        self.connected_clients.load(Ordering::Relaxed)
+    }
+
+    /// Render all metrics in the Prometheus text format.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - The amount of records in the database.
+    pub fn render(&self, keys: usize) -> String {
+        let mut out = String::new();
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let nanos_to_secs = |nanos: u64| nanos as f64 / 1_000_000_000.0;
+
+        // Writing to a string can't fail.
+        let mut metric = |name: &str, kind: &str, help: &str, values: Vec<(String, String)>| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (labels, value) in values {
+                let _ = writeln!(out, "{}{} {}", name, labels, value);
+            }
+        };
+
+        let mut queries: Vec<_> = QueryType::iter()
+            .map(|query_type| {
+                (
+                    format!("{{query_type=\"{:?}\"}}", query_type),
+                    load(&self.queries[query_type as usize]).to_string(),
+                )
+            })
+            .collect();
+        queries.push((
+            "{query_type=\"Invalid\"}".to_string(),
+            load(&self.invalid_queries).to_string(),
+        ));
+        metric(
+            "ffly_queries_total",
+            "counter",
+            "Processed queries by query type.",
+            queries,
+        );
+
+        let mut latency: Vec<_> = self
+            .latency_buckets
+            .iter()
+            .zip(LATENCY_BUCKETS)
+            .map(|(bucket, bound)| {
+                (
+                    format!("_bucket{{le=\"{}\"}}", bound),
+                    load(bucket).to_string(),
+                )
+            })
+            .collect();
+        latency.push((
+            "_bucket{le=\"+Inf\"}".to_string(),
+            load(&self.latency_count).to_string(),
+        ));
+        latency.push((
+            "_sum".to_string(),
+            nanos_to_secs(load(&self.latency_sum_nanos)).to_string(),
+        ));
+        latency.push(("_count".to_string(), load(&self.latency_count).to_string()));
+        metric(
+            "ffly_query_duration_seconds",
+            "histogram",
+            "Time it took to process a query.",
+            latency,
+        );
+
+        let single = |value: String| vec![(String::new(), value)];
+        metric(
+            "ffly_rate_limited_queries_total",
+            "counter",
+            "Queries refused because of a rate limit.",
+            single(load(&self.rate_limited_queries).to_string()),
+        );
+        metric(
+            "ffly_keys",
+            "gauge",
+            "Records in the database.",
+            single(keys.to_string()),
+        );
+        metric(
+            "ffly_expired_keys_total",
+            "counter",
+            "Records dropped because their TTL expired.",
+            single(load(&self.expired_total).to_string()),
+        );
+        metric(
+            "ffly_expired_keys_last_sweep",
+            "gauge",
+            "Records dropped by the last expiration sweep.",
+            single(load(&self.expired_last_sweep).to_string()),
+        );
+        metric(
+            "ffly_expiration_sweeps_total",
+            "counter",
+            "Executed expiration sweeps.",
+            single(load(&self.sweeps_total).to_string()),
+        );
+        metric(
+            "ffly_evicted_keys_total",
+            "counter",
+            "Records evicted to stay within the memory limit.",
+            single(self.evicted().to_string()),
+        );
+        metric(
+            "ffly_snapshots_total",
+            "counter",
+            "Snapshots written to disk.",
+            single(load(&self.snapshots_total).to_string()),
+        );
+        metric(
+            "ffly_snapshot_duration_seconds",
+            "gauge",
+            "Time it took to write the last snapshot.",
+            single(nanos_to_secs(load(&self.snapshot_duration_nanos)).to_string()),
+        );
+        metric(
+            "ffly_snapshot_size_bytes",
+            "gauge",
+            "Size of the last snapshot.",
+            single(load(&self.snapshot_size_bytes).to_string()),
+        );
+        metric(
+            "ffly_connections_total",
+            "counter",
+            "Accepted client connections.",
+            single(load(&self.connections_total).to_string()),
+        );
+        metric(
+            "ffly_rejected_connections_total",
+            "counter",
+            "Connections rejected because the maximum amount of clients was connected.",
+            single(load(&self.rejected_connections_total).to_string()),
+        );
+        metric(
+            "ffly_connected_clients",
+            "gauge",
+            "Currently connected clients.",
+            single(self.connected_clients().to_string()),
+        );
+
+        out
+    }
+}
+
+/// Answer a single HTTP request with the metrics.
+///
+/// # Arguments
+///
+/// * `socket` - The HTTP connection.
+/// * `state` - The server state.
+async fn serve_metrics(mut socket: TcpStream, state: State) -> std::io::Result<()> {
+    let mut buf = vec![0; 1024];
+    let size = socket.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..size]);
+
+    let response = if request.starts_with("GET /metrics ") || request.starts_with("GET / ") {
+        let keys = state.db.lock().unwrap().len();
+        let body = state.metrics.render(keys);
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+    };
+
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+/// Serve the metrics over HTTP in the Prometheus text format, until the
+/// shutdown gets triggered.
+///
+/// # Arguments
+///
+/// * `address` - The address to bind the HTTP listener to.
+/// * `state` - The server state.
+/// * `shutdown` - The listener which tells the server to stop.
+pub async fn serve(address: String, state: State, mut shutdown: ShutdownListener) {
+    let listener = match TcpListener::bind(&address).await {
+        Ok(listener) => listener,
+        Err(e) => return error!("Could not bind the metrics listener to {}: {}", address, e),
+    };
+
+    info!("Serving metrics on http://{}/metrics", address);
+
+    loop {
+        let socket = tokio::select! {
+            accept = listener.accept() => match accept {
+                Ok((socket, _)) => socket,
+                Err(e) => {
+                    warn!("Could not accept a metrics connection: {}", e);
+                    continue;
+                }
+            },
+            _ = shutdown.recv() => return,
+        };
+
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_metrics(socket, state).await {
+                debug!("Could not serve the metrics: {}", e);
+            }
+        });
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::broadcast::{self, error::RecvError},
+};
+
+use crate::{listener::Socket, query::QueryType, shutdown::ShutdownListener, State};
+
+/// The amount of entries a monitor can lag behind before it misses entries.
+pub const MONITOR_CAPACITY: usize = 1024;
+
+/// Broadcasts every executed query to the connections in monitor mode.
+pub type Monitor = broadcast::Sender<MonitorEntry>;
+
+/// An executed query, as seen by a monitor.
+#[derive(Debug, Clone)]
+pub struct MonitorEntry {
+    /// When the query got executed. (seconds since the UNIX epoch)
+    pub timestamp: f64,
+    /// The address of the client that sent the query.
+    pub address: String,
+    pub query_type: QueryType,
+    pub arguments: Vec<String>,
+}
+
+impl fmt::Display for MonitorEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:.6} [{}] {}",
+            self.timestamp, self.address, self.query_type
+        )?;
+
+        for argument in &self.arguments {
+            write!(f, " {:?}", argument)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The positions of the arguments of a query which contain a value or secret.
+///
+/// # Arguments
+///
+/// * `query_type` - The type of the query.
+fn sensitive_arguments(query_type: QueryType) -> &'static [usize] {
+    match query_type {
+        QueryType::New => &[1],
+        QueryType::DropAll => &[0],
+        _ => &[],
+    }
+}
+
+/// Broadcast an executed query to all monitors. Passwords always get redacted,
+/// values only when configured.
+///
+/// # Arguments
+///
+/// * `state` - The server state.
+/// * `address` - The address of the client that sent the query.
+/// * `query_type` - The type of the executed query.
+/// * `arguments` - The arguments of the executed query.
+pub fn broadcast(state: &State, address: &str, query_type: QueryType, arguments: &[String]) {
+    if state.monitor.receiver_count() == 0 {
+        return;
+    }
+
+    let redact_values = state.settings.read().unwrap().monitor_redact_values;
+    let arguments = arguments
+        .iter()
+        .enumerate()
+        .map(|(index, argument)| {
+            let is_password = query_type == QueryType::Auth && index == 1;
+            let is_value = redact_values && sensitive_arguments(query_type).contains(&index);
+
+            if is_password || is_value {
+                "(redacted)".to_string()
+            } else {
+                argument.clone()
+            }
+        })
+        .collect();
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs_f64())
+        .unwrap_or_default();
+
+    let _ = state.monitor.send(MonitorEntry {
+        timestamp,
+        address: address.to_string(),
+        query_type,
+        arguments,
+    });
+}
+
+/// Stream every executed query to a client, one entry per line. This lasts
+/// until the client disconnects or the shutdown gets triggered.
+///
+/// # Arguments
+///
+/// * `socket` - The client stream.
+/// * `state` - The server state.
+/// * `shutdown` - The listener which tells the session to stop.
+pub async fn stream_entries<S: Socket>(
+    socket: &mut S,
+    state: &State,
+    shutdown: &mut ShutdownListener,
+) {
+    let mut entries = state.monitor.subscribe();
+    let mut buf = [0; 64];
+
+    loop {
+        let line = tokio::select! {
+            entry = entries.recv() => match entry {
+                Ok(entry) => format!("{}\n", entry),
+                Err(RecvError::Lagged(missed)) => format!("Missed {} entries\n", missed),
+                Err(RecvError::Closed) => return,
+            },
+            // Queries are ignored in monitor mode, but a read notices if the
+            // client disconnected.
+            read = socket.read(&mut buf) => match read {
+                Ok(0) | Err(_) => return,
+                Ok(_) => continue,
+            },
+            _ = shutdown.recv() => return,
+        };
+
+        if socket.write_all(line.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
@@ -2,10 +2,13 @@ use std::string::FromUtf8Error;
 
 use anyhow::{anyhow, Result};
 use strum::EnumCount;
-use strum_macros::EnumCount as EnumCountMacro;
+use strum_macros::{Display, EnumCount as EnumCountMacro, EnumIter};
+
+use crate::config::Permission;
 
 /// All possible query types
-#[derive(Debug, Clone, Copy, EnumCountMacro, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Display, Clone, Copy, EnumCountMacro, EnumIter, PartialEq, Eq)]
 pub enum QueryType {
     New,
     Get,
@@ -15,6 +18,26 @@ pub enum QueryType {
     DropAll,
     QueryTypeString,
     QueryTypeBitwise,
+    Auth,
+    Info,
+    SlowlogGet,
+    SlowlogReset,
+    Monitor,
+    ClientList,
+    ClientSetName,
+    ClientKill,
+    Select,
+    FlushDb,
+    DbSize,
+    Save,
+    BgSave,
+    LastSave,
+    FlushAll,
+    Sync,
+    ReplicaOf,
+    Raft,
+    Dump,
+    Restore,
 }
 
 impl QueryType {
@@ -34,6 +57,28 @@ impl QueryType {
             '5' => Some(QueryType::DropAll),
             '6' => Some(QueryType::QueryTypeString),
             '7' => Some(QueryType::QueryTypeBitwise),
+            '8' => Some(QueryType::Auth),
+            '9' => Some(QueryType::Info),
+            'A' => Some(QueryType::SlowlogGet),
+            'B' => Some(QueryType::SlowlogReset),
+            'C' => Some(QueryType::Monitor),
+            'D' => Some(QueryType::ClientList),
+            'E' => Some(QueryType::ClientSetName),
+            'F' => Some(QueryType::ClientKill),
+            'G' => Some(QueryType::Select),
+            'H' => Some(QueryType::FlushDb),
+            // 'I' is skipped, as it is easily confused with '1'.
+            'J' => Some(QueryType::DbSize),
+            'K' => Some(QueryType::Save),
+            'L' => Some(QueryType::BgSave),
+            'M' => Some(QueryType::LastSave),
+            'N' => Some(QueryType::FlushAll),
+            // 'O' is skipped, as it is easily confused with '0'.
+            'P' => Some(QueryType::Sync),
+            'Q' => Some(QueryType::ReplicaOf),
+            'R' => Some(QueryType::Raft),
+            'S' => Some(QueryType::Dump),
+            'T' => Some(QueryType::Restore),
             _ => None,
         }
     }
@@ -41,16 +86,90 @@ impl QueryType {
     /// Get the query identifier from a query type.
     pub fn as_byte(&self) -> u8 {
         match &self {
-            QueryType::New => '0' as u8,
-            QueryType::Get => '1' as u8,
-            QueryType::GetValue => '2' as u8,
-            QueryType::GetTTL => '3' as u8,
-            QueryType::Drop => '4' as u8,
-            QueryType::DropAll => '5' as u8,
-            QueryType::QueryTypeString => '6' as u8,
-            QueryType::QueryTypeBitwise => '7' as u8,
+            QueryType::New => b'0',
+            QueryType::Get => b'1',
+            QueryType::GetValue => b'2',
+            QueryType::GetTTL => b'3',
+            QueryType::Drop => b'4',
+            QueryType::DropAll => b'5',
+            QueryType::QueryTypeString => b'6',
+            QueryType::QueryTypeBitwise => b'7',
+            QueryType::Auth => b'8',
+            QueryType::Info => b'9',
+            QueryType::SlowlogGet => b'A',
+            QueryType::SlowlogReset => b'B',
+            QueryType::Monitor => b'C',
+            QueryType::ClientList => b'D',
+            QueryType::ClientSetName => b'E',
+            QueryType::ClientKill => b'F',
+            QueryType::Select => b'G',
+            QueryType::FlushDb => b'H',
+            QueryType::DbSize => b'J',
+            QueryType::Save => b'K',
+            QueryType::BgSave => b'L',
+            QueryType::LastSave => b'M',
+            QueryType::FlushAll => b'N',
+            QueryType::Sync => b'P',
+            QueryType::ReplicaOf => b'Q',
+            QueryType::Raft => b'R',
+            QueryType::Dump => b'S',
+            QueryType::Restore => b'T',
+        }
+    }
+
+    /// The permission a user needs to execute the query.
+    /// Returns `None` if every client may execute it.
+    pub fn permission(&self) -> Option<Permission> {
+        match &self {
+            QueryType::Get | QueryType::GetValue | QueryType::GetTTL | QueryType::DbSize => {
+                Some(Permission::Read)
+            }
+            QueryType::New | QueryType::Drop | QueryType::DropAll | QueryType::FlushDb => {
+                Some(Permission::Write)
+            }
+            QueryType::Info
+            | QueryType::SlowlogGet
+            | QueryType::SlowlogReset
+            | QueryType::Monitor
+            | QueryType::ClientList
+            | QueryType::ClientKill
+            | QueryType::Save
+            | QueryType::BgSave
+            | QueryType::LastSave
+            | QueryType::FlushAll
+            | QueryType::Sync
+            | QueryType::ReplicaOf
+            | QueryType::Raft
+            | QueryType::Dump
+            | QueryType::Restore => Some(Permission::Admin),
+            QueryType::QueryTypeString
+            | QueryType::QueryTypeBitwise
+            | QueryType::Auth
+            | QueryType::ClientSetName
+            | QueryType::Select => None,
         }
     }
+
+    /// If the query reads records.
+    pub fn is_read(&self) -> bool {
+        matches!(
+            self,
+            QueryType::Get | QueryType::GetValue | QueryType::GetTTL | QueryType::DbSize
+        )
+    }
+
+    /// If the query changes records.
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            QueryType::New
+                | QueryType::Drop
+                | QueryType::DropAll
+                | QueryType::FlushDb
+                | QueryType::FlushAll
+                | QueryType::Restore
+        )
+    }
 }
 
 /// Each query type its defining syntax
@@ -63,6 +182,26 @@ const BINARY_VALUES: [(&[u8], QueryType); QueryType::COUNT] = [
     ("DROPALL".as_bytes(), QueryType::DropAll),
     ("QUERYTYPESTRING".as_bytes(), QueryType::QueryTypeString),
     ("QUERYTYPEBITWISE".as_bytes(), QueryType::QueryTypeBitwise),
+    ("AUTH".as_bytes(), QueryType::Auth),
+    ("INFO".as_bytes(), QueryType::Info),
+    ("SLOWLOGGET".as_bytes(), QueryType::SlowlogGet),
+    ("SLOWLOGRESET".as_bytes(), QueryType::SlowlogReset),
+    ("MONITOR".as_bytes(), QueryType::Monitor),
+    ("CLIENTLIST".as_bytes(), QueryType::ClientList),
+    ("CLIENTSETNAME".as_bytes(), QueryType::ClientSetName),
+    ("CLIENTKILL".as_bytes(), QueryType::ClientKill),
+    ("SELECT".as_bytes(), QueryType::Select),
+    ("FLUSHDB".as_bytes(), QueryType::FlushDb),
+    ("DBSIZE".as_bytes(), QueryType::DbSize),
+    ("SAVE".as_bytes(), QueryType::Save),
+    ("BGSAVE".as_bytes(), QueryType::BgSave),
+    ("LASTSAVE".as_bytes(), QueryType::LastSave),
+    ("FLUSHALL".as_bytes(), QueryType::FlushAll),
+    ("SYNC".as_bytes(), QueryType::Sync),
+    ("REPLICAOF".as_bytes(), QueryType::ReplicaOf),
+    ("RAFT".as_bytes(), QueryType::Raft),
+    ("DUMP".as_bytes(), QueryType::Dump),
+    ("RESTORE".as_bytes(), QueryType::Restore),
 ];
 
 /// Deduct the query type.
@@ -77,29 +216,26 @@ pub(crate) fn get_query_type(query: String) -> Option<QueryType> {
     let mut byte_index = 0;
 
     for byte in query.bytes() {
-        if byte == '\n' as u8 || byte == ' ' as u8 {
+        if byte == b'\n' || byte == b' ' {
             continue;
         }
 
-        matchable = matchable
-            .into_iter()
-            .filter(|(bytes, query_type)| {
-                if bytes.len() - 1 == byte_index {
-                    last_match = Some(*query_type);
-                } else if bytes.len() - 1 < byte_index {
-                    return false;
-                }
+        matchable.retain(|(bytes, query_type)| {
+            if bytes.len() - 1 == byte_index {
+                last_match = Some(*query_type);
+            } else if bytes.len() - 1 < byte_index {
+                return false;
+            }
 
-                bytes[byte_index] == byte.to_ascii_uppercase()
-            })
-            .collect();
+            bytes[byte_index] == byte.to_ascii_uppercase()
+        });
 
         byte_index += 1;
 
         if matchable.len() == 1 {
             return Some(matchable[0].1);
-        } else if matchable.len() == 0 {
-            if let Some(_) = last_match {
+        } else if matchable.is_empty() {
+            if last_match.is_some() {
                 return last_match;
             }
             break;
@@ -109,9 +245,9 @@ pub(crate) fn get_query_type(query: String) -> Option<QueryType> {
     None
 }
 
-const SINGLE_QUOTE: u8 = '\'' as u8;
-const DOUBLE_QUOTE: u8 = '"' as u8;
-const END_QUERY: u8 = ';' as u8;
+const SINGLE_QUOTE: u8 = b'\'';
+const DOUBLE_QUOTE: u8 = b'"';
+const END_QUERY: u8 = b';';
 
 /// Retrieve all query arguments.
 ///
@@ -128,7 +264,7 @@ pub(crate) fn get_arguments(query: String) -> Result<Vec<String>, FromUtf8Error>
         let is_delimiter = byte == SINGLE_QUOTE || byte == DOUBLE_QUOTE;
 
         if is_delimiter {
-            if current_argument.len() == 0 {
+            if current_argument.is_empty() {
                 is_within_value = true;
                 end_byte = byte;
                 continue;
@@ -146,7 +282,7 @@ pub(crate) fn get_arguments(query: String) -> Result<Vec<String>, FromUtf8Error>
         }
     }
 
-    return Ok(arguments);
+    Ok(arguments)
 }
 
 /// Parse a query its arguments and perform some checks on the validity of the
@@ -165,8 +301,24 @@ pub(crate) fn parse_query_arguments(
 ) -> Result<(QueryType, Vec<String>)> {
     let expected_arg_count = match query_type {
         QueryType::New => 3,
+        QueryType::Auth => 2,
         QueryType::QueryTypeString => 0,
         QueryType::QueryTypeBitwise => 0,
+        QueryType::Info => 0,
+        QueryType::SlowlogGet => 0,
+        QueryType::SlowlogReset => 0,
+        QueryType::Monitor => 0,
+        QueryType::ClientList => 0,
+        QueryType::FlushDb => 0,
+        QueryType::DbSize => 0,
+        QueryType::Save => 0,
+        QueryType::BgSave => 0,
+        QueryType::LastSave => 0,
+        QueryType::FlushAll => 0,
+        QueryType::Sync => 0,
+        QueryType::Raft => 0,
+        QueryType::Dump => 0,
+        QueryType::Restore => 0,
         _ => 1,
     };
 
@@ -0,0 +1,1323 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{lookup_host, TcpStream},
+    sync::watch,
+    task::{spawn_blocking, JoinSet},
+    time::{sleep, sleep_until, timeout, Instant},
+};
+
+use crate::{
+    config::Permission,
+    keyspace::Databases,
+    query::QueryType,
+    replication::{connect_to, read_frame, write_frame, Mutation},
+    shutdown::ShutdownListener,
+    State,
+};
+
+/// How often the leader sends its log (or a heartbeat) to the followers.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The minimum time without a leader before a node starts an election. The
+/// actual timeout is randomized between this and twice this.
+const ELECTION_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// How long a node waits for the response of another node.
+const RPC_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How long a write waits until it got replicated to a majority of the nodes.
+const COMMIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The maximum amount of entries the leader sends at once.
+const MAX_BATCH_LEN: usize = 512;
+
+/// The log gets compacted once it contains this many entries. Followers that
+/// lag further behind receive a snapshot instead.
+const MAX_LOG_LEN: usize = 4096;
+
+/// How long a node waits before it retries to store its state.
+const PERSIST_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// The role of a node in the cluster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Follower,
+    Candidate,
+    Leader,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Role::Follower => write!(f, "follower"),
+            Role::Candidate => write!(f, "candidate"),
+            Role::Leader => write!(f, "leader"),
+        }
+    }
+}
+
+/// An entry of the replicated log. The leader appends a no-op entry (without
+/// mutation) when it gets elected.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    term: u64,
+    mutation: Option<Mutation>,
+}
+
+/// What a node sends to another node.
+#[derive(Debug, Serialize, Deserialize)]
+enum Request {
+    /// A candidate asks for a vote.
+    Vote {
+        term: u64,
+        candidate: String,
+        last_index: u64,
+        last_term: u64,
+    },
+    /// The leader replicates its log, without entries this is a heartbeat.
+    Append {
+        term: u64,
+        leader: String,
+        prev_index: u64,
+        prev_term: u64,
+        entries: Vec<Entry>,
+        commit: u64,
+    },
+    /// The leader replaces the databases of a follower which can't catch up
+    /// with the log.
+    Snapshot {
+        term: u64,
+        leader: String,
+        index: u64,
+        index_term: u64,
+        commit: u64,
+        data: Vec<u8>,
+    },
+}
+
+/// What a node responds to a request.
+#[derive(Debug, Serialize, Deserialize)]
+enum Response {
+    Vote {
+        term: u64,
+        granted: bool,
+    },
+    Append {
+        term: u64,
+        success: bool,
+        /// The last index of the follower its log, or where it conflicts.
+        last_index: u64,
+        needs_snapshot: bool,
+    },
+    Snapshot {
+        term: u64,
+    },
+}
+
+/// The entries of the log which haven't been compacted.
+#[derive(Debug)]
+struct Log {
+    /// The index of the first entry.
+    start: u64,
+    /// The term of the entry before the first one.
+    start_term: u64,
+    entries: VecDeque<Entry>,
+}
+
+impl Log {
+    fn new(index: u64, term: u64) -> Self {
+        Self {
+            start: index + 1,
+            start_term: term,
+            entries: VecDeque::new(),
+        }
+    }
+
+    fn last_index(&self) -> u64 {
+        self.start + self.entries.len() as u64 - 1
+    }
+
+    fn last_term(&self) -> u64 {
+        self.entries
+            .back()
+            .map_or(self.start_term, |entry| entry.term)
+    }
+
+    /// The term of the entry at an index, `None` if it isn't in the log.
+    fn term_at(&self, index: u64) -> Option<u64> {
+        if index + 1 == self.start {
+            return Some(self.start_term);
+        }
+
+        self.get(index).map(|entry| entry.term)
+    }
+
+    fn get(&self, index: u64) -> Option<&Entry> {
+        index
+            .checked_sub(self.start)
+            .and_then(|offset| self.entries.get(offset as usize))
+    }
+
+    /// Remove the entry at an index and all entries after it.
+    fn truncate_from(&mut self, index: u64) {
+        self.entries
+            .truncate(index.saturating_sub(self.start) as usize);
+    }
+
+    /// The entries from an index on, at most `MAX_BATCH_LEN` of them.
+    fn entries_from(&self, index: u64) -> Vec<Entry> {
+        self.entries
+            .iter()
+            .skip(index.saturating_sub(self.start) as usize)
+            .take(MAX_BATCH_LEN)
+            .cloned()
+            .collect()
+    }
+
+    /// Drop old entries, up to (and including) an index.
+    fn compact(&mut self, up_to: u64) {
+        while self.start <= up_to {
+            match self.entries.pop_front() {
+                Some(entry) => {
+                    self.start += 1;
+                    self.start_term = entry.term;
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+/// The state of a node which must survive a restart: the term, vote and log.
+/// The databases, which contain every entry up to `applied`, get stored right
+/// after it.
+#[derive(Debug, Serialize, Deserialize)]
+struct DurableState {
+    term: u64,
+    voted_for: Option<String>,
+    start: u64,
+    start_term: u64,
+    entries: VecDeque<Entry>,
+    applied: u64,
+    synced: bool,
+}
+
+/// Read the state a node stored, `None` if it didn't store anything yet.
+///
+/// # Arguments
+///
+/// * `path` - Where the state got stored.
+fn read_state(path: &Path) -> Option<(DurableState, Databases)> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+        Err(e) => panic!("Could not read {}: {}", path.display(), e),
+    };
+
+    let mut reader = data.as_slice();
+    let stored = bincode::deserialize_from(&mut reader)
+        .and_then(|stored| Ok((stored, Databases::from_snapshot(reader)?)))
+        .unwrap_or_else(|e| panic!("Could not read {}: {}", path.display(), e));
+    Some(stored)
+}
+
+/// Write the state of a node, and only return once it reached the disk.
+///
+/// # Arguments
+///
+/// * `path` - Where the state gets stored.
+/// * `stored` - The term, vote and log.
+/// * `databases` - The databases, up to the applied index.
+fn write_state(path: &Path, stored: &DurableState, databases: &Databases) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    bincode::serialize_into(&mut writer, stored)?;
+    databases.write_snapshot(&mut writer)?;
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    fs::rename(&tmp, path)?;
+
+    // The rename only survives a crash once the directory got synced.
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+struct RaftState {
+    role: Role,
+    term: u64,
+    voted_for: Option<String>,
+    leader: Option<String>,
+    log: Log,
+    /// The highest index known to be replicated on a majority.
+    commit: u64,
+    /// The highest index applied to the databases.
+    applied: u64,
+    /// If the databases are in sync with the log. A node which restarted or
+    /// applied writes that didn't make it into the log needs a snapshot.
+    synced: bool,
+    /// Incremented every time the databases get reset, so waiting writes
+    /// know their entry got lost.
+    generation: u64,
+    /// The next index to send to each follower, 0 means a snapshot.
+    next: HashMap<String, u64>,
+    /// The highest index known to be replicated on each follower.
+    matched: HashMap<String, u64>,
+    /// When to start an election if the leader doesn't send anything.
+    deadline: Instant,
+    /// Incremented every time the term, vote or log changes.
+    version: u64,
+    /// The highest index of the log the leader stored in its current term.
+    durable: u64,
+}
+
+/// A node of a Raft cluster. Writes get applied by the leader, which then
+/// replicates the resulting mutations to the followers. A write is only
+/// acknowledged once a majority of the nodes stored it, and reads on the
+/// leader only return once everything they could have seen got committed.
+pub struct Raft {
+    /// The address of this node, as the other nodes know it.
+    pub id: String,
+    peers: Vec<String>,
+    state: Mutex<RaftState>,
+    /// The commit index and generation, for the writes that wait on them.
+    committed: watch::Sender<(u64, u64)>,
+    /// Wakes up the tasks that replicate the log to the followers.
+    appended: watch::Sender<()>,
+    /// Wakes up the task that stores the state, with the new version.
+    changed: watch::Sender<u64>,
+    /// The stored version and the amount of failed attempts to store it.
+    persisted: watch::Sender<(u64, u64)>,
+    /// Where the state gets stored.
+    path: Option<PathBuf>,
+    /// The stored databases, until `start` replaces the loaded ones with them.
+    restored: Mutex<Option<Databases>>,
+}
+
+/// A random election deadline from now on.
+fn election_deadline() -> Instant {
+    Instant::now() + ELECTION_TIMEOUT.mul_f64(1.0 + fastrand::f64())
+}
+
+impl Raft {
+    /// Create a node, which starts as a follower. The term, vote, log and
+    /// databases get restored from disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The address of this node, as the other nodes know it.
+    /// * `peers` - The addresses of the other nodes.
+    /// * `path` - Where to store the state, `None` keeps it in memory.
+    pub fn new(id: String, peers: Vec<String>, path: Option<PathBuf>) -> Self {
+        let mut state = RaftState {
+            role: Role::Follower,
+            term: 0,
+            voted_for: None,
+            leader: None,
+            log: Log::new(0, 0),
+            commit: 0,
+            applied: 0,
+            synced: false,
+            generation: 0,
+            next: HashMap::new(),
+            matched: HashMap::new(),
+            deadline: election_deadline(),
+            version: 0,
+            durable: 0,
+        };
+
+        let restored = path
+            .as_deref()
+            .and_then(read_state)
+            .map(|(stored, databases)| {
+                info!(
+                    "Restored the Raft state of term {} up to index {}",
+                    stored.term,
+                    stored.start + stored.entries.len() as u64 - 1
+                );
+                state.term = stored.term;
+                state.voted_for = stored.voted_for;
+                state.log = Log {
+                    start: stored.start,
+                    start_term: stored.start_term,
+                    entries: stored.entries,
+                };
+                state.applied = stored.applied;
+                state.synced = stored.synced;
+                databases
+            });
+
+        Self {
+            id,
+            peers,
+            state: Mutex::new(state),
+            committed: watch::channel((0, 0)).0,
+            appended: watch::channel(()).0,
+            changed: watch::channel(0).0,
+            persisted: watch::channel((0, 0)).0,
+            path,
+            restored: Mutex::new(restored),
+        }
+    }
+
+    /// The amount of nodes that form a majority.
+    fn majority(&self) -> usize {
+        let nodes = self.peers.len() + 1;
+        nodes / 2 + 1
+    }
+
+    /// Register that the term, vote or log changed, so it gets stored. Nothing
+    /// that changed may be sent to another node before it got stored.
+    fn touch(&self, state: &mut RaftState) {
+        state.version += 1;
+        self.changed.send_replace(state.version);
+    }
+
+    /// The version of the term, vote and log.
+    fn version(&self) -> u64 {
+        self.state.lock().unwrap().version
+    }
+
+    /// Wait until a version of the state got stored. Returns `false` if
+    /// storing it failed.
+    ///
+    /// # Arguments
+    ///
+    /// * `version` - The version returned by `Raft::version`.
+    async fn wait_persisted(&self, version: u64) -> bool {
+        let mut persisted = self.persisted.subscribe();
+        let failures = persisted.borrow().1;
+        let waiting =
+            persisted.wait_for(|(stored, failed)| *stored >= version || *failed != failures);
+
+        let persisted = match waiting.await {
+            Ok(value) => value.0 >= version,
+            Err(_) => false,
+        };
+        persisted
+    }
+
+    /// Register that a version of the state got stored. The leader can count
+    /// the entries it stored towards a majority from now on.
+    ///
+    /// # Arguments
+    ///
+    /// * `version` - The stored version.
+    /// * `term` - The term of the stored state.
+    /// * `leader` - If the node was the leader when the state got copied.
+    /// * `last_index` - The last index of the stored log.
+    fn stored(&self, version: u64, term: u64, leader: bool, last_index: u64) {
+        let mut state = self.state.lock().unwrap();
+        if leader && state.role == Role::Leader && state.term == term {
+            state.durable = state.durable.max(last_index);
+            self.advance_commit(&mut state);
+        }
+        drop(state);
+
+        self.persisted.send_modify(|(stored, _)| *stored = version);
+    }
+
+    /// Register that the state couldn't be stored. A leader steps down, as it
+    /// can't guarantee its writes survive.
+    fn store_failed(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.role == Role::Leader {
+            warn!("Stepping down as leader, the Raft state can't be stored");
+            state.role = Role::Follower;
+            state.leader = None;
+            state.deadline = election_deadline();
+        }
+        drop(state);
+
+        self.persisted.send_modify(|(_, failed)| *failed += 1);
+    }
+
+    /// Switch to a newer term as a follower.
+    fn step_down(&self, state: &mut RaftState, term: u64) {
+        if state.role == Role::Leader {
+            info!("Stepping down as leader, term {} started", term);
+        }
+
+        state.role = Role::Follower;
+        if term > state.term {
+            state.term = term;
+            state.voted_for = None;
+            state.leader = None;
+            self.touch(state);
+        }
+    }
+
+    /// Forget the log and wait for a snapshot of the leader, because the
+    /// databases contain writes which didn't make it into the log.
+    fn reset(&self, state: &mut RaftState) {
+        warn!("The databases diverged from the Raft log, waiting for a snapshot");
+        state.synced = false;
+        state.generation += 1;
+        state.log = Log::new(0, 0);
+        state.commit = 0;
+        state.applied = 0;
+        self.touch(state);
+        self.committed.send_replace((0, state.generation));
+    }
+
+    /// The role, term and leader of the node.
+    pub fn status(&self) -> (Role, u64, Option<String>) {
+        let state = self.state.lock().unwrap();
+        (state.role, state.term, state.leader.clone())
+    }
+
+    /// The index up to which the log is committed.
+    pub fn commit_index(&self) -> u64 {
+        self.state.lock().unwrap().commit
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.state.lock().unwrap().role == Role::Leader
+    }
+
+    /// Append a mutation the leader applied to its log. This must be called
+    /// while the databases are locked, so the log has the same order.
+    ///
+    /// # Arguments
+    ///
+    /// * `mutation` - The applied mutation.
+    pub fn append(&self, mutation: Mutation) {
+        let mut state = self.state.lock().unwrap();
+
+        if state.role != Role::Leader {
+            // Leadership got lost while the query executed.
+            self.reset(&mut state);
+            return;
+        }
+
+        let term = state.term;
+        state.log.entries.push_back(Entry {
+            term,
+            mutation: Some(mutation),
+        });
+        state.applied = state.log.last_index();
+        self.touch(&mut state);
+        drop(state);
+
+        self.appended.send_replace(());
+    }
+
+    /// What a write has to wait for: the last index of the log and the
+    /// current generation.
+    pub fn pending(&self) -> (u64, u64) {
+        let state = self.state.lock().unwrap();
+        (state.log.last_index(), state.generation)
+    }
+
+    /// Wait until an index got committed. Returns `false` if it didn't get
+    /// committed in time, or if the entry got lost.
+    ///
+    /// # Arguments
+    ///
+    /// * `pending` - The index and generation returned by `Raft::pending`.
+    pub async fn wait_for_commit(&self, (index, generation): (u64, u64)) -> bool {
+        let mut committed = self.committed.subscribe();
+        let waiting =
+            committed.wait_for(|(commit, current)| *commit >= index || *current != generation);
+
+        let committed = match timeout(COMMIT_TIMEOUT, waiting).await {
+            Ok(Ok(value)) => value.1 == generation,
+            _ => false,
+        };
+        committed
+    }
+
+    /// Advance the commit index to the highest index of the current term
+    /// that a majority stored. The leader only counts itself once it stored
+    /// the entry as well.
+    fn advance_commit(&self, state: &mut RaftState) {
+        let mut index = state.log.last_index();
+
+        while index > state.commit {
+            let stored = usize::from(state.durable >= index)
+                + state
+                    .matched
+                    .values()
+                    .filter(|matched| **matched >= index)
+                    .count();
+
+            if stored >= self.majority() && state.log.term_at(index) == Some(state.term) {
+                state.commit = index;
+                self.committed.send_replace((index, state.generation));
+                break;
+            }
+
+            index -= 1;
+        }
+
+        self.compact(state);
+    }
+
+    /// Drop old, applied entries when the log gets too long.
+    fn compact(&self, state: &mut RaftState) {
+        if state.log.entries.len() > MAX_LOG_LEN {
+            let up_to = state.log.last_index() - (MAX_LOG_LEN / 2) as u64;
+            state
+                .log
+                .compact(up_to.min(state.commit).min(state.applied));
+        }
+    }
+
+    /// Handle a vote request of a candidate.
+    fn handle_vote(
+        &self,
+        term: u64,
+        candidate: String,
+        last_index: u64,
+        last_term: u64,
+    ) -> Response {
+        let mut state = self.state.lock().unwrap();
+        if term > state.term {
+            self.step_down(&mut state, term);
+        }
+
+        let up_to_date = (last_term, last_index) >= (state.log.last_term(), state.log.last_index());
+        let may_vote = state.voted_for.is_none() || state.voted_for.as_ref() == Some(&candidate);
+        let granted = term == state.term && may_vote && up_to_date;
+
+        if granted {
+            debug!("Voted for {} in term {}", candidate, term);
+            state.voted_for = Some(candidate);
+            state.deadline = election_deadline();
+            self.touch(&mut state);
+        }
+
+        Response::Vote {
+            term: state.term,
+            granted,
+        }
+    }
+
+    /// Follow a leader that sent a valid request.
+    fn follow_leader(&self, state: &mut RaftState, term: u64, leader: String) {
+        if term > state.term || state.role != Role::Follower {
+            self.step_down(state, term);
+        }
+
+        if state.leader.as_ref() != Some(&leader) {
+            info!("Following leader {} in term {}", leader, term);
+            state.leader = Some(leader);
+        }
+
+        state.deadline = election_deadline();
+    }
+
+    /// Apply the entries which haven't been applied yet, up to an index.
+    fn apply_until(&self, state: &mut RaftState, db: &mut Databases, app: &State, up_to: u64) {
+        while state.applied < up_to {
+            let index = state.applied + 1;
+            if let Some(Entry {
+                mutation: Some(mutation),
+                ..
+            }) = state.log.get(index)
+            {
+                mutation.apply(db);
+                // Replicas of this node receive the same mutations. The log
+                // is locked, so this can't go through `replicate`.
+                if app.replication.receiver_count() != 0 {
+                    let _ = app.replication.send(mutation.clone());
+                }
+                *app.changed.lock().unwrap() += 1;
+            }
+
+            state.applied = index;
+        }
+    }
+
+    /// Handle the log entries of the leader.
+    fn handle_append(&self, app: &State, request: Request) -> Response {
+        let Request::Append {
+            term,
+            leader,
+            prev_index,
+            prev_term,
+            entries,
+            commit,
+        } = request
+        else {
+            unreachable!("only append requests are handled here")
+        };
+
+        let mut db = app.db.lock().unwrap();
+        let mut state = self.state.lock().unwrap();
+
+        let reject = |state: &RaftState, last_index, needs_snapshot| Response::Append {
+            term: state.term,
+            success: false,
+            last_index,
+            needs_snapshot,
+        };
+
+        if term < state.term {
+            return reject(&state, state.log.last_index(), false);
+        }
+
+        self.follow_leader(&mut state, term, leader);
+
+        if !state.synced {
+            return reject(&state, 0, true);
+        }
+
+        if prev_index > state.log.last_index() {
+            return reject(&state, state.log.last_index(), false);
+        }
+
+        match state.log.term_at(prev_index) {
+            Some(term) if term == prev_term => {}
+            // The entry got compacted, so it is committed and matches.
+            None if prev_index < state.log.start => {}
+            _ => {
+                if prev_index <= state.applied {
+                    self.reset(&mut state);
+                    return reject(&state, 0, true);
+                }
+
+                state.log.truncate_from(prev_index);
+                self.touch(&mut state);
+                return reject(&state, prev_index - 1, false);
+            }
+        }
+
+        let last_new = prev_index + entries.len() as u64;
+        let mut appended = false;
+        for (index, entry) in (prev_index + 1..).zip(entries) {
+            if index < state.log.start {
+                continue;
+            }
+
+            match state.log.term_at(index) {
+                Some(term) if term == entry.term => continue,
+                Some(_) => {
+                    if index <= state.applied {
+                        self.reset(&mut state);
+                        return reject(&state, 0, true);
+                    }
+                    state.log.truncate_from(index);
+                }
+                None => {}
+            }
+
+            state.log.entries.push_back(entry);
+            appended = true;
+        }
+
+        if appended {
+            self.touch(&mut state);
+        }
+
+        let commit = commit.min(last_new);
+        if commit > state.commit {
+            state.commit = commit;
+            self.apply_until(&mut state, &mut db, app, commit);
+            self.committed
+                .send_replace((state.commit, state.generation));
+        }
+
+        self.compact(&mut state);
+
+        Response::Append {
+            term: state.term,
+            success: true,
+            last_index: last_new,
+            needs_snapshot: false,
+        }
+    }
+
+    /// Replace the databases and log with a snapshot of the leader.
+    fn handle_snapshot(&self, app: &State, request: Request) -> Result<Response> {
+        let Request::Snapshot {
+            term,
+            leader,
+            index,
+            index_term,
+            commit,
+            data,
+        } = request
+        else {
+            unreachable!("only snapshot requests are handled here")
+        };
+
+        let databases = Databases::from_snapshot(&data)?;
+        let mut db = app.db.lock().unwrap();
+        let mut state = self.state.lock().unwrap();
+
+        if term < state.term {
+            return Ok(Response::Snapshot { term: state.term });
+        }
+
+        self.follow_leader(&mut state, term, leader);
+        info!(
+            "Installed a snapshot of {} records up to index {}",
+            databases.len(),
+            index
+        );
+
+        *db = databases;
+        *app.changed.lock().unwrap() += 1;
+        state.log = Log::new(index, index_term);
+        state.applied = index;
+        state.commit = commit.min(index);
+        state.synced = true;
+        self.touch(&mut state);
+        self.committed
+            .send_replace((state.commit, state.generation));
+
+        Ok(Response::Snapshot { term: state.term })
+    }
+
+    /// Become the leader, if this node is still a candidate in the term.
+    fn become_leader(&self, app: &State, term: u64) {
+        let mut db = app.db.lock().unwrap();
+        let mut state = self.state.lock().unwrap();
+        if state.role != Role::Candidate || state.term != term {
+            return;
+        }
+
+        info!("Elected as leader in term {}", term);
+        state.role = Role::Leader;
+        state.leader = Some(self.id.clone());
+
+        // The databases of the leader contain every entry of its log.
+        let last_index = state.log.last_index();
+        self.apply_until(&mut state, &mut db, app, last_index);
+        state.synced = true;
+
+        let next = state.log.last_index() + 1;
+        state.next = self.peers.iter().map(|peer| (peer.clone(), next)).collect();
+        state.matched = self.peers.iter().map(|peer| (peer.clone(), 0)).collect();
+
+        // Entries of earlier terms only get committed through an entry of the
+        // current term.
+        state.log.entries.push_back(Entry {
+            term,
+            mutation: None,
+        });
+        state.applied = state.log.last_index();
+        state.durable = 0;
+        self.touch(&mut state);
+        drop(state);
+
+        self.appended.send_replace(());
+    }
+}
+
+/// Send a request to another node, over an established connection.
+///
+/// # Arguments
+///
+/// * `socket` - The connection with the other node.
+/// * `request` - The request to send.
+async fn call<S: AsyncRead + AsyncWrite + Unpin>(
+    socket: &mut S,
+    request: &Request,
+) -> Result<Response> {
+    let calling = async {
+        write_frame(socket, request).await?;
+        read_frame(socket).await
+    };
+
+    timeout(RPC_TIMEOUT, calling)
+        .await
+        .map_err(|_| anyhow!("timed out"))?
+}
+
+/// Connect to another node of the cluster.
+///
+/// # Arguments
+///
+/// * `state` - The server state.
+/// * `peer` - The address of the other node.
+async fn connect(state: &State, peer: &str) -> Result<TcpStream> {
+    timeout(RPC_TIMEOUT, connect_to(state, peer, QueryType::Raft))
+        .await
+        .map_err(|_| anyhow!("timed out"))?
+}
+
+/// Check if a client connects from the host of one of the configured peers.
+/// Only they may send the requests which change the term, log and records.
+///
+/// # Arguments
+///
+/// * `state` - The server state.
+/// * `address` - The address of the client.
+pub async fn is_peer(state: &State, address: &str) -> bool {
+    let Ok(address) = address.parse::<SocketAddr>() else {
+        return false;
+    };
+
+    let peers = state.settings.read().unwrap().cluster_peers.clone();
+    for peer in peers {
+        match lookup_host(&peer).await {
+            Ok(mut resolved) => {
+                if resolved.any(|peer| peer.ip() == address.ip()) {
+                    return true;
+                }
+            }
+            Err(e) => warn!("Could not resolve cluster peer {}: {}", peer, e),
+        }
+    }
+
+    false
+}
+
+/// Answer the requests of another node, until it disconnects or the shutdown
+/// gets triggered.
+///
+/// # Arguments
+///
+/// * `socket` - The connection with the other node.
+/// * `state` - The server state.
+/// * `shutdown` - The listener which tells the session to stop.
+pub async fn serve_peer<S: AsyncRead + AsyncWrite + Unpin>(
+    socket: &mut S,
+    state: &State,
+    shutdown: &mut ShutdownListener,
+) -> Result<()> {
+    let raft = state
+        .raft
+        .as_ref()
+        .ok_or_else(|| anyhow!("cluster mode is disabled"))?;
+
+    loop {
+        let request = tokio::select! {
+            request = read_frame(socket) => request?,
+            _ = shutdown.recv() => return Ok(()),
+        };
+
+        let response = match request {
+            Request::Vote {
+                term,
+                candidate,
+                last_index,
+                last_term,
+            } => raft.handle_vote(term, candidate, last_index, last_term),
+            request @ Request::Append { .. } => raft.handle_append(state, request),
+            request @ Request::Snapshot { .. } => raft.handle_snapshot(state, request)?,
+        };
+
+        // A vote or stored entries only count once they survive a restart.
+        let persisted = tokio::select! {
+            persisted = raft.wait_persisted(raft.version()) => persisted,
+            _ = shutdown.recv() => return Ok(()),
+        };
+        if !persisted {
+            return Err(anyhow!("could not store the Raft state"));
+        }
+
+        write_frame(socket, &response).await?;
+    }
+}
+
+/// Build the next request for a follower, `None` if this node isn't the
+/// leader.
+///
+/// # Arguments
+///
+/// * `state` - The server state.
+/// * `raft` - The node.
+/// * `peer` - The address of the follower.
+fn next_request(state: &State, raft: &Raft, peer: &str) -> Result<Option<Request>> {
+    let db = state.db.lock().unwrap();
+    let node = raft.state.lock().unwrap();
+    if node.role != Role::Leader {
+        return Ok(None);
+    }
+
+    let next = node.next.get(peer).copied().unwrap_or_default();
+    if next == 0 || next < node.log.start {
+        // The copy shares the records, so it gets serialized without the locks.
+        let databases = db.clone();
+        let (term, index, index_term, commit) = (
+            node.term,
+            node.log.last_index(),
+            node.log.last_term(),
+            node.commit,
+        );
+        drop(node);
+        drop(db);
+
+        return Ok(Some(Request::Snapshot {
+            term,
+            leader: raft.id.clone(),
+            index,
+            index_term,
+            commit,
+            data: databases.to_snapshot()?,
+        }));
+    }
+
+    Ok(Some(Request::Append {
+        term: node.term,
+        leader: raft.id.clone(),
+        prev_index: next - 1,
+        prev_term: node.log.term_at(next - 1).unwrap_or_default(),
+        entries: node.log.entries_from(next),
+        commit: node.commit,
+    }))
+}
+
+/// Process the response of a follower. Returns `true` if the follower has
+/// more entries to receive.
+///
+/// # Arguments
+///
+/// * `raft` - The node.
+/// * `peer` - The address of the follower.
+/// * `request` - The request that got sent.
+/// * `response` - The response of the follower.
+fn handle_response(raft: &Raft, peer: &str, request: &Request, response: Response) -> bool {
+    let mut state = raft.state.lock().unwrap();
+
+    let (term, request_term) = match (&response, request) {
+        (Response::Append { term, .. }, Request::Append { term: sent, .. })
+        | (Response::Snapshot { term }, Request::Snapshot { term: sent, .. }) => (*term, *sent),
+        _ => return false,
+    };
+
+    if term > state.term {
+        raft.step_down(&mut state, term);
+        return false;
+    }
+
+    if state.role != Role::Leader || request_term != state.term {
+        return false;
+    }
+
+    let matched = match (response, request) {
+        (
+            Response::Append {
+                success: true,
+                last_index,
+                ..
+            },
+            _,
+        ) => last_index,
+        (Response::Snapshot { .. }, Request::Snapshot { index, .. }) => *index,
+        (
+            Response::Append {
+                needs_snapshot,
+                last_index,
+                ..
+            },
+            Request::Append { prev_index, .. },
+        ) => {
+            let next = match needs_snapshot {
+                true => 0,
+                false => (last_index + 1).min(*prev_index).max(1),
+            };
+            state.next.insert(peer.to_string(), next);
+            return true;
+        }
+        _ => return false,
+    };
+
+    state.matched.insert(peer.to_string(), matched);
+    state.next.insert(peer.to_string(), matched + 1);
+    raft.advance_commit(&mut state);
+
+    matched < state.log.last_index()
+}
+
+/// Replicate the log to a single follower while this node is the leader.
+///
+/// # Arguments
+///
+/// * `state` - The server state.
+/// * `peer` - The address of the follower.
+/// * `shutdown` - The listener which tells the task to stop.
+async fn replicate_to(state: State, peer: String, mut shutdown: ShutdownListener) {
+    let raft = state.raft.clone().expect("cluster mode is enabled");
+    let mut appended = raft.appended.subscribe();
+    let mut connection: Option<TcpStream> = None;
+
+    loop {
+        let mut more = false;
+
+        match next_request(&state, &raft, &peer) {
+            Ok(Some(request)) => {
+                if connection.is_none() {
+                    match connect(&state, &peer).await {
+                        Ok(socket) => connection = Some(socket),
+                        Err(e) => debug!("Could not connect to node {}: {}", peer, e),
+                    }
+                }
+
+                if let Some(socket) = connection.as_mut() {
+                    match call(socket, &request).await {
+                        Ok(response) => more = handle_response(&raft, &peer, &request, response),
+                        Err(e) => {
+                            debug!("Lost the connection with node {}: {}", peer, e);
+                            connection = None;
+                        }
+                    }
+                }
+            }
+            Ok(None) => connection = None,
+            Err(e) => error!("Could not create a snapshot for {}: {}", peer, e),
+        }
+
+        if more {
+            continue;
+        }
+
+        tokio::select! {
+            _ = sleep(HEARTBEAT_INTERVAL) => {},
+            _ = appended.changed() => {},
+            _ = shutdown.recv() => return,
+        }
+    }
+}
+
+/// Store the state of the node every time it changes, until the shutdown gets
+/// triggered. The state gets copied while it is locked, and written on a
+/// blocking thread.
+///
+/// # Arguments
+///
+/// * `state` - The server state.
+/// * `shutdown` - The listener which tells the task to stop.
+async fn persist(state: State, mut shutdown: ShutdownListener) {
+    let raft = state.raft.clone().expect("cluster mode is enabled");
+    let mut changed = raft.changed.subscribe();
+    let mut persisted = 0;
+
+    loop {
+        if raft.version() == persisted {
+            tokio::select! {
+                _ = changed.changed() => continue,
+                _ = shutdown.recv() => return,
+            }
+        }
+
+        let (version, term, leader, last_index, stored, databases) = {
+            let db = state.db.lock().unwrap();
+            let node = raft.state.lock().unwrap();
+            let stored = DurableState {
+                term: node.term,
+                voted_for: node.voted_for.clone(),
+                start: node.log.start,
+                start_term: node.log.start_term,
+                entries: node.log.entries.clone(),
+                applied: node.applied,
+                synced: node.synced,
+            };
+
+            let leader = node.role == Role::Leader;
+            (
+                node.version,
+                node.term,
+                leader,
+                node.log.last_index(),
+                stored,
+                db.clone(),
+            )
+        };
+
+        let written = match raft.path.clone() {
+            Some(path) => spawn_blocking(move || write_state(&path, &stored, &databases))
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|written| written),
+            None => Ok(()),
+        };
+
+        match written {
+            Ok(_) => {
+                persisted = version;
+                raft.stored(version, term, leader, last_index);
+            }
+            Err(e) => {
+                error!("Could not store the Raft state: {}", e);
+                raft.store_failed();
+
+                tokio::select! {
+                    _ = sleep(PERSIST_RETRY_DELAY) => {},
+                    _ = shutdown.recv() => return,
+                }
+            }
+        }
+    }
+}
+
+/// Start an election, and become the leader if a majority votes for this node.
+///
+/// # Arguments
+///
+/// * `state` - The server state.
+/// * `raft` - The node.
+async fn run_election(state: &State, raft: &Raft) {
+    let (term, last_index, last_term, version) = {
+        let mut node = raft.state.lock().unwrap();
+        node.role = Role::Candidate;
+        node.term += 1;
+        node.voted_for = Some(raft.id.clone());
+        node.leader = None;
+        node.deadline = election_deadline();
+        raft.touch(&mut node);
+
+        debug!("Starting an election for term {}", node.term);
+        (
+            node.term,
+            node.log.last_index(),
+            node.log.last_term(),
+            node.version,
+        )
+    };
+
+    // The node must not vote twice in the term, not even after a restart.
+    if !raft.wait_persisted(version).await {
+        let mut node = raft.state.lock().unwrap();
+        if node.term == term {
+            node.role = Role::Follower;
+        }
+        return;
+    }
+
+    let mut votes = JoinSet::new();
+    for peer in &raft.peers {
+        let (state, peer) = (state.clone(), peer.clone());
+        let request = Request::Vote {
+            term,
+            candidate: raft.id.clone(),
+            last_index,
+            last_term,
+        };
+
+        votes.spawn(async move {
+            let mut socket = connect(&state, &peer).await?;
+            call(&mut socket, &request).await
+        });
+    }
+
+    let mut granted = 1;
+    if granted >= raft.majority() {
+        raft.become_leader(state, term);
+        return;
+    }
+
+    while let Some(result) = votes.join_next().await {
+        let Ok(Ok(Response::Vote {
+            term: voter_term,
+            granted: vote,
+        })) = result
+        else {
+            continue;
+        };
+
+        if voter_term > term {
+            let mut node = raft.state.lock().unwrap();
+            raft.step_down(&mut node, voter_term);
+            return;
+        }
+
+        if vote {
+            granted += 1;
+            if granted >= raft.majority() {
+                raft.become_leader(state, term);
+                return;
+            }
+        }
+    }
+}
+
+/// Start the tasks of a cluster node: the election timer and the replication
+/// to every other node.
+///
+/// # Arguments
+///
+/// * `state` - The server state.
+/// * `shutdown` - The listener which tells the tasks to stop.
+pub fn start(state: State, mut shutdown: ShutdownListener) {
+    let Some(raft) = state.raft.clone() else {
+        return;
+    };
+
+    info!(
+        "Starting cluster node {} with peers {:?}",
+        raft.id, raft.peers
+    );
+
+    if let Some(databases) = raft.restored.lock().unwrap().take() {
+        *state.db.lock().unwrap() = databases;
+    }
+
+    tokio::spawn(persist(state.clone(), shutdown.clone()));
+    for peer in &raft.peers {
+        tokio::spawn(replicate_to(state.clone(), peer.clone(), shutdown.clone()));
+    }
+
+    tokio::spawn(async move {
+        loop {
+            let (role, deadline) = {
+                let node = raft.state.lock().unwrap();
+                (node.role, node.deadline)
+            };
+
+            if role != Role::Leader && Instant::now() >= deadline {
+                run_election(&state, &raft).await;
+                continue;
+            }
+
+            let wake = match role {
+                Role::Leader => Instant::now() + HEARTBEAT_INTERVAL,
+                _ => deadline,
+            };
+
+            tokio::select! {
+                _ = sleep_until(wake) => {},
+                _ = shutdown.recv() => return,
+            }
+        }
+    });
+}
+
+/// Check if this node may execute a query. Writes are only accepted by the
+/// leader, reads also by followers when stale reads are allowed. Returns the
+/// error response if the query is refused.
+///
+/// # Arguments
+///
+/// * `state` - The server state.
+/// * `query_type` - The type of the query.
+pub fn check_role(state: &State, query_type: QueryType) -> Option<&'static str> {
+    let raft = state.raft.as_ref()?;
+    let is_read = query_type.permission() == Some(Permission::Read);
+
+    if !query_type.is_write() && !is_read {
+        return None;
+    }
+
+    if raft.is_leader() || (is_read && state.settings.read().unwrap().cluster_stale_reads) {
+        return None;
+    }
+
+    Some("Error: Not the leader!")
+}
@@ -0,0 +1,116 @@
+use std::{collections::HashMap, sync::Mutex, time::Instant};
+
+use crate::{config::Permission, connection::Session, query::QueryType, State};
+
+/// A token bucket which refills at a fixed rate, and can hold up to one
+/// second worth of tokens.
+#[derive(Debug, Default, Clone)]
+pub struct TokenBucket {
+    tokens: f64,
+    /// When the bucket got refilled, `None` if it hasn't been used yet.
+    refilled: Option<Instant>,
+}
+
+impl TokenBucket {
+    /// Try to take a token out of the bucket. Returns false if the bucket is
+    /// empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate` - The amount of tokens added per second. (0 means unlimited)
+    pub fn try_take(&mut self, rate: u64) -> bool {
+        if rate == 0 {
+            return true;
+        }
+
+        let rate = rate as f64;
+        let now = Instant::now();
+        self.tokens = match self.refilled {
+            Some(refilled) => {
+                (self.tokens + now.duration_since(refilled).as_secs_f64() * rate).min(rate)
+            }
+            None => rate,
+        };
+        self.refilled = Some(now);
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+/// The token buckets of a single connection or user.
+#[derive(Debug, Default, Clone)]
+pub struct RateLimits {
+    read: TokenBucket,
+    write: TokenBucket,
+}
+
+impl RateLimits {
+    /// Take a token out of the bucket for a kind of query.
+    ///
+    /// # Arguments
+    ///
+    /// * `permission` - The permission the query requires. (read or write)
+    /// * `read_rate` - The allowed read queries per second.
+    /// * `write_rate` - The allowed write queries per second.
+    pub fn try_take(&mut self, permission: Permission, read_rate: u64, write_rate: u64) -> bool {
+        match permission {
+            Permission::Read => self.read.try_take(read_rate),
+            Permission::Write => self.write.try_take(write_rate),
+            Permission::Admin => true,
+        }
+    }
+}
+
+/// The rate limits shared by all connections of the same user.
+#[derive(Default)]
+pub struct UserRateLimits {
+    users: Mutex<HashMap<String, RateLimits>>,
+}
+
+/// Check if the session may execute a query without exceeding its
+/// connection or user rate limits. Only read and write queries are limited.
+///
+/// # Arguments
+///
+/// * `state` - The server state.
+/// * `session` - The session that sent the query.
+/// * `query_type` - The type of the query.
+pub fn check_rate_limit(state: &State, session: &mut Session, query_type: QueryType) -> bool {
+    let Some(permission) = query_type.permission() else {
+        return true;
+    };
+
+    let (client_read, client_write, user_read, user_write) = {
+        let settings = state.settings.read().unwrap();
+        (
+            settings.client_read_rate,
+            settings.client_write_rate,
+            settings.user_read_rate,
+            settings.user_write_rate,
+        )
+    };
+
+    if !session
+        .rate_limits
+        .try_take(permission, client_read, client_write)
+    {
+        return false;
+    }
+
+    match &session.user {
+        Some(user) => state
+            .user_rate_limits
+            .users
+            .lock()
+            .unwrap()
+            .entry(user.clone())
+            .or_default()
+            .try_take(permission, user_read, user_write),
+        None => true,
+    }
+}
@@ -0,0 +1,411 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+    sync::broadcast::{self, error::RecvError},
+    time::sleep,
+};
+
+use crate::{keyspace::Databases, query::QueryType, shutdown::ShutdownListener, State};
+
+/// The amount of mutations a replica can lag behind before it has to resync.
+pub const REPLICATION_CAPACITY: usize = 16 * 1024;
+
+/// How long a replica waits before it reconnects to its primary.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// The largest frame that gets sent or accepted, anything bigger is refused
+/// before it gets allocated. This also limits the size of a snapshot a
+/// replica or cluster node can receive.
+pub const MAX_FRAME_SIZE: usize = 1024 * 1024 * 1024;
+
+/// Broadcasts every mutation to the connected replicas.
+pub type Replication = broadcast::Sender<Mutation>;
+
+/// A change to the databases, which a replica applies to stay in sync.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mutation {
+    /// A record got created or overwritten.
+    Insert {
+        database: String,
+        key: String,
+        value: String,
+        ttl: String,
+    },
+    /// A record got dropped, expired or evicted.
+    Remove { database: String, key: String },
+    /// All records with a certain value got dropped.
+    RemoveValues { database: String, value: String },
+    /// All records of a database got removed.
+    FlushDb { database: String },
+    /// All records of all databases got removed.
+    FlushAll,
+    /// All databases got replaced by a snapshot.
+    Restore { snapshot: Vec<u8> },
+    /// The records of a database with these keys expired.
+    Expire { database: String, keys: Vec<String> },
+}
+
+/// What a primary sends to its replicas.
+#[derive(Debug, Serialize, Deserialize)]
+enum Frame {
+    /// All databases, in the snapshot format.
+    Snapshot(Vec<u8>),
+    Mutation(Mutation),
+}
+
+impl Mutation {
+    /// Apply the mutation to the databases.
+    ///
+    /// # Arguments
+    ///
+    /// * `databases` - The databases to change.
+    pub fn apply(&self, databases: &mut Databases) {
+        match self {
+            Mutation::Insert {
+                database,
+                key,
+                value,
+                ttl,
+            } => databases
+                .keyspace(database)
+                .insert(key.clone(), value.clone(), ttl.clone()),
+            Mutation::Remove { database, key } => {
+                if let Some(keyspace) = databases.get_mut(database) {
+                    keyspace.remove(key);
+                }
+            }
+            Mutation::RemoveValues { database, value } => {
+                if let Some(keyspace) = databases.get_mut(database) {
+                    keyspace.retain(|_, record| record.value != *value);
+                }
+            }
+            Mutation::FlushDb { database } => {
+                databases.remove(database);
+            }
+            Mutation::FlushAll => databases.clear(),
+            Mutation::Restore { snapshot } => match Databases::from_snapshot(snapshot) {
+                Ok(restored) => *databases = restored,
+                Err(e) => error!("Could not apply a restored snapshot: {}", e),
+            },
+            Mutation::Expire { database, keys } => {
+                if let Some(keyspace) = databases.get_mut(database) {
+                    for key in keys {
+                        keyspace.remove(key);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Send a mutation to all replicas, and append it to the Raft log if this
+/// node leads a cluster. This must be called while the databases are locked,
+/// so the replicas receive the mutations in the same order as they got
+/// applied.
+///
+/// # Arguments
+///
+/// * `state` - The server state.
+/// * `mutation` - Creates the mutation, only called if there are replicas or
+///   a cluster.
+pub fn replicate(state: &State, mutation: impl FnOnce() -> Mutation) {
+    let raft = state.raft.as_ref().filter(|raft| raft.is_leader());
+    if state.replication.receiver_count() == 0 && raft.is_none() {
+        return;
+    }
+
+    let mutation = mutation();
+    if let Some(raft) = raft {
+        raft.append(mutation.clone());
+    }
+
+    if state.replication.receiver_count() != 0 {
+        let _ = state.replication.send(mutation);
+    }
+}
+
+/// If the server replicates a primary, and therefore refuses writes.
+///
+/// # Arguments
+///
+/// * `state` - The server state.
+pub fn is_replica(state: &State) -> bool {
+    state.settings.read().unwrap().replica_of.is_some()
+}
+
+/// Write a length prefixed frame.
+///
+/// # Arguments
+///
+/// * `socket` - The stream to write to.
+/// * `frame` - The frame to write.
+pub(crate) async fn write_frame<S: AsyncWrite + Unpin, T: Serialize>(
+    socket: &mut S,
+    frame: &T,
+) -> Result<()> {
+    let data = bincode::serialize(frame)?;
+    if data.len() > MAX_FRAME_SIZE {
+        return Err(anyhow!("a frame of {} bytes is too large", data.len()));
+    }
+
+    socket.write_all(&(data.len() as u64).to_be_bytes()).await?;
+    socket.write_all(&data).await?;
+    Ok(())
+}
+
+/// Read a length prefixed frame.
+///
+/// # Arguments
+///
+/// * `socket` - The stream to read from.
+pub(crate) async fn read_frame<S: AsyncRead + Unpin, T: DeserializeOwned>(
+    socket: &mut S,
+) -> Result<T> {
+    let mut length = [0; 8];
+    socket.read_exact(&mut length).await?;
+
+    let length = u64::from_be_bytes(length);
+    if length > MAX_FRAME_SIZE as u64 {
+        return Err(anyhow!("a frame of {} bytes is too large", length));
+    }
+
+    // The buffer grows while the data arrives, a peer which announces a large
+    // frame without sending it doesn't get the memory reserved.
+    let mut data = Vec::new();
+    socket.take(length).read_to_end(&mut data).await?;
+    if data.len() as u64 != length {
+        return Err(anyhow!("the connection closed in the middle of a frame"));
+    }
+
+    Ok(bincode::deserialize(&data)?)
+}
+
+/// Send a snapshot of all databases to a replica, followed by every mutation.
+/// This lasts until the replica disconnects, lags too far behind or the
+/// shutdown gets triggered.
+///
+/// # Arguments
+///
+/// * `socket` - The replica its stream.
+/// * `state` - The server state.
+/// * `shutdown` - The listener which tells the session to stop.
+pub async fn serve_replica<S: AsyncRead + AsyncWrite + Unpin>(
+    socket: &mut S,
+    state: &State,
+    shutdown: &mut ShutdownListener,
+) -> Result<()> {
+    // Subscribing while the databases are locked guarantees that the replica
+    // receives every mutation after the snapshot, and none before it.
+    let (databases, mut mutations) = {
+        let db = state.db.lock().unwrap();
+        (db.clone(), state.replication.subscribe())
+    };
+    let snapshot = databases.to_snapshot()?;
+
+    write_frame(socket, &Frame::Snapshot(snapshot)).await?;
+    let mut buf = [0; 64];
+
+    loop {
+        let mutation = tokio::select! {
+            mutation = mutations.recv() => match mutation {
+                Ok(mutation) => mutation,
+                Err(RecvError::Lagged(missed)) => {
+                    return Err(anyhow!("the replica lagged {} mutations behind", missed))
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            // The replica never sends anything, but a read notices if it
+            // disconnected.
+            read = socket.read(&mut buf) => match read {
+                Ok(0) | Err(_) => return Ok(()),
+                Ok(_) => continue,
+            },
+            _ = shutdown.recv() => return Ok(()),
+        };
+
+        write_frame(socket, &Frame::Mutation(mutation)).await?;
+    }
+}
+
+/// Connect to the primary and apply its snapshot and mutations, until the
+/// connection gets lost or the shutdown gets triggered.
+///
+/// # Arguments
+///
+/// * `state` - The server state.
+/// * `primary` - The address of the primary.
+/// * `shutdown` - The listener which tells the replica to stop.
+async fn sync_with(state: &State, primary: &str, shutdown: &mut ShutdownListener) -> Result<()> {
+    let mut socket = connect_to(state, primary, QueryType::Sync).await?;
+
+    loop {
+        let frame = tokio::select! {
+            frame = read_frame::<_, Frame>(&mut socket) => frame?,
+            _ = shutdown.recv() => return Ok(()),
+        };
+
+        let mut db = state.db.lock().unwrap();
+        match frame {
+            Frame::Snapshot(snapshot) => {
+                *db = Databases::from_snapshot(&snapshot)?;
+                info!("Synced {} records from primary {}", db.len(), primary);
+            }
+            Frame::Mutation(mutation) => {
+                mutation.apply(&mut db);
+                // Replicas of this replica receive the same mutations.
+                replicate(state, || mutation);
+            }
+        }
+
+        *state.changed.lock().unwrap() += 1;
+    }
+}
+
+/// Connect to another server, authenticate with the configured primary
+/// credentials and execute a query which switches the connection to a
+/// server-to-server protocol. (e.g. `SYNC`)
+///
+/// # Arguments
+///
+/// * `state` - The server state, its settings contain the credentials.
+/// * `address` - The address of the other server.
+/// * `query_type` - The query which switches the protocol.
+pub(crate) async fn connect_to(
+    state: &State,
+    address: &str,
+    query_type: QueryType,
+) -> Result<TcpStream> {
+    let mut socket = TcpStream::connect(address).await?;
+
+    let credentials = {
+        let settings = state.settings.read().unwrap();
+        settings
+            .primary_user
+            .clone()
+            .zip(settings.primary_password.clone())
+    };
+
+    // Bitwise queries don't require the credentials to be escaped.
+    socket.write_all(b"QUERY TYPE BITWISE;").await?;
+    expect_ok(&mut socket).await?;
+
+    if let Some((user, password)) = credentials {
+        socket
+            .write_all(format!("8{}\0{}", user, password).as_bytes())
+            .await?;
+        expect_ok(&mut socket).await?;
+    }
+
+    socket.write_all(&[query_type.as_byte()]).await?;
+    expect_ok(&mut socket).await?;
+
+    Ok(socket)
+}
+
+/// Read the response to a query, and fail if it isn't "Ok".
+///
+/// # Arguments
+///
+/// * `socket` - The connection with the other server.
+async fn expect_ok(socket: &mut TcpStream) -> Result<()> {
+    let mut response = [0; 2];
+    socket.read_exact(&mut response).await?;
+
+    if &response != b"Ok" {
+        let mut rest = vec![0; 256];
+        let size = socket.read(&mut rest).await.unwrap_or_default();
+        return Err(anyhow!(
+            "the server responded with: {}{}",
+            String::from_utf8_lossy(&response),
+            String::from_utf8_lossy(&rest[..size])
+        ));
+    }
+
+    Ok(())
+}
+
+/// Change the role of the server at runtime. "NO ONE" promotes a replica to a
+/// primary, an address (host:port) turns the server into a replica of it.
+///
+/// # Arguments
+///
+/// * `state` - The server state.
+/// * `target` - "NO ONE" or the address of the new primary.
+pub fn replica_of(state: &State, target: &str) -> String {
+    if state.raft.is_some() {
+        return "Error: Cluster nodes can't be replicas!".to_string();
+    }
+
+    let primary = if target.trim().eq_ignore_ascii_case("NO ONE") {
+        None
+    } else if target.contains(':') {
+        Some(target.to_string())
+    } else {
+        return "Error: Invalid primary address!".to_string();
+    };
+
+    {
+        let mut settings = state.settings.write().unwrap();
+        if settings.replica_of == primary {
+            return "Ok".to_string();
+        }
+        settings.replica_of = primary.clone();
+    }
+
+    match &primary {
+        Some(primary) => info!("Now replicating primary {}", primary),
+        None => info!("Promoted to primary"),
+    }
+
+    state.role_changed.notify_one();
+    "Ok".to_string()
+}
+
+/// Replicate the primary the server is configured to follow. When the
+/// connection gets lost the replica reconnects, and resyncs all data. A
+/// `REPLICAOF` query switches to another primary, or stops replicating.
+///
+/// # Arguments
+///
+/// * `state` - The server state, its settings contain the primary address.
+/// * `shutdown` - The listener which tells the replica to stop.
+pub fn follow(state: State, mut shutdown: ShutdownListener) {
+    tokio::spawn(async move {
+        loop {
+            let replica_of = state.settings.read().unwrap().replica_of.clone();
+            let primary = match replica_of {
+                Some(primary) => primary,
+                None => {
+                    tokio::select! {
+                        _ = state.role_changed.notified() => continue,
+                        _ = shutdown.recv() => return,
+                    }
+                }
+            };
+
+            info!("Replicating primary {}", primary);
+            let result = tokio::select! {
+                result = sync_with(&state, &primary, &mut shutdown) => result,
+                _ = state.role_changed.notified() => continue,
+            };
+
+            match result {
+                Ok(_) => return,
+                Err(e) => warn!(
+                    "Lost the connection with primary {}: {}, resyncing in {:?}",
+                    primary, e, RECONNECT_DELAY
+                ),
+            }
+
+            tokio::select! {
+                _ = sleep(RECONNECT_DELAY) => {},
+                _ = state.role_changed.notified() => {},
+                _ = shutdown.recv() => return,
+            }
+        }
+    });
+}
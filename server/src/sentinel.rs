@@ -0,0 +1,527 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::{sleep, timeout},
+};
+
+use crate::{
+    query::QueryType,
+    shutdown::{wait_for_signal, Shutdown, ShutdownListener},
+};
+
+/// The maximum size of a request or response between sentinels and clients.
+const MAX_MESSAGE_SIZE: u64 = 1024;
+
+/// The maximum size of a response of a monitored server.
+const MAX_RESPONSE_SIZE: usize = 16 * 1024;
+
+/// Watch a primary, and promote one of its replicas once enough sentinels
+/// agree that the primary is down.
+#[derive(clap::Args, Debug, Clone)]
+pub struct SentinelArgs {
+    /// Print help.
+    #[clap(long, action = clap::ArgAction::Help)]
+    pub help: Option<bool>,
+
+    /// The address the sentinel listens on, for clients and other sentinels.
+    #[clap(long, env = "FFLY_SENTINEL_LISTEN", default_value = "127.0.0.1:46700")]
+    pub listen: String,
+
+    /// The address (host:port) of the primary to watch.
+    #[clap(long, env = "FFLY_SENTINEL_PRIMARY")]
+    pub primary: String,
+
+    /// The address of a replica of the primary, which can get promoted.
+    /// (can be repeated)
+    #[clap(long = "replica", value_name = "REPLICA")]
+    pub replicas: Vec<String>,
+
+    /// The address of another sentinel watching the same primary.
+    /// (can be repeated)
+    #[clap(long = "peer", value_name = "PEER")]
+    pub peers: Vec<String>,
+
+    /// The amount of sentinels (including this one) which must consider the
+    /// primary down before it gets replaced. [default: a majority]
+    #[clap(long, env = "FFLY_SENTINEL_QUORUM")]
+    pub quorum: Option<usize>,
+
+    /// Consider a server down when it didn't respond for N milliseconds.
+    #[clap(long, env = "FFLY_SENTINEL_DOWN_AFTER", default_value_t = 5000)]
+    pub down_after: u64,
+
+    /// The user the sentinel authenticates as, it needs the admin permission.
+    #[clap(long, env = "FFLY_SENTINEL_USER")]
+    pub user: Option<String>,
+
+    /// The password the sentinel authenticates with.
+    #[clap(long, env = "FFLY_SENTINEL_PASSWORD")]
+    pub password: Option<String>,
+}
+
+/// What a sentinel believes about the monitored servers.
+#[derive(Debug)]
+struct View {
+    /// Incremented by every failover, the view with the highest epoch wins.
+    epoch: u64,
+    /// The highest epoch this sentinel voted in.
+    voted_epoch: u64,
+    primary: String,
+    /// Every known server, the primary included.
+    nodes: Vec<String>,
+    /// Since when the primary doesn't respond.
+    down_since: Option<Instant>,
+}
+
+struct Sentinel {
+    /// The address of the sentinel, used as its identity in elections.
+    id: String,
+    args: SentinelArgs,
+    view: Mutex<View>,
+}
+
+impl Sentinel {
+    /// How long a server may not respond before it is considered down.
+    fn down_after(&self) -> Duration {
+        Duration::from_millis(self.args.down_after)
+    }
+
+    /// The amount of sentinels which must agree the primary is down.
+    fn quorum(&self) -> usize {
+        let total = self.args.peers.len() + 1;
+        self.args.quorum.unwrap_or(total / 2 + 1)
+    }
+
+    /// If this sentinel considers the primary down.
+    fn is_down(&self, view: &View) -> bool {
+        view.down_since
+            .is_some_and(|since| since.elapsed() >= self.down_after())
+    }
+
+    /// Respond to a request of a client or another sentinel.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The NUL delimited request.
+    fn respond(&self, request: &str) -> String {
+        let parts: Vec<&str> = request.trim().split('\0').collect();
+        let mut view = self.view.lock().unwrap();
+
+        match parts.as_slice() {
+            ["PRIMARY"] => view.primary.clone(),
+            ["STATE"] => format!(
+                "{}\0{}\0{}",
+                view.epoch,
+                view.primary,
+                self.is_down(&view) as u8
+            ),
+            ["VOTE", epoch, candidate] => match epoch.parse::<u64>() {
+                Ok(epoch) if epoch > view.epoch && epoch > view.voted_epoch => {
+                    view.voted_epoch = epoch;
+                    info!("Voted for sentinel {} in epoch {}", candidate, epoch);
+                    "1".to_string()
+                }
+                _ => "0".to_string(),
+            },
+            _ => "Error: Unknown request!".to_string(),
+        }
+    }
+
+    /// Execute a bitwise query on a monitored server.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of the server.
+    /// * `query` - The bitwise query.
+    async fn query(&self, address: &str, query: &[u8]) -> Result<String> {
+        let credentials = self.args.user.as_ref().zip(self.args.password.as_ref());
+
+        let querying = async {
+            let mut socket = TcpStream::connect(address).await?;
+
+            // Bitwise queries don't require the arguments to be escaped.
+            expect_ok(execute(&mut socket, b"QUERY TYPE BITWISE;").await?)?;
+            if let Some((user, password)) = credentials {
+                let auth = format!("8{}\0{}", user, password);
+                expect_ok(execute(&mut socket, auth.as_bytes()).await?)?;
+            }
+
+            execute(&mut socket, query).await
+        };
+
+        timeout(self.down_after(), querying)
+            .await
+            .map_err(|_| anyhow!("timed out"))?
+    }
+
+    /// Get the role of a server and the primary it replicates, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of the server.
+    async fn role(&self, address: &str) -> Result<(String, String)> {
+        let info = self.query(address, &[QueryType::Info.as_byte()]).await?;
+        let field = |name: &str| {
+            info.lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        match field("role") {
+            role if role.is_empty() => Err(anyhow!("unexpected response: {}", info)),
+            role => Ok((role, field("primary"))),
+        }
+    }
+
+    /// Send a `REPLICAOF` query to a server.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of the server.
+    /// * `primary` - The new primary of the server, or "NO ONE".
+    async fn replica_of(&self, address: &str, primary: &str) -> Result<()> {
+        let query = format!("{}{}", QueryType::ReplicaOf.as_byte() as char, primary);
+        expect_ok(self.query(address, query.as_bytes()).await?)
+    }
+
+    /// Send a request to another sentinel.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - The address of the other sentinel.
+    /// * `request` - The NUL delimited request.
+    async fn ask(&self, peer: &str, request: &str) -> Result<String> {
+        timeout(self.down_after(), ask(peer, request))
+            .await
+            .map_err(|_| anyhow!("timed out"))?
+    }
+
+    /// Adopt the views of other sentinels with a higher epoch, and count how
+    /// many of them consider the current primary down.
+    async fn gather_peers(&self) -> usize {
+        let mut down = 0;
+
+        for peer in &self.args.peers {
+            let state = match self.ask(peer, "STATE").await {
+                Ok(state) => state,
+                Err(e) => {
+                    debug!("Could not reach sentinel {}: {}", peer, e);
+                    continue;
+                }
+            };
+
+            let parts: Vec<&str> = state.split('\0').collect();
+            let (epoch, primary, is_down) = match parts.as_slice() {
+                [epoch, primary, is_down] => match epoch.parse::<u64>() {
+                    Ok(epoch) => (epoch, *primary, *is_down == "1"),
+                    Err(_) => continue,
+                },
+                _ => continue,
+            };
+
+            let mut view = self.view.lock().unwrap();
+            if epoch > view.epoch {
+                info!(
+                    "Sentinel {} switched to primary {} in epoch {}",
+                    peer, primary, epoch
+                );
+                view.epoch = epoch;
+                view.primary = primary.to_string();
+                view.down_since = None;
+                if !view.nodes.iter().any(|node| node == primary) {
+                    view.nodes.push(primary.to_string());
+                }
+            } else if epoch == view.epoch && primary == view.primary && is_down {
+                down += 1;
+            }
+        }
+
+        down
+    }
+
+    /// Make every other server replicate the current primary. A former
+    /// primary that comes back gets turned into a replica this way.
+    ///
+    /// # Arguments
+    ///
+    /// * `primary` - The address of the current primary.
+    async fn reconfigure(&self, primary: &str) {
+        let nodes = self.view.lock().unwrap().nodes.clone();
+
+        for node in nodes.iter().filter(|node| *node != primary) {
+            match self.role(node).await {
+                Ok((role, replicating)) if role == "primary" || replicating != primary => {
+                    info!("Making {} a replica of {}", node, primary);
+                    if let Err(e) = self.replica_of(node, primary).await {
+                        warn!("Could not reconfigure {}: {}", node, e);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => debug!("Could not reach {}: {}", node, e),
+            }
+        }
+    }
+
+    /// Try to get elected by a majority of the sentinels for a new epoch.
+    /// Returns the epoch if this sentinel won the election.
+    async fn elect(&self) -> Option<u64> {
+        let epoch = {
+            let mut view = self.view.lock().unwrap();
+            view.voted_epoch = view.voted_epoch.max(view.epoch) + 1;
+            view.voted_epoch
+        };
+
+        let mut votes = 1;
+        for peer in &self.args.peers {
+            let request = format!("VOTE\0{}\0{}", epoch, self.id);
+            if let Ok(response) = self.ask(peer, &request).await {
+                votes += (response == "1") as usize;
+            }
+        }
+
+        let total = self.args.peers.len() + 1;
+        if votes > total / 2 {
+            info!("Elected to lead the failover of epoch {}", epoch);
+            Some(epoch)
+        } else {
+            None
+        }
+    }
+
+    /// Promote a reachable replica, and make the other servers replicate it.
+    ///
+    /// # Arguments
+    ///
+    /// * `epoch` - The epoch this sentinel got elected in.
+    /// * `old_primary` - The address of the primary that went down.
+    async fn failover(&self, epoch: u64, old_primary: &str) -> Result<()> {
+        let nodes = self.view.lock().unwrap().nodes.clone();
+
+        for candidate in nodes.iter().filter(|node| *node != old_primary) {
+            if let Err(e) = self.role(candidate).await {
+                debug!("Not promoting {}: {}", candidate, e);
+                continue;
+            }
+
+            self.replica_of(candidate, "NO ONE").await?;
+
+            {
+                let mut view = self.view.lock().unwrap();
+                view.epoch = epoch;
+                view.primary = candidate.clone();
+                view.down_since = None;
+            }
+
+            warn!(
+                "Promoted {} to primary, replacing {} (epoch {})",
+                candidate, old_primary, epoch
+            );
+            self.reconfigure(candidate).await;
+            return Ok(());
+        }
+
+        Err(anyhow!("no replica is reachable"))
+    }
+
+    /// Check the primary once, and replace it if enough sentinels consider
+    /// it down.
+    async fn check(&self) {
+        let peers_down = self.gather_peers().await;
+        let primary = self.view.lock().unwrap().primary.clone();
+
+        if self.role(&primary).await.is_ok() {
+            self.view.lock().unwrap().down_since = None;
+            self.reconfigure(&primary).await;
+            return;
+        }
+
+        let is_down = {
+            let mut view = self.view.lock().unwrap();
+            if view.primary != primary {
+                return;
+            }
+            view.down_since.get_or_insert_with(Instant::now);
+            self.is_down(&view)
+        };
+
+        if !is_down {
+            return;
+        }
+
+        let agreeing = peers_down + 1;
+        if agreeing < self.quorum() {
+            debug!(
+                "Primary {} is down according to {}/{} sentinels",
+                primary,
+                agreeing,
+                self.quorum()
+            );
+            return;
+        }
+
+        match self.elect().await {
+            Some(epoch) => {
+                if let Err(e) = self.failover(epoch, &primary).await {
+                    error!("Could not replace primary {}: {}", primary, e);
+                }
+            }
+            // Back off a random time, so the sentinels don't keep splitting
+            // their votes.
+            None => sleep(self.down_after().mul_f64(fastrand::f64())).await,
+        }
+    }
+}
+
+/// Send a query to a monitored server and read its response.
+///
+/// # Arguments
+///
+/// * `socket` - The connection with the server.
+/// * `query` - The query to send.
+async fn execute(socket: &mut TcpStream, query: &[u8]) -> Result<String> {
+    socket.write_all(query).await?;
+
+    let mut buf = vec![0; MAX_RESPONSE_SIZE];
+    let size = socket.read(&mut buf).await?;
+    if size == 0 {
+        return Err(anyhow!("the connection got closed"));
+    }
+
+    Ok(String::from_utf8_lossy(&buf[..size]).to_string())
+}
+
+/// Fail if a response of a monitored server isn't "Ok".
+fn expect_ok(response: String) -> Result<()> {
+    match response.as_str() {
+        "Ok" => Ok(()),
+        _ => Err(anyhow!("unexpected response: {}", response)),
+    }
+}
+
+/// Send a request to a sentinel and read its response.
+///
+/// # Arguments
+///
+/// * `sentinel` - The address of the sentinel.
+/// * `request` - The NUL delimited request.
+pub async fn ask(sentinel: &str, request: &str) -> Result<String> {
+    let mut socket = TcpStream::connect(sentinel).await?;
+    socket.write_all(request.as_bytes()).await?;
+    socket.shutdown().await?;
+
+    let mut response = String::new();
+    socket
+        .take(MAX_MESSAGE_SIZE)
+        .read_to_string(&mut response)
+        .await?;
+    Ok(response)
+}
+
+/// Answer a single request of a client or another sentinel.
+///
+/// # Arguments
+///
+/// * `socket` - The connection.
+/// * `sentinel` - The sentinel.
+async fn handle_request(mut socket: TcpStream, sentinel: Arc<Sentinel>) -> Result<()> {
+    let mut request = String::new();
+    timeout(
+        sentinel.down_after(),
+        (&mut socket)
+            .take(MAX_MESSAGE_SIZE)
+            .read_to_string(&mut request),
+    )
+    .await??;
+
+    socket
+        .write_all(sentinel.respond(&request).as_bytes())
+        .await?;
+    Ok(())
+}
+
+/// Watch the primary and answer requests, until the shutdown gets triggered.
+///
+/// # Arguments
+///
+/// * `listener` - The listener for clients and other sentinels.
+/// * `args` - The sentinel settings.
+/// * `shutdown` - The listener which tells the sentinel to stop.
+pub async fn serve(
+    listener: TcpListener,
+    args: SentinelArgs,
+    mut shutdown: ShutdownListener,
+) -> Result<()> {
+    let mut nodes = vec![args.primary.clone()];
+    nodes.extend(args.replicas.iter().cloned());
+
+    let sentinel = Arc::new(Sentinel {
+        id: listener.local_addr()?.to_string(),
+        view: Mutex::new(View {
+            epoch: 0,
+            voted_epoch: 0,
+            primary: args.primary.clone(),
+            nodes,
+            down_since: None,
+        }),
+        args,
+    });
+
+    info!(
+        "Sentinel {} watching primary {} (quorum {})",
+        sentinel.id,
+        sentinel.args.primary,
+        sentinel.quorum()
+    );
+
+    let interval = (sentinel.down_after() / 5).max(Duration::from_millis(10));
+    let mut checking = shutdown.clone();
+    let watcher = sentinel.clone();
+    tokio::spawn(async move {
+        loop {
+            watcher.check().await;
+            tokio::select! {
+                _ = sleep(interval) => {},
+                _ = checking.recv() => return,
+            }
+        }
+    });
+
+    loop {
+        let socket = tokio::select! {
+            accepted = listener.accept() => accepted?.0,
+            _ = shutdown.recv() => return Ok(()),
+        };
+
+        let sentinel = sentinel.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_request(socket, sentinel).await {
+                debug!("Could not answer a request: {}", e);
+            }
+        });
+    }
+}
+
+/// Run a sentinel until the process receives a shutdown signal.
+///
+/// # Arguments
+///
+/// * `args` - The sentinel settings.
+pub async fn run(args: SentinelArgs) -> Result<()> {
+    let listener = TcpListener::bind(&args.listen).await?;
+    let shutdown = Shutdown::new();
+
+    tokio::select! {
+        result = serve(listener, args, shutdown.subscribe()) => result,
+        _ = wait_for_signal() => {
+            info!("Sentinel stopped");
+            Ok(())
+        },
+    }
+}
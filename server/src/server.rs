@@ -0,0 +1,269 @@
+use std::{io, net::SocketAddr, time::Duration};
+
+use tokio::task::JoinSet;
+
+#[cfg(unix)]
+use crate::{config::reload_on_hangup, Args};
+use crate::{
+    config::{Config, Persistence},
+    connection::accept_connections,
+    database::{detect_changes, detect_expirations, load_db},
+    listener::Listener,
+    metrics, raft,
+    replication::follow,
+    shutdown::Shutdown,
+    State,
+};
+
+/// Builds a server which runs in the current process, e.g. for tests.
+///
+/// ```no_run
+/// # async fn example() -> std::io::Result<()> {
+/// let server = ffly::Server::default()
+///     .bind("127.0.0.1", 0)
+///     .snapshot("/tmp/ffly-test.bincode")
+///     .start()
+///     .await?;
+///
+/// println!("Listening on {}", server.local_addr().unwrap());
+/// server.shutdown().await;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Server {
+    config: Config,
+    /// The flags the configuration got loaded from, to reload it on a SIGHUP.
+    #[cfg(unix)]
+    args: Option<Args>,
+}
+
+impl Server {
+    /// Create a server with its settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The server settings.
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            #[cfg(unix)]
+            args: None,
+        }
+    }
+
+    /// Set the TCP address to listen on.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - The host (ip) to bind to.
+    /// * `port` - The port to bind to, 0 picks a free port.
+    pub fn bind(mut self, host: &str, port: u16) -> Self {
+        self.config.host = host.to_string();
+        self.config.port = port;
+        self
+    }
+
+    /// Set the snapshot file the records get loaded from and written to.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the snapshot file.
+    pub fn snapshot(mut self, path: &str) -> Self {
+        self.config.out = path.to_string();
+        self
+    }
+
+    /// Set how often changes get written to the snapshot file.
+    ///
+    /// # Arguments
+    ///
+    /// * `seconds` - The interval between checks for changes.
+    pub fn save_every(mut self, seconds: u64) -> Self {
+        self.config.save_every = seconds;
+        self
+    }
+
+    /// Set how the records get persisted, e.g. `Persistence::Disabled` for a
+    /// server which never touches the disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `persistence` - If the snapshot gets loaded and written.
+    pub fn persistence(mut self, persistence: Persistence) -> Self {
+        self.config.persistence = persistence;
+        self
+    }
+
+    /// Reload the settings from the flags (and the config file they point
+    /// to) every time the process receives a SIGHUP.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The command line flags the settings got loaded from.
+    #[cfg(unix)]
+    pub fn reload_on_hangup(mut self, args: Args) -> Self {
+        self.args = Some(args);
+        self
+    }
+
+    /// The settings the server will start with.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Bind the listeners, load the snapshot and start accepting clients and
+    /// the background tasks.
+    pub async fn start(self) -> io::Result<ServerHandle> {
+        let config = self.config;
+        config
+            .validate()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+
+        let mut listeners = Vec::new();
+
+        if let Some(path) = &config.socket {
+            #[cfg(unix)]
+            {
+                listeners.push(Listener::bind_unix(path, config.socket_permissions)?);
+                info!("Binding connection to unix:{}", path);
+            }
+
+            #[cfg(not(unix))]
+            warn!(
+                "Unix sockets are not supported on this platform, ignoring {}",
+                path
+            );
+        }
+
+        let mut local_addr = None;
+        if !config.no_tcp || cfg!(not(unix)) {
+            let bind_addr = format!("{}:{}", config.host, config.port);
+            let listener = Listener::bind_tcp(&bind_addr).await?;
+            local_addr = listener.local_addr();
+            info!("Binding connection to {}", listener.describe());
+            listeners.push(listener);
+        }
+
+        let state = State::new(config.clone());
+        let connections = Shutdown::new();
+        let background = Shutdown::new();
+
+        if config.persistence.loads() {
+            load_db(state.db.clone(), &config.out);
+        }
+
+        match config.persistence {
+            Persistence::Snapshot => detect_changes(state.clone(), background.subscribe()),
+            Persistence::ReadOnly => info!("The changes won't be written to {}", config.out),
+            Persistence::Disabled => {
+                info!("Persistence is disabled, nothing gets loaded or written")
+            }
+        }
+        detect_expirations(state.clone(), background.subscribe());
+        follow(state.clone(), background.subscribe());
+        raft::start(state.clone(), background.subscribe());
+
+        #[cfg(unix)]
+        if let Some(args) = self.args {
+            reload_on_hangup(state.settings.clone(), args);
+        }
+
+        if let Some(address) = &config.metrics_addr {
+            tokio::spawn(metrics::serve(
+                address.clone(),
+                state.clone(),
+                connections.subscribe(),
+            ));
+        }
+
+        let mut accepting = JoinSet::new();
+        for listener in listeners {
+            accepting.spawn(accept_connections(
+                listener,
+                state.clone(),
+                connections.subscribe(),
+            ));
+        }
+
+        Ok(ServerHandle {
+            local_addr,
+            state,
+            connections,
+            background,
+            accepting,
+        })
+    }
+}
+
+/// A running server, returned by `Server::start`.
+pub struct ServerHandle {
+    local_addr: Option<SocketAddr>,
+    state: State,
+    connections: Shutdown,
+    background: Shutdown,
+    accepting: JoinSet<io::Result<usize>>,
+}
+
+impl ServerHandle {
+    /// The TCP address the server is bound to, with the picked port if it
+    /// got bound to port 0. `None` if it only listens on a Unix socket.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// The state the connections and background tasks share.
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Wait until the server stops accepting clients by itself, which only
+    /// happens when a listener fails.
+    pub async fn wait(&mut self) -> io::Result<()> {
+        match self.accepting.join_next().await {
+            Some(Ok(result)) => result.map(|_| ()),
+            Some(Err(e)) => Err(io::Error::other(e)),
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Stop accepting clients, let the connections finish their queries and
+    /// write the final snapshot. Returns the amount of connections the server
+    /// accepted since it started.
+    pub async fn shutdown(mut self) -> usize {
+        let shutdown_timeout = self.state.settings.read().unwrap().shutdown_timeout;
+        let timeout = Duration::from_secs(shutdown_timeout);
+
+        info!("Shutting down, no longer accepting new connections...");
+        let drained = self.connections.shutdown(timeout).await;
+
+        let mut accepted = 0;
+        while let Some(result) = self.accepting.join_next().await {
+            if let Ok(Ok(count)) = result {
+                accepted += count;
+            }
+        }
+
+        if !drained {
+            warn!(
+                "Not all connections finished within {} seconds, closing them",
+                shutdown_timeout
+            );
+        }
+
+        if !self.background.shutdown(timeout).await {
+            error!(
+                "The final snapshot did not finish within {} seconds",
+                shutdown_timeout
+            );
+        }
+
+        info!(
+            "Firefly stopped after {:.2?}, served {} connection(s) since the start",
+            self.state.started.elapsed(),
+            accepted
+        );
+
+        accepted
+    }
+}
@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use tokio::{
+    sync::{mpsc, watch},
+    time::timeout,
+};
+
+/// Coordinates the shutdown of a group of tasks. (e.g. all connections)
+///
+/// Every task in the group holds a `ShutdownListener`. Once the shutdown gets
+/// triggered the tasks get notified, and the group is drained when all the
+/// listeners have been dropped.
+pub struct Shutdown {
+    notify: watch::Sender<bool>,
+    guard: mpsc::Sender<()>,
+    drained: mpsc::Receiver<()>,
+}
+
+/// The handle a task uses to know when it should stop.
+#[derive(Clone)]
+pub struct ShutdownListener {
+    receiver: watch::Receiver<bool>,
+    _guard: mpsc::Sender<()>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (notify, _) = watch::channel(false);
+        let (guard, drained) = mpsc::channel(1);
+
+        Self {
+            notify,
+            guard,
+            drained,
+        }
+    }
+
+    /// Add a new task to the group.
+    pub fn subscribe(&self) -> ShutdownListener {
+        ShutdownListener {
+            receiver: self.notify.subscribe(),
+            _guard: self.guard.clone(),
+        }
+    }
+
+    /// Notify all tasks in the group to stop and wait for them to finish.
+    /// Returns `false` if not all tasks finished within the timeout.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The maximum time to wait for the tasks to finish.
+    pub async fn shutdown(self, limit: Duration) -> bool {
+        let Self {
+            notify,
+            guard,
+            mut drained,
+        } = self;
+
+        let _ = notify.send(true);
+        drop(guard);
+
+        timeout(limit, drained.recv()).await.is_ok()
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShutdownListener {
+    /// Wait until the shutdown has been triggered.
+    pub async fn recv(&mut self) {
+        while !*self.receiver.borrow() {
+            if self.receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Wait until the process receives a termination signal. (SIGTERM or SIGINT)
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate()).expect("Could not listen for SIGTERM");
+        tokio::select! {
+            _ = terminate.recv() => info!("Received SIGTERM"),
+            _ = tokio::signal::ctrl_c() => info!("Received SIGINT"),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        info!("Received CTRL-C");
+    }
+}
@@ -0,0 +1,123 @@
+use std::{collections::VecDeque, fmt::Display, sync::Mutex, time::Duration};
+
+use crate::{database::current_epoch, State};
+
+/// A query or background task which took longer than the slow log threshold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlowLogEntry {
+    /// A unique, incrementing identifier.
+    pub id: u64,
+    /// When the entry got logged. (seconds since the UNIX epoch)
+    pub timestamp: u64,
+    /// How long the query or task took.
+    pub duration: Duration,
+    /// The query type or the name of the background task.
+    pub kind: String,
+    /// The key (first argument) of the query, if any.
+    pub key: Option<String>,
+}
+
+/// A bounded in-memory log of slow queries and background tasks. When the log
+/// is full the oldest entry gets dropped.
+#[derive(Default)]
+pub struct SlowLog {
+    entries: Mutex<VecDeque<SlowLogEntry>>,
+    next_id: Mutex<u64>,
+}
+
+impl SlowLog {
+    /// Add an entry to the log if it took longer than the threshold.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The query type or the name of the background task.
+    /// * `key` - The key (first argument) of the query, if any.
+    /// * `duration` - How long the query or task took.
+    /// * `threshold` - The minimum duration for an entry to be logged.
+    /// * `max_len` - The maximum amount of entries the log keeps. (0 disables it)
+    pub fn record(
+        &self,
+        kind: impl Display,
+        key: Option<&str>,
+        duration: Duration,
+        threshold: Duration,
+        max_len: usize,
+    ) {
+        if duration < threshold || max_len == 0 {
+            return;
+        }
+
+        let id = {
+            let mut next_id = self.next_id.lock().unwrap();
+            *next_id += 1;
+            *next_id
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.push_front(SlowLogEntry {
+            id,
+            timestamp: current_epoch(),
+            duration,
+            kind: kind.to_string(),
+            key: key.map(str::to_string),
+        });
+        entries.truncate(max_len);
+    }
+
+    /// Get all entries, the most recent one first.
+    pub fn entries(&self) -> Vec<SlowLogEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Remove all entries.
+    pub fn reset(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+/// Format the slow log as a response. The first line contains the amount of
+/// entries, every next line is an entry with its fields delimited by a NUL
+/// character. (id, timestamp, duration in microseconds, kind and key)
+///
+/// # Arguments
+///
+/// * `entries` - The entries to format.
+pub fn format_entries(entries: &[SlowLogEntry]) -> String {
+    let mut lines = vec![entries.len().to_string()];
+
+    for entry in entries {
+        lines.push(format!(
+            "{}\0{}\0{}\0{}\0{}",
+            entry.id,
+            entry.timestamp,
+            entry.duration.as_micros(),
+            entry.kind,
+            entry.key.as_deref().unwrap_or_default()
+        ));
+    }
+
+    lines.join("\n")
+}
+
+/// Add an entry to the server its slow log, using the configured threshold
+/// and length.
+///
+/// # Arguments
+///
+/// * `state` - The server state.
+/// * `kind` - The query type or the name of the background task.
+/// * `key` - The key (first argument) of the query, if any.
+/// * `duration` - How long the query or task took.
+pub fn log_slow(state: &State, kind: impl Display, key: Option<&str>, duration: Duration) {
+    let (threshold, max_len) = {
+        let settings = state.settings.read().unwrap();
+        (
+            Duration::from_micros(settings.slowlog_threshold),
+            settings.slowlog_max_len,
+        )
+    };
+
+    state
+        .slowlog
+        .record(kind, key, duration, threshold, max_len);
+}
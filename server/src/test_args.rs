@@ -0,0 +1,8 @@
+use clap::CommandFactory;
+
+use crate::Args;
+
+#[test]
+fn test_args_definition() {
+    Args::command().debug_assert();
+}
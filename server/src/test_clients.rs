@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use crate::{
+    clients::ClientRegistry, config::Config, connection::Session, database::process_query,
+    query::QueryType, State,
+};
+
+#[test]
+fn test_client_registry() {
+    let registry = Arc::new(ClientRegistry::default());
+    let first = ClientRegistry::register(&registry, "127.0.0.1:1000", 0).unwrap();
+    let second = ClientRegistry::register(&registry, "127.0.0.1:2000", 0).unwrap();
+
+    registry.set_name(first.id, "worker");
+    registry.record_query(second.id, Some(QueryType::Get), true);
+
+    let clients = registry.list();
+    assert_eq!(clients.len(), 2);
+    assert_eq!(clients[0].name.as_deref(), Some("worker"));
+    assert_eq!(clients[1].queries, 1);
+    assert!(clients[1].describe().contains("mode=bitwise"));
+    assert!(clients[1].describe().ends_with("cmd=Get"));
+
+    drop(first);
+    assert_eq!(registry.list().len(), 1);
+}
+
+#[tokio::test]
+async fn test_client_kill() {
+    let registry = Arc::new(ClientRegistry::default());
+    let client = ClientRegistry::register(&registry, "127.0.0.1:1000", 0).unwrap();
+
+    assert_eq!(registry.kill("127.0.0.1:9999"), 0);
+    assert_eq!(registry.kill(&client.id.to_string()), 1);
+
+    // The notification is stored until the connection waits for it.
+    client.killed().await;
+}
+
+#[tokio::test]
+async fn test_client_kill_unix() {
+    let registry = Arc::new(ClientRegistry::default());
+    let first = ClientRegistry::register(&registry, "unix:/run/ffly.sock", 0).unwrap();
+    let _second = ClientRegistry::register(&registry, "unix:/run/ffly.sock", 0).unwrap();
+
+    let clients = registry.list();
+    assert_ne!(clients[0].address, clients[1].address);
+    assert_eq!(first.address, format!("unix:/run/ffly.sock#{}", first.id));
+
+    assert_eq!(registry.kill("unix:/run/ffly.sock"), 0);
+    assert_eq!(
+        registry.kill(&format!("unix:/run/ffly.sock#{}", first.id)),
+        1
+    );
+    first.killed().await;
+}
+
+#[test]
+fn test_client_queries() {
+    let state = State::new(Config::default());
+    let client = ClientRegistry::register(&state.clients, "127.0.0.1:1000", 0).unwrap();
+    let mut session = Session {
+        id: client.id,
+        address: "127.0.0.1:1000".to_string(),
+        ..Session::default()
+    };
+
+    let (_, res) = process_query(&state, b"CLIENT SETNAME 'worker';", &mut session);
+    assert_eq!(res, "Ok");
+
+    let (_, res) = process_query(&state, b"CLIENT LIST;", &mut session);
+    assert!(res.contains("addr=127.0.0.1:1000 name=worker"));
+
+    let (_, res) = process_query(&state, b"CLIENT KILL 'unknown';", &mut session);
+    assert_eq!(res, "Error: No such client!");
+}
+
+#[test]
+fn test_client_limit() {
+    let registry = Arc::new(ClientRegistry::default());
+    let first = ClientRegistry::register(&registry, "127.0.0.1:1000", 1);
+
+    assert!(first.is_some());
+    assert!(ClientRegistry::register(&registry, "127.0.0.1:2000", 1).is_none());
+
+    drop(first);
+    assert!(ClientRegistry::register(&registry, "127.0.0.1:2000", 1).is_some());
+}
@@ -0,0 +1,78 @@
+use clap::Parser;
+
+use crate::{
+    config::{Config, Permission, Persistence},
+    expect, Args,
+};
+
+static CONFIG: &str = r#"
+port = 46601
+save_every = 5
+
+[[users]]
+name = "app"
+password = "secret"
+permissions = ["read", "write"]
+"#;
+
+#[test]
+fn test_config_parse() {
+    let config: Config = toml::from_str(CONFIG).unwrap();
+    assert_eq!(config.port, 46601);
+    assert_eq!(config.save_every, 5);
+    assert_eq!(config.host, Config::default().host);
+    assert_eq!(config.users.len(), 1);
+}
+
+#[test]
+fn test_config_unknown_field() {
+    expect!(Err(_), toml::from_str::<Config>("unknown = 1"));
+}
+
+#[test]
+fn test_config_flags_override() {
+    let mut config: Config = toml::from_str(CONFIG).unwrap();
+    let args = Args::parse_from(["ffly", "--port", "46602", "--persistence", "read-only"]);
+    args.apply(&mut config);
+
+    assert_eq!(config.port, 46602);
+    assert_eq!(config.save_every, 5);
+    assert_eq!(config.persistence, Persistence::ReadOnly);
+}
+
+#[test]
+fn test_config_permissions() {
+    let config: Config = toml::from_str(CONFIG).unwrap();
+    assert!(config.authenticate("app", "secret").is_some());
+    assert!(config.authenticate("app", "wrong").is_none());
+    assert!(config.is_allowed(Some("app"), Permission::Write));
+    assert!(!config.is_allowed(Some("app"), Permission::Admin));
+    assert!(!config.is_allowed(None, Permission::Read));
+    assert!(Config::default().is_allowed(None, Permission::Admin));
+}
+
+#[test]
+fn test_config_requires_listener() {
+    let args = Args::parse_from(["ffly", "--no-tcp"]);
+    assert!(Config::load(&args).is_err());
+
+    let args = Args::parse_from(["ffly", "--no-tcp", "--socket", "/tmp/ffly.sock"]);
+    assert!(Config::load(&args).is_ok());
+}
+
+#[test]
+fn test_config_cluster_persistence() {
+    let args = Args::parse_from(["ffly", "--cluster-peer", "127.0.0.1:46601"]);
+    assert!(Config::load(&args).is_ok());
+
+    for persistence in ["read-only", "disabled"] {
+        let args = Args::parse_from([
+            "ffly",
+            "--cluster-peer",
+            "127.0.0.1:46601",
+            "--persistence",
+            persistence,
+        ]);
+        assert!(Config::load(&args).is_err());
+    }
+}
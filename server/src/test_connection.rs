@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use tokio::{
+    io::{duplex, AsyncReadExt, AsyncWriteExt},
+    time::timeout,
+};
+
+use crate::{
+    clients::ClientRegistry, config::Config, connection::handle_connection, shutdown::Shutdown,
+    State,
+};
+
+#[tokio::test]
+async fn test_max_clients() {
+    let state = State::new(Config {
+        max_clients: 1,
+        ..Config::default()
+    });
+    let shutdown = Shutdown::new();
+    let _connected = ClientRegistry::register(&state.clients, "127.0.0.1:1000", 0);
+
+    let (mut client, server) = duplex(512);
+    handle_connection(
+        server,
+        "127.0.0.1:2000".to_string(),
+        state,
+        shutdown.subscribe(),
+    );
+
+    let mut res = String::new();
+    client.read_to_string(&mut res).await.unwrap();
+    assert_eq!(res, "Error: Max number of clients reached!");
+}
+
+#[tokio::test]
+async fn test_read_timeout() {
+    let state = State::new(Config {
+        read_timeout: 1,
+        ..Config::default()
+    });
+    let shutdown = Shutdown::new();
+
+    let (mut client, server) = duplex(512);
+    handle_connection(
+        server,
+        "127.0.0.1:2000".to_string(),
+        state,
+        shutdown.subscribe(),
+    );
+
+    client.write_all(b"QUERY TYPE STRING;").await.unwrap();
+    let mut buf = [0; 2];
+    client.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"Ok");
+
+    // The server closes the connection once the timeout expires.
+    let read = timeout(Duration::from_secs(5), client.read(&mut buf)).await;
+    assert_eq!(read.unwrap().unwrap(), 0);
+}
@@ -0,0 +1,146 @@
+use std::{fs, io::BufReader};
+
+use crate::{
+    config::Config,
+    connection::{accept_connections, Session},
+    convert::{
+        export, import, read_records, write_records, ExportArgs, ExportedRecord, Format, ImportArgs,
+    },
+    database::process_query,
+    listener::Listener,
+    shutdown::Shutdown,
+    test_util::temp_path,
+    State,
+};
+
+/// Records with values that need escaping in both formats.
+fn records() -> Vec<ExportedRecord> {
+    vec![
+        ExportedRecord {
+            key: "session".to_string(),
+            value: "a,\"quoted\" value".to_string(),
+            ttl: 0,
+        },
+        ExportedRecord {
+            key: "token".to_string(),
+            value: "{\"user\": 1}".to_string(),
+            ttl: 1_700_000_000,
+        },
+    ]
+}
+
+/// Arguments which import a file into a snapshot.
+fn import_args(input: &str, format: Format, snapshot: &str) -> ImportArgs {
+    ImportArgs {
+        help: None,
+        input: Some(input.to_string()),
+        format,
+        database: "sessions".to_string(),
+        snapshot: Some(snapshot.to_string()),
+        server: None,
+        user: None,
+        password: None,
+        progress_every: 1,
+        connections: 2,
+    }
+}
+
+#[test]
+fn test_formats_round_trip() {
+    for format in [Format::Jsonl, Format::Csv] {
+        let mut data = Vec::new();
+        assert_eq!(write_records(records(), format, &mut data).unwrap(), 2);
+
+        let read = read_records(BufReader::new(&data[..]), format).unwrap();
+        assert_eq!(read, records());
+    }
+}
+
+#[test]
+fn test_invalid_records() {
+    let jsonl = b"{\"key\": \"a\", \"value\": \"b\", \"ttl\": 0}\n\n{\"key\": \"a\"}\n";
+    let error = read_records(BufReader::new(&jsonl[..]), Format::Jsonl).unwrap_err();
+    assert_eq!(error.to_string(), "Invalid record on line 3");
+
+    let csv = b"key,value,ttl\na,b,0\na,b,never\n";
+    let error = read_records(BufReader::new(&csv[..]), Format::Csv).unwrap_err();
+    assert_eq!(error.to_string(), "Invalid record on line 3");
+}
+
+#[tokio::test]
+async fn test_snapshot_import_and_export() {
+    let (input, snapshot, output) = (
+        temp_path("convert-in.csv"),
+        temp_path("convert-db"),
+        temp_path("convert-out"),
+    );
+    let mut csv = Vec::new();
+    write_records(records(), Format::Csv, &mut csv).unwrap();
+    fs::write(&input, csv).unwrap();
+
+    assert_eq!(
+        import(&import_args(&input, Format::Csv, &snapshot))
+            .await
+            .unwrap(),
+        2
+    );
+
+    let exported = export(&ExportArgs {
+        help: None,
+        snapshot: snapshot.clone(),
+        database: "sessions".to_string(),
+        format: Format::Jsonl,
+        output: Some(output.clone()),
+    })
+    .unwrap();
+    assert_eq!(exported, 2);
+
+    let data = fs::read(&output).unwrap();
+    let mut read = read_records(BufReader::new(&data[..]), Format::Jsonl).unwrap();
+    read.sort_by(|a, b| a.key.cmp(&b.key));
+    assert_eq!(read, records());
+
+    for path in [input, snapshot, output] {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_server_import() {
+    let state = State::new(Config::default());
+    let shutdown = Shutdown::new();
+    let listener = Listener::bind_tcp("127.0.0.1:0").await.unwrap();
+    let address = listener.describe();
+    tokio::spawn(accept_connections(
+        listener,
+        state.clone(),
+        shutdown.subscribe(),
+    ));
+
+    let input = temp_path("convert-server.jsonl");
+    let mut jsonl = Vec::new();
+    let many: Vec<ExportedRecord> = (0..25)
+        .map(|index| ExportedRecord {
+            key: format!("key-{index}"),
+            value: "value".to_string(),
+            ttl: 0,
+        })
+        .collect();
+    write_records(many, Format::Jsonl, &mut jsonl).unwrap();
+    fs::write(&input, jsonl).unwrap();
+
+    let args = ImportArgs {
+        snapshot: None,
+        server: Some(address),
+        progress_every: 10,
+        ..import_args(&input, Format::Jsonl, "")
+    };
+    assert_eq!(import(&args).await.unwrap(), 25);
+
+    let mut session = Session::default();
+    process_query(&state, b"SELECT 'sessions';", &mut session);
+    let (_, res) = process_query(&state, b"DBSIZE;", &mut session);
+    assert_eq!(res, "25");
+
+    fs::remove_file(input).unwrap();
+}
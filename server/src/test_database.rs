@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use crate::{
+    config::Config,
+    connection::Session,
+    database::{process_query, save},
+    keyspace::{Databases, DEFAULT_DATABASE},
+    query::QueryType,
+    test_util::{remove_snapshot, temp_path},
+    State,
+};
+
+#[test]
+fn test_size_limits() {
+    let state = State::new(Config {
+        max_key_size: 3,
+        max_value_size: 5,
+        ..Config::default()
+    });
+    let mut session = Session::default();
+
+    let (query_type, res) = process_query(&state, b"NEW 'long key' VALUE 'a';", &mut session);
+    assert_eq!(res, "Error: Key too long!");
+    assert_eq!(query_type, None);
+
+    let (query_type, res) = process_query(&state, b"NEW 'key' VALUE 'long value';", &mut session);
+    assert_eq!(res, "Error: Value too long!");
+    assert_eq!(query_type, None);
+
+    let (_, res) = process_query(&state, b"NEW 'key' VALUE 'value';", &mut session);
+    assert_eq!(res, "Ok");
+    assert_eq!(state.db.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_logical_databases() {
+    let state = State::new(Config::default());
+    let mut sessions = Session::default();
+    let mut tokens = Session::default();
+
+    process_query(&state, b"SELECT 'sessions';", &mut sessions);
+    process_query(&state, b"SELECT 'tokens';", &mut tokens);
+    process_query(&state, b"NEW 'a' VALUE 'session';", &mut sessions);
+    process_query(&state, b"NEW 'a' VALUE 'token';", &mut tokens);
+    process_query(&state, b"NEW 'b' VALUE 'token';", &mut tokens);
+
+    let (_, res) = process_query(&state, b"GET VALUE 'a';", &mut sessions);
+    assert_eq!(res, "session");
+    let (_, res) = process_query(&state, b"DBSIZE;", &mut tokens);
+    assert_eq!(res, "2");
+
+    process_query(&state, b"FLUSHDB;", &mut tokens);
+    let (_, res) = process_query(&state, b"DBSIZE;", &mut tokens);
+    assert_eq!(res, "0");
+    let (_, res) = process_query(&state, b"DBSIZE;", &mut sessions);
+    assert_eq!(res, "1");
+    assert!(state.db.lock().unwrap().get("tokens").is_none());
+}
+
+#[test]
+fn test_database_creation() {
+    let state = State::new(Config {
+        max_databases: 2,
+        ..Config::default()
+    });
+    let mut session = Session::default();
+
+    // Only writing a record creates a database.
+    for query in [
+        "SELECT 'x1';",
+        "DBSIZE;",
+        "GET 'a';",
+        "DROP 'a';",
+        "FLUSHDB;",
+    ] {
+        process_query(&state, query.as_bytes(), &mut session);
+    }
+    let (_, res) = process_query(&state, b"GET VALUE 'a';", &mut session);
+    assert_eq!(res, "Error: Key not found!");
+    assert_eq!(state.db.lock().unwrap().count(), 0);
+
+    process_query(&state, b"NEW 'a' VALUE 'b';", &mut session);
+    process_query(&state, b"SELECT 'x2';", &mut session);
+    process_query(&state, b"NEW 'a' VALUE 'b';", &mut session);
+    process_query(&state, b"SELECT 'x3';", &mut session);
+    let (_, res) = process_query(&state, b"NEW 'a' VALUE 'b';", &mut session);
+    assert_eq!(res, "Error: Too many databases!");
+    assert_eq!(state.db.lock().unwrap().count(), 2);
+}
+
+#[test]
+fn test_databases_snapshot() {
+    let mut databases = Databases::default();
+    databases
+        .keyspace("tokens")
+        .insert("a".to_string(), "b".to_string(), "0".to_string());
+
+    let restored = Databases::from_snapshot(&databases.to_snapshot().unwrap()).unwrap();
+    assert_eq!(restored.get("tokens").unwrap().len(), 1);
+    assert!(restored.get(DEFAULT_DATABASE).is_none());
+
+    // Snapshots from before databases existed only contain the records.
+    let legacy = HashMap::from([("a".to_string(), ("b".to_string(), "0".to_string()))]);
+    let restored = Databases::from_snapshot(&bincode::serialize(&legacy).unwrap()).unwrap();
+    assert_eq!(restored.get(DEFAULT_DATABASE).unwrap().len(), 1);
+}
+
+#[test]
+fn test_databases_copy_on_write() {
+    let mut databases = Databases::default();
+    databases
+        .keyspace("tokens")
+        .insert("a".to_string(), "b".to_string(), "0".to_string());
+    let dump = databases.clone();
+
+    // Reads don't copy the shared records, writes do.
+    assert_eq!(databases.get_record("tokens", "a").unwrap().value, "b");
+    assert!(std::ptr::eq(
+        databases.get("tokens").unwrap(),
+        dump.get("tokens").unwrap()
+    ));
+
+    databases
+        .keyspace("tokens")
+        .insert("c".to_string(), "d".to_string(), "0".to_string());
+    assert!(!std::ptr::eq(
+        databases.get("tokens").unwrap(),
+        dump.get("tokens").unwrap()
+    ));
+    assert_eq!((databases.len(), dump.len()), (2, 1));
+}
+
+#[tokio::test]
+async fn test_admin_queries() {
+    let out = temp_path("database.bincode");
+    let state = State::new(Config {
+        out: out.clone(),
+        ..Config::default()
+    });
+    let mut session = Session::default();
+
+    let (_, res) = process_query(&state, b"LASTSAVE;", &mut session);
+    assert_eq!(res, "0");
+
+    // The connection loop counts the changes of a query.
+    process_query(&state, b"NEW 'a' VALUE 'b';", &mut session);
+    *state.changed.lock().unwrap() += 1;
+    let (query_type, res) = process_query(&state, b"SAVE;", &mut session);
+    assert_eq!((query_type, res.as_str()), (Some(QueryType::Save), ""));
+
+    // The connection writes the snapshot, outside of `process_query`.
+    assert_eq!(save(&state).await, "Ok");
+    assert_eq!(*state.changed.lock().unwrap(), 0);
+    assert!(std::path::Path::new(&out).exists());
+
+    let (_, res) = process_query(&state, b"LASTSAVE;", &mut session);
+    assert_ne!(res, "0");
+
+    // A snapshot that is being written blocks background saves.
+    let saving = state.saving.try_lock().unwrap();
+    let (_, res) = process_query(&state, b"BGSAVE;", &mut session);
+    assert_eq!(res, "Error: A snapshot is already being written!");
+    drop(saving);
+
+    // The background save holds the lock from the moment it gets accepted.
+    // (it can't finish while the databases are locked)
+    let db = state.db.lock().unwrap();
+    let (_, res) = process_query(&state, b"BGSAVE;", &mut session);
+    assert_eq!(res, "Ok");
+    let (_, res) = process_query(&state, b"BGSAVE;", &mut session);
+    assert_eq!(res, "Error: A snapshot is already being written!");
+    drop(db);
+    drop(state.saving.lock().await);
+
+    process_query(&state, b"SELECT 'other';", &mut session);
+    process_query(&state, b"NEW 'c' VALUE 'd';", &mut session);
+    let (_, res) = process_query(&state, b"FLUSHALL;", &mut session);
+    assert_eq!(res, "Ok");
+    assert!(state.db.lock().unwrap().is_empty());
+
+    remove_snapshot(&out);
+}
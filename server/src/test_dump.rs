@@ -0,0 +1,143 @@
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+use crate::{
+    config::Config,
+    connection::{handle_connection, Session},
+    database::process_query,
+    keyspace::Databases,
+    shutdown::Shutdown,
+    State,
+};
+
+/// Connect a client to a server state.
+fn connect(state: &State, shutdown: &Shutdown) -> DuplexStream {
+    let (client, server) = duplex(64 * 1024);
+    handle_connection(
+        server,
+        "127.0.0.1:2000".to_string(),
+        state.clone(),
+        shutdown.subscribe(),
+    );
+    client
+}
+
+/// Send a query and read its response.
+async fn query(client: &mut DuplexStream, query: &[u8]) -> String {
+    client.write_all(query).await.unwrap();
+    let mut buf = vec![0; 512];
+    let size = client.read(&mut buf).await.unwrap();
+    String::from_utf8(buf[..size].to_vec()).unwrap()
+}
+
+/// Read the chunks of a dump, until the empty chunk which ends it.
+async fn read_dump(client: &mut DuplexStream) -> (Vec<u8>, usize) {
+    let (mut data, mut chunks) = (Vec::new(), 0);
+
+    loop {
+        let length = client.read_u64().await.unwrap() as usize;
+        if length == 0 {
+            return (data, chunks);
+        }
+
+        let mut chunk = vec![0; length];
+        client.read_exact(&mut chunk).await.unwrap();
+        data.extend_from_slice(&chunk);
+        chunks += 1;
+    }
+}
+
+/// Send data as the chunks of a dump.
+async fn write_dump(client: &mut DuplexStream, data: &[u8]) {
+    for chunk in data.chunks(1000) {
+        client.write_u64(chunk.len() as u64).await.unwrap();
+        client.write_all(chunk).await.unwrap();
+    }
+    client.write_u64(0).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_dump_and_restore() {
+    let shutdown = Shutdown::new();
+    let source = State::new(Config::default());
+    let mut session = Session::default();
+    for index in 0..2_000 {
+        let query = format!("NEW 'key-{index}' VALUE '{:0>64}';", index);
+        process_query(&source, query.as_bytes(), &mut session);
+    }
+    process_query(&source, b"SELECT 'other';", &mut session);
+    process_query(
+        &source,
+        b"NEW 'token' VALUE 'abc' WITH TTL '0';",
+        &mut session,
+    );
+
+    // The dump is the snapshot format, sent in multiple chunks.
+    let mut client = connect(&source, &shutdown);
+    client.write_all(b"DUMP;").await.unwrap();
+    let mut ok = [0; 2];
+    client.read_exact(&mut ok).await.unwrap();
+    assert_eq!(&ok, b"Ok");
+
+    let (data, chunks) = read_dump(&mut client).await;
+    assert!(chunks > 1);
+    let dumped = Databases::from_snapshot(&data).unwrap();
+    assert_eq!(dumped.len(), 2_001);
+    assert_eq!(
+        dumped.get("other").unwrap().peek("token").unwrap().value,
+        "abc"
+    );
+
+    // The connection can be used after the dump.
+    assert_eq!(query(&mut client, b"DBSIZE;").await, "2000");
+
+    // A restore replaces every database of the target.
+    let target = State::new(Config::default());
+    let mut target_session = Session::default();
+    process_query(&target, b"NEW 'old' VALUE 'gone';", &mut target_session);
+
+    let mut client = connect(&target, &shutdown);
+    assert_eq!(query(&mut client, b"RESTORE;").await, "Ok");
+    write_dump(&mut client, &data).await;
+    let mut res = vec![0; 512];
+    let size = client.read(&mut res).await.unwrap();
+    assert_eq!(&res[..size], b"Ok");
+
+    let (_, res) = process_query(&target, b"GET VALUE 'old';", &mut target_session);
+    assert_eq!(res, "Error: Key not found!");
+    let (_, res) = process_query(&target, b"GET VALUE 'key-7';", &mut target_session);
+    assert_eq!(res, format!("{:0>64}", 7));
+    process_query(&target, b"SELECT 'other';", &mut target_session);
+    let (_, res) = process_query(&target, b"GET VALUE 'token';", &mut target_session);
+    assert_eq!(res, "abc");
+    assert_eq!(*target.changed.lock().unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_restore_invalid_snapshot() {
+    let shutdown = Shutdown::new();
+    let state = State::new(Config::default());
+    let mut session = Session::default();
+    process_query(&state, b"NEW 'kept' VALUE 'yes';", &mut session);
+
+    let mut client = connect(&state, &shutdown);
+    assert_eq!(query(&mut client, b"RESTORE;").await, "Ok");
+    write_dump(&mut client, b"FFLYDBS1 not a snapshot").await;
+    let mut res = vec![0; 512];
+    let size = client.read(&mut res).await.unwrap();
+    assert_eq!(&res[..size], b"Error: Invalid snapshot!");
+
+    // Nothing changed, and the connection is still usable.
+    assert_eq!(query(&mut client, b"GET VALUE 'kept';").await, "yes");
+}
+
+#[tokio::test]
+async fn test_restore_refused_by_replica() {
+    let state = State::new(Config {
+        replica_of: Some("127.0.0.1:1".to_string()),
+        ..Config::default()
+    });
+    let mut session = Session::default();
+
+    let (_, res) = process_query(&state, b"RESTORE;", &mut session);
+    assert_eq!(res, "Error: Read-only replica!");
+}
@@ -0,0 +1,150 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    config::Config,
+    connection::Session,
+    database::{process_query, record_size},
+    eviction::{make_room, EvictionPolicy},
+    keyspace::Keyspace,
+    State,
+};
+
+#[test]
+fn test_keyspace_memory() {
+    let mut keyspace = Keyspace::default();
+
+    keyspace.insert("a".to_string(), "value".to_string(), "0".to_string());
+    keyspace.insert("b".to_string(), "value".to_string(), "0".to_string());
+    keyspace.insert("a".to_string(), "longer value".to_string(), "0".to_string());
+    assert_eq!(
+        keyspace.used_memory(),
+        record_size("a", "longer value", "0") + record_size("b", "value", "0")
+    );
+
+    keyspace.remove("a");
+    assert_eq!(keyspace.retain(|_, record| record.value != "value"), 1);
+    assert_eq!(keyspace.used_memory(), 0);
+}
+
+#[test]
+fn test_keyspace_snapshot_format() {
+    let map = HashMap::from([("a".to_string(), ("b".to_string(), "0".to_string()))]);
+
+    let mut keyspace: Keyspace = bincode::deserialize(&bincode::serialize(&map).unwrap()).unwrap();
+    assert_eq!(keyspace.get("a").unwrap().value, "b");
+    assert_eq!(keyspace.used_memory(), record_size("a", "b", "0"));
+
+    let restored: HashMap<String, (String, String)> =
+        bincode::deserialize(&bincode::serialize(&keyspace).unwrap()).unwrap();
+    assert_eq!(restored, map);
+}
+
+#[test]
+fn test_eviction_policies() {
+    let limit = record_size("a", "value", "0") * 2;
+    let mut keyspace = Keyspace::default();
+    keyspace.insert("a".to_string(), "value".to_string(), "0".to_string());
+    keyspace.insert("b".to_string(), "value".to_string(), "0".to_string());
+
+    let noeviction = make_room(
+        &mut keyspace,
+        EvictionPolicy::NoEviction,
+        limit,
+        "c",
+        "value",
+        "0",
+    );
+    assert_eq!(noeviction, (Vec::new(), false));
+
+    keyspace.get("a");
+    let lru = make_room(
+        &mut keyspace,
+        EvictionPolicy::AllKeysLru,
+        limit,
+        "c",
+        "value",
+        "0",
+    );
+    assert_eq!(lru, (vec!["b".to_string()], true));
+    assert!(keyspace.peek("b").is_none());
+
+    // Overwriting a record only needs room for the difference.
+    let overwrite = make_room(
+        &mut keyspace,
+        EvictionPolicy::NoEviction,
+        limit,
+        "a",
+        "other",
+        "0",
+    );
+    assert_eq!(overwrite, (Vec::new(), true));
+}
+
+#[test]
+fn test_volatile_ttl_eviction() {
+    let limit = record_size("a", "value", "100") * 2;
+    let mut keyspace = Keyspace::default();
+    keyspace.insert("a".to_string(), "value".to_string(), "200".to_string());
+    keyspace.insert("b".to_string(), "value".to_string(), "100".to_string());
+
+    let evicted = make_room(
+        &mut keyspace,
+        EvictionPolicy::VolatileTtl,
+        limit,
+        "c",
+        "value",
+        "0",
+    );
+    assert_eq!(evicted, (vec!["b".to_string()], true));
+    assert!(keyspace.peek("b").is_none());
+}
+
+#[test]
+fn test_random_eviction() {
+    let limit = record_size("a", "value", "0") * 3;
+    let mut evicted = HashSet::new();
+
+    // A keyspace smaller than the sample still gets a random victim.
+    for _ in 0..100 {
+        let mut keyspace = Keyspace::default();
+        for key in ["a", "b", "c"] {
+            keyspace.insert(key.to_string(), "value".to_string(), "0".to_string());
+        }
+
+        let (victims, fits) = make_room(
+            &mut keyspace,
+            EvictionPolicy::AllKeysRandom,
+            limit,
+            "d",
+            "value",
+            "0",
+        );
+        assert!(fits);
+        evicted.extend(victims);
+    }
+    assert_eq!(evicted.len(), 3);
+}
+
+#[test]
+fn test_max_memory_queries() {
+    let state = State::new(Config {
+        max_memory: record_size("a", "b", "0"),
+        ..Config::default()
+    });
+    let mut session = Session::default();
+
+    process_query(&state, b"NEW 'a' VALUE 'b';", &mut session);
+    let (query_type, res) = process_query(&state, b"NEW 'c' VALUE 'd';", &mut session);
+    assert_eq!(res, "Error: Out of memory!");
+
+    // The refused write isn't registered as an executed query.
+    assert_eq!(query_type, None);
+    let metrics = state.metrics.render(1);
+    assert!(metrics.contains("ffly_queries_total{query_type=\"New\"} 1\n"));
+    assert_eq!(*state.changed.lock().unwrap(), 0);
+
+    state.settings.write().unwrap().eviction_policy = EvictionPolicy::AllKeysRandom;
+    let (_, res) = process_query(&state, b"NEW 'c' VALUE 'd';", &mut session);
+    assert_eq!(res, "Ok");
+    assert_eq!(state.metrics.evicted(), 1);
+}
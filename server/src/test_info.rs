@@ -0,0 +1,15 @@
+use crate::{config::Config, connection::Session, database::process_query, State};
+
+#[test]
+fn test_info_query() {
+    let state = State::new(Config::default());
+    let mut session = Session::default();
+
+    process_query(&state, b"NEW 'a' VALUE 'b' WITH TTL '99';", &mut session);
+    process_query(&state, b"NEW 'c' VALUE 'd';", &mut session);
+    let (_, info) = process_query(&state, b"INFO;", &mut session);
+
+    assert!(info.lines().any(|line| line == "keys:2"));
+    assert!(info.lines().any(|line| line == "keys_with_ttl:1"));
+    assert!(info.lines().any(|line| line == "last_save:0"));
+}
@@ -0,0 +1,134 @@
+use std::{collections::BTreeMap, fs};
+
+use crate::{
+    inspect::{inspect, salvage, InspectArgs, Statistics, TtlDistribution},
+    keyspace::Databases,
+    test_util::temp_path,
+};
+
+/// Databases with records in two databases, in insertion order.
+fn databases() -> Databases {
+    let mut databases = Databases::default();
+    let sessions = databases.keyspace("sessions");
+    sessions.insert("a".to_string(), "1".to_string(), "0".to_string());
+    sessions.insert("b".to_string(), "22".to_string(), "100".to_string());
+    sessions.insert("c".to_string(), "333".to_string(), "5000".to_string());
+    databases
+        .keyspace("tokens")
+        .insert("t".to_string(), "4444".to_string(), "never".to_string());
+    databases
+}
+
+#[test]
+fn test_salvage_valid() {
+    let data = databases().to_snapshot().unwrap();
+    let salvaged = salvage(&data);
+
+    assert!(!salvaged.legacy);
+    assert_eq!(salvaged.problem, None);
+    assert_eq!(salvaged.readable, data.len());
+    assert_eq!(salvaged.databases.len(), 4);
+}
+
+#[test]
+fn test_salvage_truncated() {
+    let data = databases().to_snapshot().unwrap();
+
+    // Cut off in the middle of the TTL of the last record of the first
+    // database. (the second database its name, length and record follow)
+    let tokens = (8 + 6) + 8 + (8 + 1) + (8 + 4) + (8 + 5);
+    let cut = data.len() - tokens - 3;
+    let salvaged = salvage(&data[..cut]);
+    assert!(Databases::from_snapshot(&data[..cut]).is_err());
+
+    let sessions = salvaged.databases.get("sessions").unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions.peek("b").unwrap().value, "22");
+    assert!(salvaged.databases.get("tokens").is_none());
+    assert!(salvaged.problem.unwrap().contains("only 1 are left"));
+}
+
+#[test]
+fn test_salvage_trailing_data() {
+    let mut data = databases().to_snapshot().unwrap();
+    data.extend_from_slice(b"abc");
+
+    let salvaged = salvage(&data);
+    assert_eq!(salvaged.databases.len(), 4);
+    assert_eq!(salvaged.problem.unwrap(), "3 bytes of trailing data");
+}
+
+#[test]
+fn test_salvage_legacy() {
+    let records = BTreeMap::from([("key".to_string(), ("value".to_string(), "0".to_string()))]);
+    let data = bincode::serialize(&records).unwrap();
+
+    let salvaged = salvage(&data);
+    assert!(salvaged.legacy);
+    assert_eq!(salvaged.problem, None);
+    assert_eq!(salvaged.databases.get("0").unwrap().len(), 1);
+}
+
+#[test]
+fn test_statistics() {
+    let statistics = Statistics::gather(&databases(), 2, 1000);
+
+    assert_eq!(statistics.records, 4);
+    assert_eq!(
+        statistics.databases,
+        vec![("sessions".to_string(), 3), ("tokens".to_string(), 1)]
+    );
+    assert_eq!(
+        statistics.ttl,
+        TtlDistribution {
+            never: 1,
+            expired: 1,
+            within_hour: 0,
+            within_day: 1,
+            within_week: 0,
+            later: 0,
+            invalid: 1,
+        }
+    );
+    assert_eq!(
+        statistics.largest,
+        vec![
+            (4, "tokens".to_string(), "t".to_string()),
+            (3, "sessions".to_string(), "c".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_inspect_repair() {
+    let snapshot = temp_path("inspect");
+    let repaired = format!("{}.repaired", snapshot);
+
+    let mut data = databases().to_snapshot().unwrap();
+    data.truncate(data.len() - 3);
+    fs::write(&snapshot, data).unwrap();
+
+    let args = InspectArgs {
+        help: None,
+        snapshot: snapshot.clone(),
+        largest: 10,
+        repair: Some(repaired.clone()),
+        drop_expired: true,
+    };
+    assert!(!inspect(&args).unwrap());
+
+    // The copy loads, without the expired and the unreadable records.
+    let databases = Databases::from_snapshot(&fs::read(&repaired).unwrap()).unwrap();
+    assert_eq!(databases.len(), 1);
+    assert!(databases.get("sessions").unwrap().peek("a").is_some());
+
+    // The original file never gets overwritten.
+    let args = InspectArgs {
+        repair: Some(snapshot.clone()),
+        ..args
+    };
+    assert!(inspect(&args).is_err());
+
+    fs::remove_file(snapshot).unwrap();
+    fs::remove_file(repaired).unwrap();
+}
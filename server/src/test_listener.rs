@@ -0,0 +1,57 @@
+use std::{fs, os::unix::net::UnixListener as StdUnixListener};
+
+use ffly_rs::FireflyStream;
+
+use crate::{
+    config::Config,
+    listener::Listener,
+    test_util::{remove_snapshot, temp_path},
+    Server,
+};
+
+#[tokio::test]
+async fn test_bind_unix_replaces_stale_socket() {
+    let path = temp_path("listener-stale.sock");
+    drop(StdUnixListener::bind(&path).unwrap());
+
+    let listener = Listener::bind_unix(&path, 0o600).unwrap();
+    assert_eq!(listener.describe(), format!("unix:{}", path));
+
+    drop(listener);
+    assert!(fs::symlink_metadata(&path).is_err());
+}
+
+#[tokio::test]
+async fn test_bind_unix_keeps_regular_file() {
+    let path = temp_path("listener-regular.sock");
+    fs::write(&path, "records").unwrap();
+
+    assert!(Listener::bind_unix(&path, 0o600).is_err());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "records");
+
+    fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_unix_round_trip() {
+    let path = temp_path("listener-round-trip.sock");
+    let snapshot = temp_path("listener-round-trip.bincode");
+    let server = Server::new(Config {
+        socket: Some(path.clone()),
+        no_tcp: true,
+        ..Config::default()
+    })
+    .snapshot(&snapshot)
+    .start()
+    .await
+    .unwrap();
+    assert_eq!(server.local_addr(), None);
+
+    let firefly = FireflyStream::connect_unix(&path).await.unwrap();
+    firefly.new("key", "value").await.unwrap();
+    assert_eq!(firefly.get_value("key").await.unwrap(), "value");
+
+    drop(firefly);
+    server.shutdown().await;
+    remove_snapshot(&snapshot);
+}
@@ -0,0 +1,28 @@
+use std::time::Duration;
+
+use crate::{metrics::Metrics, query::QueryType};
+
+#[test]
+fn test_metrics_render() {
+    let metrics = Metrics::default();
+    metrics.record_query(Some(QueryType::Get), Duration::from_micros(20));
+    metrics.record_query(None, Duration::from_millis(2));
+    metrics.record_sweep(3);
+
+    let rendered = metrics.render(42);
+    assert!(rendered.contains("ffly_queries_total{query_type=\"Get\"} 1"));
+    assert!(rendered.contains("ffly_queries_total{query_type=\"Invalid\"} 1"));
+    assert!(rendered.contains("ffly_query_duration_seconds_bucket{le=\"0.00005\"} 1"));
+    assert!(rendered.contains("ffly_query_duration_seconds_bucket{le=\"+Inf\"} 2"));
+    assert!(rendered.contains("ffly_keys 42"));
+    assert!(rendered.contains("ffly_expired_keys_total 3"));
+}
+
+#[test]
+fn test_metrics_connected_clients() {
+    let metrics = Metrics::default();
+    metrics.client_connected();
+    metrics.client_connected();
+    metrics.client_disconnected();
+    assert_eq!(metrics.connected_clients(), 1);
+}
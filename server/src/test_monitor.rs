@@ -0,0 +1,72 @@
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+use crate::{
+    config::Config,
+    connection::{handle_connection, Session},
+    database::process_query,
+    shutdown::Shutdown,
+    State,
+};
+
+#[test]
+fn test_monitor_broadcast() {
+    let state = State::new(Config::default());
+    let mut entries = state.monitor.subscribe();
+    let mut session = Session {
+        address: "127.0.0.1:1234".to_string(),
+        ..Session::default()
+    };
+
+    process_query(&state, b"NEW 'a' VALUE 'b';", &mut session);
+
+    let entry = entries.try_recv().unwrap();
+    assert_eq!(entry.address, "127.0.0.1:1234");
+    assert!(entry
+        .to_string()
+        .ends_with("[127.0.0.1:1234] New \"a\" \"b\" \"0\""));
+}
+
+#[test]
+fn test_monitor_redaction() {
+    let state = State::new(Config {
+        monitor_redact_values: true,
+        ..Config::default()
+    });
+    let mut entries = state.monitor.subscribe();
+    let mut session = Session::default();
+
+    process_query(&state, b"NEW 'a' VALUE 'b';", &mut session);
+    process_query(&state, b"AUTH 'user' 'password';", &mut session);
+
+    assert_eq!(
+        entries.try_recv().unwrap().arguments,
+        ["a", "(redacted)", "0"]
+    );
+    assert_eq!(
+        entries.try_recv().unwrap().arguments,
+        ["user", "(redacted)"]
+    );
+}
+
+#[tokio::test]
+async fn test_monitor_unix_address() {
+    let state = State::new(Config::default());
+    let mut entries = state.monitor.subscribe();
+    let shutdown = Shutdown::new();
+
+    let (mut client, server) = duplex(512);
+    handle_connection(
+        server,
+        "unix:/run/ffly.sock".to_string(),
+        state.clone(),
+        shutdown.subscribe(),
+    );
+    client.write_all(b"NEW 'a' VALUE 'b';").await.unwrap();
+    let mut buf = [0; 2];
+    client.read_exact(&mut buf).await.unwrap();
+
+    // The entry carries the address the client list shows and kills by.
+    let entry = entries.recv().await.unwrap();
+    assert_eq!(state.clients.list()[0].address, entry.address);
+    assert!(entry.address.starts_with("unix:/run/ffly.sock#"));
+}
@@ -1,10 +1,9 @@
 use crate::query::{get_arguments, get_query_type, parse_query, QueryType};
 
-static QUERY_NEW: &'static str =
-    "NEW 'hi' VALUE 'hello there \"general kenobi\"'WITH TTL '604800';";
+static QUERY_NEW: &str = "NEW 'hi' VALUE 'hello there \"general kenobi\"'WITH TTL '604800';";
 
-static QUERY_GET: &'static str = "GET 'hi';";
-static QUERY_GETTTL: &'static str = "GET TTL 'hi';";
+static QUERY_GET: &str = "GET 'hi';";
+static QUERY_GETTTL: &str = "GET TTL 'hi';";
 
 #[macro_export]
 macro_rules! expect {
@@ -0,0 +1,302 @@
+use std::time::Duration;
+
+use tokio::{
+    io::{duplex, AsyncReadExt, AsyncWriteExt},
+    time::{sleep, timeout},
+};
+
+use crate::{
+    config::Config,
+    connection::{accept_connections, handle_connection, Session},
+    database::{detect_expirations, process_query},
+    listener::Listener,
+    raft::{is_peer, start, Role},
+    shutdown::Shutdown,
+    test_util::{remove_snapshot, temp_path},
+    State,
+};
+
+/// A cluster node running on a random localhost port.
+struct Node {
+    state: State,
+    shutdown: Shutdown,
+    out: String,
+}
+
+impl Node {
+    /// Shut the node down, and remove the state it stored.
+    async fn stop(self) {
+        assert!(self.shutdown.shutdown(Duration::from_secs(2)).await);
+        remove_snapshot(&self.out);
+    }
+}
+
+/// Start a cluster of nodes on localhost.
+async fn start_cluster(size: usize) -> Vec<Node> {
+    let mut listeners = Vec::new();
+    for _ in 0..size {
+        listeners.push(Listener::bind_tcp("127.0.0.1:0").await.unwrap());
+    }
+
+    start_nodes(listeners).await
+}
+
+/// Start a node on every listener, the listeners are the cluster. Nodes that
+/// ran on the same addresses before restore their state.
+async fn start_nodes(listeners: Vec<Listener>) -> Vec<Node> {
+    let addresses: Vec<String> = listeners
+        .iter()
+        .map(|listener| listener.describe())
+        .collect();
+
+    let mut nodes = Vec::new();
+    for (index, listener) in listeners.into_iter().enumerate() {
+        let out = temp_path(&format!("raft-{}", addresses[index].replace(':', "-")));
+        let state = State::new(Config {
+            out: out.clone(),
+            cluster_addr: Some(addresses[index].clone()),
+            cluster_peers: addresses
+                .iter()
+                .filter(|address| **address != addresses[index])
+                .cloned()
+                .collect(),
+            clear_every: 1,
+            ..Config::default()
+        });
+        let shutdown = Shutdown::new();
+
+        tokio::spawn(accept_connections(
+            listener,
+            state.clone(),
+            shutdown.subscribe(),
+        ));
+        start(state.clone(), shutdown.subscribe());
+        detect_expirations(state.clone(), shutdown.subscribe());
+        nodes.push(Node {
+            state,
+            shutdown,
+            out,
+        });
+    }
+
+    nodes
+}
+
+/// Wait until one of the nodes got elected as leader, and return its index.
+async fn wait_for_leader(nodes: &[Node]) -> usize {
+    let waiting = async {
+        loop {
+            let leaders: Vec<usize> = (0..nodes.len())
+                .filter(|index| {
+                    let raft = nodes[*index].state.raft.as_ref().unwrap();
+                    raft.status().0 == Role::Leader
+                })
+                .collect();
+
+            if let [leader] = leaders.as_slice() {
+                return *leader;
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+    };
+
+    timeout(Duration::from_secs(10), waiting)
+        .await
+        .expect("No leader got elected")
+}
+
+/// Wait until a query on a node returns the expected response.
+async fn wait_for(node: &Node, query: &[u8], expected: &str) {
+    let mut session = Session::default();
+    let waiting = async {
+        while process_query(&node.state, query, &mut session).1 != expected {
+            sleep(Duration::from_millis(20)).await;
+        }
+    };
+
+    timeout(Duration::from_secs(10), waiting)
+        .await
+        .unwrap_or_else(|_| panic!("The node never returned {:?}", expected));
+}
+
+/// Execute a query on a node and wait until what it wrote or read got
+/// committed, like a connection does.
+async fn execute(node: &Node, query: &[u8]) -> String {
+    let mut session = Session::default();
+    let (query_type, res) = process_query(&node.state, query, &mut session);
+
+    let Some(pending) = session.pending_commit else {
+        return res;
+    };
+
+    let raft = node.state.raft.as_ref().unwrap();
+    match (raft.wait_for_commit(pending).await, query_type) {
+        (true, _) => res,
+        (false, Some(query_type)) if query_type.is_read() => {
+            "Error: Read not committed!".to_string()
+        }
+        (false, _) => "Error: Write not committed!".to_string(),
+    }
+}
+
+#[tokio::test]
+async fn test_raft_cluster() {
+    let mut nodes = start_cluster(3).await;
+    let leader = wait_for_leader(&nodes).await;
+    let follower = (leader + 1) % nodes.len();
+
+    // Writes get acknowledged once a majority stored them.
+    assert_eq!(
+        execute(&nodes[leader], b"NEW 'token' VALUE 'abc';").await,
+        "Ok"
+    );
+    assert!(nodes[leader].state.raft.as_ref().unwrap().commit_index() > 0);
+
+    // Followers refuse writes, and only serve (stale) reads when allowed.
+    let mut session = Session::default();
+    let (_, res) = process_query(&nodes[follower].state, b"NEW 'a' VALUE 'b';", &mut session);
+    assert_eq!(res, "Error: Not the leader!");
+    let (_, res) = process_query(&nodes[follower].state, b"GET VALUE 'token';", &mut session);
+    assert_eq!(res, "Error: Not the leader!");
+
+    nodes[follower]
+        .state
+        .settings
+        .write()
+        .unwrap()
+        .cluster_stale_reads = true;
+    wait_for(&nodes[follower], b"GET VALUE 'token';", "abc").await;
+
+    // The leader goes down, the acknowledged write survives.
+    let old_leader = nodes.remove(leader);
+    old_leader.stop().await;
+
+    let leader = wait_for_leader(&nodes).await;
+    let mut session = Session::default();
+    let (_, res) = process_query(&nodes[leader].state, b"GET VALUE 'token';", &mut session);
+    assert_eq!(res, "abc");
+
+    // The remaining majority still accepts writes.
+    assert_eq!(execute(&nodes[leader], b"DROP 'token';").await, "Ok");
+    for node in &nodes {
+        node.state.settings.write().unwrap().cluster_stale_reads = true;
+        wait_for(node, b"DBSIZE;", "0").await;
+    }
+
+    for node in nodes {
+        node.stop().await;
+    }
+}
+
+#[tokio::test]
+async fn test_raft_expirations() {
+    let nodes = start_cluster(3).await;
+    let leader = wait_for_leader(&nodes).await;
+
+    assert_eq!(
+        execute(&nodes[leader], b"NEW 'reset' VALUE 'abc' WITH TTL '1';").await,
+        "Ok"
+    );
+
+    // Followers don't expire records themselves, the leader replicates the
+    // expirations through the log.
+    for node in &nodes {
+        node.state.settings.write().unwrap().cluster_stale_reads = true;
+        wait_for(node, b"DBSIZE;", "0").await;
+    }
+
+    for node in nodes {
+        node.stop().await;
+    }
+}
+
+#[tokio::test]
+async fn test_raft_uncommitted_writes() {
+    let mut nodes = start_cluster(3).await;
+    let leader = nodes.remove(wait_for_leader(&nodes).await);
+    assert_eq!(execute(&leader, b"NEW 'kept' VALUE 'a';").await, "Ok");
+
+    // Without its followers the leader can't commit anything.
+    for follower in nodes {
+        follower.stop().await;
+    }
+
+    let (write, read) = tokio::join!(
+        execute(&leader, b"NEW 'lost' VALUE 'b';"),
+        execute(&leader, b"GET VALUE 'lost';"),
+    );
+    assert_eq!(write, "Error: Write not committed!");
+    assert_eq!(read, "Error: Read not committed!");
+
+    let (_, res) = process_query(&leader.state, b"GET VALUE 'kept';", &mut Session::default());
+    assert_eq!(res, "a");
+    leader.stop().await;
+}
+
+#[tokio::test]
+async fn test_raft_peers() {
+    let state = State::new(Config {
+        out: temp_path("raft-peers"),
+        cluster_peers: vec!["127.0.0.1:46601".to_string()],
+        ..Config::default()
+    });
+    assert!(is_peer(&state, "127.0.0.1:2000").await);
+    assert!(!is_peer(&state, "10.0.0.1:2000").await);
+    assert!(!is_peer(&state, "unix:/tmp/ffly.sock#1").await);
+
+    // Other clients can't switch their connection to cluster requests.
+    let shutdown = Shutdown::new();
+    for (address, expected) in [
+        ("10.0.0.1:2000", "Error: Not a cluster peer!"),
+        ("127.0.0.1:2000", "Ok"),
+    ] {
+        let (mut client, server) = duplex(512);
+        handle_connection(
+            server,
+            address.to_string(),
+            state.clone(),
+            shutdown.subscribe(),
+        );
+
+        client.write_all(b"RAFT;").await.unwrap();
+        let mut buf = vec![0; expected.len()];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), expected);
+    }
+
+    assert!(shutdown.shutdown(Duration::from_secs(2)).await);
+}
+
+#[tokio::test]
+async fn test_raft_restart() {
+    let nodes = start_cluster(3).await;
+    let leader = wait_for_leader(&nodes).await;
+    for index in 0..3 {
+        let query = format!("NEW 'key{}' VALUE 'abc';", index);
+        assert_eq!(execute(&nodes[leader], query.as_bytes()).await, "Ok");
+    }
+
+    // The whole cluster goes down, and comes back on the same addresses.
+    let addresses: Vec<String> = nodes
+        .iter()
+        .map(|node| node.state.raft.as_ref().unwrap().id.clone())
+        .collect();
+    for node in nodes {
+        assert!(node.shutdown.shutdown(Duration::from_secs(2)).await);
+    }
+
+    let mut listeners = Vec::new();
+    for address in &addresses {
+        listeners.push(Listener::bind_tcp(address).await.unwrap());
+    }
+    let nodes = start_nodes(listeners).await;
+
+    // Every acknowledged write survives, whichever node gets elected.
+    let leader = wait_for_leader(&nodes).await;
+    assert_eq!(execute(&nodes[leader], b"DBSIZE;").await, "3");
+    assert_eq!(execute(&nodes[leader], b"GET VALUE 'key2';").await, "abc");
+
+    for node in nodes {
+        node.stop().await;
+    }
+}
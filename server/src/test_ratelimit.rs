@@ -0,0 +1,61 @@
+use crate::{
+    config::{Config, Permission, User},
+    connection::Session,
+    database::process_query,
+    ratelimit::TokenBucket,
+    State,
+};
+
+#[test]
+fn test_token_bucket() {
+    let mut bucket = TokenBucket::default();
+
+    assert!(bucket.try_take(2));
+    assert!(bucket.try_take(2));
+    assert!(!bucket.try_take(2));
+
+    // Unlimited buckets never run out.
+    assert!((0..100).all(|_| bucket.try_take(0)));
+}
+
+#[test]
+fn test_client_rate_limit() {
+    let state = State::new(Config {
+        client_write_rate: 1,
+        ..Config::default()
+    });
+    let mut session = Session::default();
+
+    let (_, res) = process_query(&state, b"NEW 'a' VALUE 'b';", &mut session);
+    assert_eq!(res, "Ok");
+    let (query_type, res) = process_query(&state, b"NEW 'a' VALUE 'b';", &mut session);
+    assert_eq!(query_type, None);
+    assert_eq!(res, "Error: Rate limited!");
+
+    // Reads have their own limit.
+    let (_, res) = process_query(&state, b"GET VALUE 'a';", &mut session);
+    assert_eq!(res, "b");
+}
+
+#[test]
+fn test_user_rate_limit() {
+    let state = State::new(Config {
+        user_read_rate: 1,
+        users: vec![User {
+            name: "app".to_string(),
+            password: "secret".to_string(),
+            permissions: vec![Permission::Read],
+        }],
+        ..Config::default()
+    });
+    let mut first = Session::default();
+    let mut second = Session::default();
+
+    process_query(&state, b"AUTH 'app' 'secret';", &mut first);
+    process_query(&state, b"AUTH 'app' 'secret';", &mut second);
+
+    let (_, res) = process_query(&state, b"GET 'a';", &mut first);
+    assert_ne!(res, "Error: Rate limited!");
+    let (_, res) = process_query(&state, b"GET 'a';", &mut second);
+    assert_eq!(res, "Error: Rate limited!");
+}
@@ -0,0 +1,222 @@
+use std::time::Duration;
+
+use tokio::time::{sleep, timeout};
+
+use crate::{
+    config::Config,
+    connection::{accept_connections, Session},
+    database::detect_expirations,
+    database::process_query,
+    listener::Listener,
+    replication::{follow, read_frame, write_frame, Mutation, MAX_FRAME_SIZE},
+    shutdown::Shutdown,
+    State,
+};
+
+/// Start a primary on a random localhost port, and return its address.
+async fn start_primary(state: &State, shutdown: &Shutdown) -> String {
+    let listener = Listener::bind_tcp("127.0.0.1:0").await.unwrap();
+    let address = listener.describe();
+    tokio::spawn(accept_connections(
+        listener,
+        state.clone(),
+        shutdown.subscribe(),
+    ));
+    address
+}
+
+/// Wait until a query on the replica returns the expected response.
+async fn wait_for(replica: &State, session: &mut Session, query: &[u8], expected: &str) {
+    let waiting = async {
+        while process_query(replica, query, session).1 != expected {
+            sleep(Duration::from_millis(10)).await;
+        }
+    };
+
+    timeout(Duration::from_secs(5), waiting)
+        .await
+        .unwrap_or_else(|_| panic!("The replica never returned {:?}", expected));
+}
+
+#[tokio::test]
+async fn test_replication() {
+    let shutdown = Shutdown::new();
+    let primary = State::new(Config::default());
+    let mut session = Session::default();
+    process_query(&primary, b"NEW 'before' VALUE 'sync';", &mut session);
+
+    let replica = State::new(Config {
+        replica_of: Some(start_primary(&primary, &shutdown).await),
+        ..Config::default()
+    });
+    let mut replica_session = Session::default();
+    follow(replica.clone(), shutdown.subscribe());
+
+    // The initial snapshot.
+    wait_for(
+        &replica,
+        &mut replica_session,
+        b"GET VALUE 'before';",
+        "sync",
+    )
+    .await;
+
+    // The mutations after the snapshot.
+    process_query(&primary, b"SELECT 'other';", &mut session);
+    process_query(&replica, b"SELECT 'other';", &mut replica_session);
+    process_query(&primary, b"NEW 'after' VALUE 'sync';", &mut session);
+    wait_for(&replica, &mut replica_session, b"DBSIZE;", "1").await;
+    process_query(&primary, b"DROP 'after';", &mut session);
+    wait_for(&replica, &mut replica_session, b"DBSIZE;", "0").await;
+
+    // Replicas refuse writes.
+    let (_, res) = process_query(&replica, b"NEW 'a' VALUE 'b';", &mut replica_session);
+    assert_eq!(res, "Error: Read-only replica!");
+
+    // After a disconnect the replica resyncs, including what it missed.
+    let replica_id = primary.clients.list()[0].id;
+    assert_eq!(primary.clients.kill(&replica_id.to_string()), 1);
+    process_query(&primary, b"NEW 'missed' VALUE 'resync';", &mut session);
+    wait_for(
+        &replica,
+        &mut replica_session,
+        b"GET VALUE 'missed';",
+        "resync",
+    )
+    .await;
+
+    assert!(shutdown.shutdown(Duration::from_secs(1)).await);
+}
+
+#[tokio::test]
+async fn test_replicated_expirations() {
+    let shutdown = Shutdown::new();
+    let primary = State::new(Config {
+        clear_every: 1,
+        ..Config::default()
+    });
+    let mut session = Session::default();
+
+    let replica = State::new(Config {
+        replica_of: Some(start_primary(&primary, &shutdown).await),
+        clear_every: 1,
+        ..Config::default()
+    });
+    let mut replica_session = Session::default();
+    follow(replica.clone(), shutdown.subscribe());
+    detect_expirations(replica.clone(), shutdown.subscribe());
+
+    process_query(&primary, b"NEW 'kept' VALUE 'a';", &mut session);
+    process_query(
+        &primary,
+        b"NEW 'expired' VALUE 'b' WITH TTL '1';",
+        &mut session,
+    );
+    wait_for(&replica, &mut replica_session, b"DBSIZE;", "2").await;
+
+    // The replica doesn't expire records itself, the primary sends them.
+    sleep(Duration::from_millis(1500)).await;
+    assert_eq!(
+        process_query(&replica, b"DBSIZE;", &mut replica_session).1,
+        "2"
+    );
+
+    detect_expirations(primary.clone(), shutdown.subscribe());
+    wait_for(&replica, &mut replica_session, b"DBSIZE;", "1").await;
+    wait_for(&replica, &mut replica_session, b"GET VALUE 'kept';", "a").await;
+}
+
+#[test]
+fn test_mutation_apply() {
+    let state = State::new(Config::default());
+    let mut db = state.db.lock().unwrap();
+
+    Mutation::Insert {
+        database: "0".to_string(),
+        key: "a".to_string(),
+        value: "b".to_string(),
+        ttl: "0".to_string(),
+    }
+    .apply(&mut db);
+    Mutation::Insert {
+        database: "1".to_string(),
+        key: "c".to_string(),
+        value: "b".to_string(),
+        ttl: "0".to_string(),
+    }
+    .apply(&mut db);
+    assert_eq!(db.len(), 2);
+
+    Mutation::RemoveValues {
+        database: "0".to_string(),
+        value: "b".to_string(),
+    }
+    .apply(&mut db);
+    assert_eq!(db.len(), 1);
+
+    Mutation::Expire {
+        database: "1".to_string(),
+        keys: vec!["c".to_string(), "missing".to_string()],
+    }
+    .apply(&mut db);
+    assert!(db.is_empty());
+
+    Mutation::FlushAll.apply(&mut db);
+    assert!(db.is_empty());
+}
+
+#[tokio::test]
+async fn test_replica_of() {
+    let shutdown = Shutdown::new();
+    let primary = State::new(Config::default());
+    let mut session = Session::default();
+    process_query(&primary, b"NEW 'a' VALUE 'b';", &mut session);
+
+    // A primary starts replicating another server.
+    let replica = State::new(Config::default());
+    let mut replica_session = Session::default();
+    follow(replica.clone(), shutdown.subscribe());
+
+    let address = start_primary(&primary, &shutdown).await;
+    let query = format!("REPLICAOF '{}';", address);
+    let (_, res) = process_query(&replica, query.as_bytes(), &mut replica_session);
+    assert_eq!(res, "Ok");
+    wait_for(&replica, &mut replica_session, b"GET VALUE 'a';", "b").await;
+
+    // The replica gets promoted, and accepts writes again.
+    let (_, res) = process_query(&replica, b"REPLICAOF 'NO ONE';", &mut replica_session);
+    assert_eq!(res, "Ok");
+    assert_eq!(replica.settings.read().unwrap().replica_of, None);
+    let (_, res) = process_query(&replica, b"NEW 'c' VALUE 'd';", &mut replica_session);
+    assert_eq!(res, "Ok");
+
+    let (_, res) = process_query(&replica, b"REPLICAOF 'nowhere';", &mut replica_session);
+    assert_eq!(res, "Error: Invalid primary address!");
+
+    assert!(shutdown.shutdown(Duration::from_secs(1)).await);
+}
+
+#[tokio::test]
+async fn test_frame_size_limit() {
+    let mut data = Vec::new();
+    write_frame(&mut data, &Mutation::FlushAll).await.unwrap();
+    let frame: Mutation = read_frame(&mut data.as_slice()).await.unwrap();
+    assert_eq!(frame, Mutation::FlushAll);
+
+    // A length from the network doesn't get allocated when it's too large.
+    let length = (MAX_FRAME_SIZE as u64 + 1).to_be_bytes();
+    assert!(read_frame::<_, Mutation>(&mut length.as_slice())
+        .await
+        .is_err());
+    assert!(
+        read_frame::<_, Mutation>(&mut u64::MAX.to_be_bytes().as_slice())
+            .await
+            .is_err()
+    );
+
+    // Neither does a frame which ends early.
+    data.truncate(data.len() - 1);
+    assert!(read_frame::<_, Mutation>(&mut data.as_slice())
+        .await
+        .is_err());
+}
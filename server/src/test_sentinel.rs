@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use tokio::{
+    net::TcpListener,
+    time::{sleep, timeout},
+};
+
+use crate::{
+    config::Config,
+    connection::{accept_connections, Session},
+    database::process_query,
+    listener::Listener,
+    replication::follow,
+    sentinel::{ask, serve, SentinelArgs},
+    shutdown::Shutdown,
+    State,
+};
+
+/// Let a server accept connections on a random localhost port, and return
+/// its address.
+async fn start_server(state: &State, shutdown: &Shutdown) -> String {
+    let listener = Listener::bind_tcp("127.0.0.1:0").await.unwrap();
+    let address = listener.describe();
+    tokio::spawn(accept_connections(
+        listener,
+        state.clone(),
+        shutdown.subscribe(),
+    ));
+    address
+}
+
+/// Wait until a sentinel reports the expected primary.
+async fn wait_for_primary(sentinel: &str, expected: &str) {
+    let waiting = async {
+        while ask(sentinel, "PRIMARY").await.unwrap_or_default() != expected {
+            sleep(Duration::from_millis(20)).await;
+        }
+    };
+
+    timeout(Duration::from_secs(10), waiting)
+        .await
+        .unwrap_or_else(|_| panic!("The sentinel never reported primary {}", expected));
+}
+
+#[tokio::test]
+async fn test_sentinel_failover() {
+    let primary_shutdown = Shutdown::new();
+    let shutdown = Shutdown::new();
+
+    let primary = State::new(Config::default());
+    let primary_address = start_server(&primary, &primary_shutdown).await;
+    let mut session = Session::default();
+    process_query(&primary, b"NEW 'a' VALUE 'b';", &mut session);
+
+    let replica = State::new(Config {
+        replica_of: Some(primary_address.clone()),
+        ..Config::default()
+    });
+    let replica_address = start_server(&replica, &shutdown).await;
+    follow(replica.clone(), shutdown.subscribe());
+
+    // Two sentinels, which both have to agree that the primary is down.
+    let listeners = [
+        TcpListener::bind("127.0.0.1:0").await.unwrap(),
+        TcpListener::bind("127.0.0.1:0").await.unwrap(),
+    ];
+    let sentinels: Vec<String> = listeners
+        .iter()
+        .map(|listener| listener.local_addr().unwrap().to_string())
+        .collect();
+
+    for (index, listener) in listeners.into_iter().enumerate() {
+        let args = SentinelArgs {
+            help: None,
+            listen: sentinels[index].clone(),
+            primary: primary_address.clone(),
+            replicas: vec![replica_address.clone()],
+            peers: vec![sentinels[1 - index].clone()],
+            quorum: None,
+            down_after: 200,
+            user: None,
+            password: None,
+        };
+        tokio::spawn(serve(listener, args, shutdown.subscribe()));
+    }
+
+    for sentinel in &sentinels {
+        wait_for_primary(sentinel, &primary_address).await;
+    }
+    assert_eq!(
+        ask(&sentinels[0], "UNKNOWN").await.unwrap(),
+        "Error: Unknown request!"
+    );
+
+    // The primary goes down, the replica gets promoted.
+    assert!(primary_shutdown.shutdown(Duration::from_secs(1)).await);
+    for sentinel in &sentinels {
+        wait_for_primary(sentinel, &replica_address).await;
+    }
+
+    assert_eq!(replica.settings.read().unwrap().replica_of, None);
+    let mut replica_session = Session::default();
+    let (_, res) = process_query(&replica, b"GET VALUE 'a';", &mut replica_session);
+    assert_eq!(res, "b");
+    let (_, res) = process_query(&replica, b"NEW 'c' VALUE 'd';", &mut replica_session);
+    assert_eq!(res, "Ok");
+
+    assert!(shutdown.shutdown(Duration::from_secs(1)).await);
+}
@@ -0,0 +1,204 @@
+use std::fs;
+
+use ffly_rs::FireflyStream;
+
+use crate::{
+    config::{Config, Persistence},
+    connection::Session,
+    database::process_query,
+    keyspace::Databases,
+    test_util::{remove_snapshot, temp_path},
+    Server,
+};
+
+#[tokio::test]
+async fn test_start_and_shutdown() {
+    let snapshot = temp_path("server-snapshot");
+    let server = Server::default()
+        .bind("127.0.0.1", 0)
+        .snapshot(&snapshot)
+        .start()
+        .await
+        .unwrap();
+
+    let address = server.local_addr().unwrap();
+    assert_ne!(address.port(), 0);
+
+    let firefly = FireflyStream::connect(&address.to_string()).await.unwrap();
+    firefly.new("key", "value").await.unwrap();
+    assert_eq!(firefly.get_value("key").await.unwrap(), "value");
+    assert_eq!(server.state().db.lock().unwrap().len(), 1);
+
+    drop(firefly);
+    assert_eq!(server.shutdown().await, 1);
+
+    // The final snapshot gets loaded by the next server.
+    let server = Server::new(Config::default())
+        .bind("127.0.0.1", 0)
+        .snapshot(&snapshot)
+        .start()
+        .await
+        .unwrap();
+
+    let firefly = FireflyStream::connect(&server.local_addr().unwrap().to_string())
+        .await
+        .unwrap();
+    assert_eq!(firefly.get_value("key").await.unwrap(), "value");
+
+    drop(firefly);
+    server.shutdown().await;
+    remove_snapshot(&snapshot);
+}
+
+#[tokio::test]
+async fn test_servers_side_by_side() {
+    let (first, second) = (temp_path("server-first"), temp_path("server-second"));
+    let start = |snapshot: String| async move {
+        Server::default()
+            .bind("127.0.0.1", 0)
+            .snapshot(&snapshot)
+            .save_every(60)
+            .start()
+            .await
+            .unwrap()
+    };
+
+    let (first_server, second_server) = (start(first.clone()).await, start(second.clone()).await);
+    assert_ne!(first_server.local_addr(), second_server.local_addr());
+
+    let firefly = FireflyStream::connect(&first_server.local_addr().unwrap().to_string())
+        .await
+        .unwrap();
+    firefly.new("key", "value").await.unwrap();
+    assert!(second_server.state().db.lock().unwrap().is_empty());
+
+    drop(firefly);
+    first_server.shutdown().await;
+    second_server.shutdown().await;
+
+    for path in [first, second] {
+        remove_snapshot(&path);
+    }
+}
+
+#[tokio::test]
+async fn test_bind_failure() {
+    let server = Server::default()
+        .bind("127.0.0.1", 0)
+        .snapshot(&temp_path("server-taken"))
+        .start()
+        .await
+        .unwrap();
+    let port = server.local_addr().unwrap().port();
+
+    let taken = Server::default().bind("127.0.0.1", port).start().await;
+    assert!(taken.is_err());
+
+    server.shutdown().await;
+    remove_snapshot(&temp_path("server-taken"));
+}
+
+/// Write a snapshot with one record in the default database.
+///
+/// # Arguments
+///
+/// * `path` - The path of the snapshot.
+fn write_snapshot(path: &str) -> Vec<u8> {
+    let mut databases = Databases::default();
+    databases
+        .keyspace("0")
+        .insert("stored".to_string(), "value".to_string(), "0".to_string());
+
+    let data = databases.to_snapshot().unwrap();
+    fs::write(path, &data).unwrap();
+    data
+}
+
+#[tokio::test]
+async fn test_persistence_disabled() {
+    let snapshot = temp_path("server-disabled");
+    write_snapshot(&snapshot);
+
+    let server = Server::default()
+        .bind("127.0.0.1", 0)
+        .snapshot(&snapshot)
+        .persistence(Persistence::Disabled)
+        .start()
+        .await
+        .unwrap();
+
+    // The existing snapshot doesn't get loaded.
+    let mut session = Session::default();
+    let (_, res) = process_query(server.state(), b"DBSIZE;", &mut session);
+    assert_eq!(res, "0");
+
+    process_query(server.state(), b"NEW 'key' VALUE 'value';", &mut session);
+    let (_, res) = process_query(server.state(), b"SAVE;", &mut session);
+    assert_eq!(res, "Error: Persistence is disabled!");
+    let (_, res) = process_query(server.state(), b"BGSAVE;", &mut session);
+    assert_eq!(res, "Error: Persistence is disabled!");
+
+    server.shutdown().await;
+    fs::remove_file(&snapshot).unwrap();
+
+    // Nothing gets written, not even without an existing snapshot.
+    let server = Server::default()
+        .bind("127.0.0.1", 0)
+        .snapshot(&snapshot)
+        .persistence(Persistence::Disabled)
+        .start()
+        .await
+        .unwrap();
+    process_query(server.state(), b"NEW 'key' VALUE 'value';", &mut session);
+    server.shutdown().await;
+
+    assert!(!std::path::Path::new(&snapshot).exists());
+}
+
+#[tokio::test]
+async fn test_persistence_read_only() {
+    let snapshot = temp_path("server-read-only");
+    let data = write_snapshot(&snapshot);
+
+    let server = Server::default()
+        .bind("127.0.0.1", 0)
+        .snapshot(&snapshot)
+        .save_every(1)
+        .persistence(Persistence::ReadOnly)
+        .start()
+        .await
+        .unwrap();
+
+    let mut session = Session::default();
+    let (_, res) = process_query(server.state(), b"GET VALUE 'stored';", &mut session);
+    assert_eq!(res, "value");
+
+    process_query(server.state(), b"NEW 'key' VALUE 'value';", &mut session);
+    let (_, res) = process_query(server.state(), b"SAVE;", &mut session);
+    assert_eq!(res, "Error: The snapshot is read-only!");
+    let (_, res) = process_query(server.state(), b"INFO;", &mut session);
+    assert!(res.contains("persistence:read-only"));
+
+    server.shutdown().await;
+    assert_eq!(fs::read(&snapshot).unwrap(), data);
+    fs::remove_file(snapshot).unwrap();
+}
+
+#[tokio::test]
+async fn test_start_validates_config() {
+    let cluster = Server::new(Config {
+        cluster_peers: vec!["127.0.0.1:46601".to_string()],
+        ..Config::default()
+    })
+    .bind("127.0.0.1", 0)
+    .persistence(Persistence::Disabled);
+    assert!(cluster.start().await.is_err());
+
+    if cfg!(unix) {
+        let no_listener = Server::new(Config {
+            no_tcp: true,
+            ..Config::default()
+        });
+        assert!(no_listener.start().await.is_err());
+    }
+}
@@ -0,0 +1,24 @@
+use std::time::Duration;
+
+use crate::shutdown::Shutdown;
+
+#[tokio::test]
+async fn test_shutdown_drains_listeners() {
+    let shutdown = Shutdown::new();
+    let mut listener = shutdown.subscribe();
+
+    tokio::spawn(async move {
+        listener.recv().await;
+    });
+
+    assert!(shutdown.shutdown(Duration::from_secs(1)).await);
+}
+
+#[tokio::test]
+async fn test_shutdown_times_out() {
+    let shutdown = Shutdown::new();
+    let listener = shutdown.subscribe();
+
+    assert!(!shutdown.shutdown(Duration::from_millis(10)).await);
+    drop(listener);
+}
@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use crate::{
+    config::Config, connection::Session, database::process_query, slowlog::SlowLog, State,
+};
+
+#[test]
+fn test_slowlog_threshold() {
+    let slowlog = SlowLog::default();
+    let threshold = Duration::from_millis(10);
+
+    slowlog.record("Get", Some("a"), Duration::from_millis(1), threshold, 10);
+    slowlog.record("Get", Some("b"), Duration::from_millis(20), threshold, 10);
+
+    let entries = slowlog.entries();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].key.as_deref(), Some("b"));
+}
+
+#[test]
+fn test_slowlog_bounded() {
+    let slowlog = SlowLog::default();
+
+    for _ in 0..5 {
+        slowlog.record("Snapshot", None, Duration::ZERO, Duration::ZERO, 3);
+    }
+
+    let entries = slowlog.entries();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0].id, 5);
+    assert_eq!(entries[2].id, 3);
+}
+
+#[test]
+fn test_slowlog_queries() {
+    let state = State::new(Config {
+        slowlog_threshold: 0,
+        ..Config::default()
+    });
+    let mut session = Session::default();
+
+    process_query(&state, b"GET 'key';", &mut session);
+    let (_, res) = process_query(&state, b"SLOWLOG GET;", &mut session);
+    let mut lines = res.lines();
+    assert_eq!(lines.next(), Some("1"));
+    assert!(lines.next().unwrap().ends_with("\0Get\0key"));
+
+    // The reset query itself is the only remaining entry.
+    process_query(&state, b"SLOWLOG RESET;", &mut session);
+    let entries = state.slowlog.entries();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].kind, "SlowlogReset");
+}
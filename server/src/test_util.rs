@@ -0,0 +1,23 @@
+/// A unique path in the temporary directory, for the files a test writes.
+///
+/// # Arguments
+///
+/// * `name` - The name of the file, unique among all tests.
+pub fn temp_path(name: &str) -> String {
+    std::env::temp_dir()
+        .join(format!("ffly-test-{}-{}", std::process::id(), name))
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Remove a snapshot a test wrote, with the backup and Raft state the server
+/// keeps next to it.
+///
+/// # Arguments
+///
+/// * `path` - The path of the snapshot.
+pub fn remove_snapshot(path: &str) {
+    for suffix in ["", ".bak", ".raft", ".tmp"] {
+        let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+    }
+}
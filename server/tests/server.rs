@@ -0,0 +1,46 @@
+use std::fs;
+
+use ffly::Server;
+use ffly_rs::FireflyStream;
+
+/// Start a server on a free localhost port, which writes to a snapshot.
+async fn start(snapshot: &str) -> ffly::ServerHandle {
+    Server::default()
+        .bind("127.0.0.1", 0)
+        .snapshot(snapshot)
+        .start()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_embedded_server() {
+    let snapshot = std::env::temp_dir()
+        .join(format!("ffly-test-{}-embedded", std::process::id()))
+        .to_string_lossy()
+        .to_string();
+
+    let server = start(&snapshot).await;
+    let address = server.local_addr().unwrap().to_string();
+    let firefly = FireflyStream::connect(&address).await.unwrap();
+    firefly.new("session", "abc").await.unwrap();
+    assert_eq!(firefly.get_value("session").await.unwrap(), "abc");
+
+    let info = firefly.info().await.unwrap();
+    assert_eq!((info.keys, info.out), (1, snapshot.clone()));
+
+    drop(firefly);
+    assert_eq!(server.shutdown().await, 1);
+
+    // The records survive a restart through the snapshot.
+    let server = start(&snapshot).await;
+    let address = server.local_addr().unwrap().to_string();
+    let firefly = FireflyStream::connect(&address).await.unwrap();
+    assert_eq!(firefly.get_value("session").await.unwrap(), "abc");
+    firefly.drop("session").await.unwrap();
+
+    drop(firefly);
+    server.shutdown().await;
+    fs::remove_file(&snapshot).unwrap();
+    fs::remove_file(format!("{}.bak", snapshot)).unwrap();
+}